@@ -0,0 +1,140 @@
+//! `#[derive(NtTable)]` for the `ntcore` crate. Use it through ntcore's `derive` feature; see
+//! `ntcore::bind` for what the generated code does.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use syn::{Data, DeriveInput, Fields, Ident, LitStr};
+
+#[proc_macro_derive(NtTable, attributes(nt))]
+pub fn derive_nt_table(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => compile_errors(err).into(),
+    }
+}
+
+/// `syn::Error::to_compile_error` refers to `::core`, which 2015 edition crates like ntcore's users
+/// may not have in scope, so spell out the plain macro instead.
+fn compile_errors(err: syn::Error) -> TokenStream2 {
+    err.into_iter().map(|err| {
+        let message = err.to_string();
+        quote_spanned!(err.span()=> compile_error!(#message);)
+    }).collect()
+}
+
+/// A field of the struct, along with its `#[nt(...)]` options.
+struct Field {
+    ident: Ident,
+    key: String,
+    persistent: bool,
+    read_only: bool,
+    subtable: bool,
+}
+
+impl Field {
+    fn parse(field: &syn::Field) -> syn::Result<Field> {
+        let ident = field.ident.clone().expect("named fields have names");
+        let mut ret = Field { key: ident.to_string(), ident, persistent: false, read_only: false, subtable: false };
+
+        for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("nt")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    ret.key = meta.value()?.parse::<LitStr>()?.value();
+                } else if meta.path.is_ident("persistent") {
+                    ret.persistent = true;
+                } else if meta.path.is_ident("read_only") {
+                    ret.read_only = true;
+                } else if meta.path.is_ident("subtable") {
+                    ret.subtable = true;
+                } else {
+                    return Err(meta.error("expected `rename`, `persistent`, `read_only` or `subtable`"));
+                }
+                Ok(())
+            })?;
+        }
+
+        if ret.subtable && ret.persistent {
+            return Err(syn::Error::new_spanned(&field.ident, "subtables can't be marked persistent"));
+        }
+        Ok(ret)
+    }
+
+    /// Code that writes this field to `table`, with `set` being either `set` or `set_default`.
+    fn write(&self, set: &Ident) -> TokenStream2 {
+        let Field { ref ident, ref key, .. } = *self;
+        if self.subtable {
+            let method = if set == "set" { quote!(publish) } else { quote!(set_defaults) };
+            return quote! {
+                ::ntcore::bind::NtTable::#method(&self.#ident, &mut table.get_subtable(#key))?;
+            };
+        }
+
+        let persist = if self.persistent { quote!(table.set_persistent(#key);) } else { quote!() };
+        quote! {
+            table.#set(#key, ::std::clone::Clone::clone(&self.#ident))?;
+            #persist
+        }
+    }
+
+    fn load(&self) -> TokenStream2 {
+        let Field { ref ident, ref key, .. } = *self;
+        if self.read_only {
+            quote!(#ident: ::std::default::Default::default())
+        } else if self.subtable {
+            quote!(#ident: ::ntcore::bind::NtTable::load(&mut table.get_subtable(#key))?)
+        } else {
+            quote! {
+                #ident: match table.get(#key).value_as()? {
+                    ::std::option::Option::Some(value) => value,
+                    ::std::option::Option::None =>
+                        return ::std::result::Result::Err(::ntcore::Error::MissingEntry(format!("{}/{}", table.path(), #key))),
+                }
+            }
+        }
+    }
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => fields.named.iter().map(Field::parse).collect::<syn::Result<Vec<_>>>()?,
+            _ => return Err(syn::Error::new_spanned(input, "NtTable can only be derived for structs with named fields")),
+        },
+        _ => return Err(syn::Error::new_spanned(input, "NtTable can only be derived for structs")),
+    };
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let set = Ident::new("set", proc_macro2::Span::call_site());
+    let set_default = Ident::new("set_default", proc_macro2::Span::call_site());
+    let publish = fields.iter().map(|field| field.write(&set));
+    let set_defaults = fields.iter().map(|field| field.write(&set_default));
+    let load = fields.iter().map(Field::load);
+
+    Ok(quote! {
+        impl #impl_generics ::ntcore::bind::NtTable for #name #ty_generics #where_clause {
+            fn publish(&self, table: &mut ::ntcore::NetworkTable) -> ::ntcore::Result<()> {
+                #(#publish)*
+                ::std::result::Result::Ok(())
+            }
+
+            fn load(table: &mut ::ntcore::NetworkTable) -> ::ntcore::Result<Self> {
+                ::std::result::Result::Ok(#name {
+                    #(#load,)*
+                })
+            }
+
+            fn set_defaults(&self, table: &mut ::ntcore::NetworkTable) -> ::ntcore::Result<()> {
+                #(#set_defaults)*
+                ::std::result::Result::Ok(())
+            }
+        }
+    })
+}
@@ -0,0 +1,606 @@
+//! A backend that keeps entries in a local map and never touches the network, for unit testing
+//! code that uses `Instance` and `NetworkTable`.
+//!
+//! Entries behave like they do in ntcore: values can't change type, defaults only apply to missing
+//! entries, deleting all entries leaves persistent ones alone, and listeners see the same flags
+//! they would from ntcore. Every change made through this backend is local, so listeners need
+//! `NotifyFlags::LOCAL` to hear about them.
+//!
+//! Unlike ntcore, listeners are run synchronously, before the change that triggered them returns,
+//! so tests don't have to wait for a notifier thread. Callbacks can safely change entries or add
+//! and remove listeners; any notifications that causes are delivered after the callback returns.
+//!
+//! ```rs
+//! let inst = Instance::in_memory();
+//! inst.get_table("/Shooter".into()).set("speed", 3000.0)?;
+//! let backend = inst.memory_backend().unwrap();
+//! assert_eq!(backend.value("/Shooter/speed"), Some(Value::Double(3000.0)));
+//! ```
+
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, MutexGuard, TryLockError};
+use backend::{Backend, ConnectionCallback, Handle, ListenerFilter, Notification, NotificationCallback, Target};
+use connection::OwnedConnectionInfo;
+use entry::{EntryFlags, EntryMask, EntryType, Value};
+use listener::NotifyFlags;
+use ::{now, Error, NetworkTime, Result};
+
+#[derive(Debug)]
+struct MemoryEntry {
+    name: String,
+    value: Option<Value>,
+    flags: EntryFlags,
+    last_change: NetworkTime,
+}
+
+/// Changes to the set of callbacks. They are applied by whichever thread is delivering
+/// notifications, since callbacks can add and remove listeners while it holds the callbacks.
+enum CallbackChange {
+    Add(Handle, NotificationCallback),
+    Remove(Handle),
+}
+
+#[derive(Default)]
+struct State {
+    entries: Vec<MemoryEntry>,
+    handles: HashMap<String, Handle>,
+    listeners: HashMap<Handle, ListenerFilter>,
+    connection_listeners: HashMap<Handle, ConnectionCallback>,
+    next_listener: Handle,
+    changes: Vec<CallbackChange>,
+    /// Notifications waiting to be delivered, with the listener each one is for.
+    pending: Vec<(Handle, Notification)>,
+    history: Vec<(String, Value)>,
+}
+
+impl State {
+    fn entry(&self, handle: Handle) -> Option<&MemoryEntry> {
+        // Handles start at 1, so that 0 is never a valid handle.
+        self.entries.get((handle as usize).wrapping_sub(1))
+    }
+
+    fn entry_mut(&mut self, handle: Handle) -> Option<&mut MemoryEntry> {
+        self.entries.get_mut((handle as usize).wrapping_sub(1))
+    }
+
+    fn notify(&mut self, handle: Handle, flags: NotifyFlags, value: Option<Value>) {
+        let name = match self.entry(handle) {
+            Some(entry) => entry.name.clone(),
+            None => return,
+        };
+
+        let State { ref listeners, ref mut pending, .. } = *self;
+        for (&listener, filter) in listeners {
+            if filter.matches(handle, &name, flags) {
+                pending.push((listener, Notification { entry: handle, name: name.clone(), value: value.clone(), flags }));
+            }
+        }
+    }
+
+    /// Queue notifications for every existing entry a new listener covers.
+    fn notify_immediate(&mut self, listener: Handle) {
+        let flags = NotifyFlags::IMMEDIATE | NotifyFlags::NEW;
+        let mut pending = Vec::new();
+        if let Some(filter) = self.listeners.get(&listener) {
+            for (index, entry) in self.entries.iter().enumerate() {
+                let handle = index as Handle + 1;
+                if filter.target.covers(handle, &entry.name) && entry.value.is_some() {
+                    let name = entry.name.clone();
+                    pending.push((listener, Notification { entry: handle, name, value: entry.value.clone(), flags }));
+                }
+            }
+        }
+        self.pending.extend(pending);
+    }
+
+    fn add_listener(&mut self, filter: ListenerFilter, callback: NotificationCallback) -> Handle {
+        self.next_listener += 1;
+        let listener = self.next_listener;
+        let immediate = filter.flags.is_immediate();
+        self.listeners.insert(listener, filter);
+        self.changes.push(CallbackChange::Add(listener, callback));
+        if immediate { self.notify_immediate(listener); }
+        listener
+    }
+
+    fn set_value(&mut self, handle: Handle, value: &Value) {
+        let old = match self.entry_mut(handle) {
+            Some(entry) => {
+                entry.last_change = now();
+                entry.value.replace(value.clone())
+            }
+            None => return,
+        };
+        let name = self.entry(handle).map(|entry| entry.name.clone()).unwrap_or_default();
+        self.history.push((name, value.clone()));
+
+        match old {
+            None => self.notify(handle, NotifyFlags::NEW | NotifyFlags::LOCAL, Some(value.clone())),
+            // ntcore doesn't notify when a value is set to what it already was.
+            Some(ref old) if old == value => {}
+            Some(_) => self.notify(handle, NotifyFlags::UPDATE | NotifyFlags::LOCAL, Some(value.clone())),
+        }
+    }
+
+    fn delete(&mut self, handle: Handle) {
+        let old = match self.entry_mut(handle) {
+            Some(entry) => {
+                entry.flags = EntryFlags::NONE;
+                entry.value.take()
+            }
+            None => return,
+        };
+        if old.is_some() {
+            self.notify(handle, NotifyFlags::DELETE | NotifyFlags::LOCAL, old);
+        }
+    }
+}
+
+/// Delivers notifications to callbacks. Held by one thread at a time.
+#[derive(Default)]
+struct Callbacks {
+    entry: HashMap<Handle, NotificationCallback>,
+}
+
+/// An in-memory NetworkTables implementation. See the module docs.
+#[derive(Default)]
+pub struct MemoryBackend {
+    state: Mutex<State>,
+    callbacks: Mutex<Callbacks>,
+}
+
+impl ::std::fmt::Debug for MemoryBackend {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        let state = self.lock();
+        f.debug_struct("MemoryBackend")
+            .field("entries", &state.entries)
+            .field("listeners", &state.listeners)
+            .finish()
+    }
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self { MemoryBackend::default() }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // A panicking listener shouldn't break every test that runs after it.
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Run `func` on the state, then deliver any notifications it queued.
+    fn update<R, F: FnOnce(&mut State) -> R>(&self, func: F) -> R {
+        let ret = func(&mut self.lock());
+        self.dispatch();
+        ret
+    }
+
+    fn dispatch(&self) {
+        loop {
+            let mut callbacks = match self.callbacks.try_lock() {
+                Ok(callbacks) => callbacks,
+                Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+                // Someone else is delivering, possibly this thread further up the stack. They'll
+                // pick up what we queued.
+                Err(TryLockError::WouldBlock) => return,
+            };
+
+            loop {
+                // Changes are applied before every notification, so a listener removed by a
+                // callback doesn't hear about anything after that.
+                let next = {
+                    let mut state = self.lock();
+                    for change in state.changes.drain(..) {
+                        match change {
+                            CallbackChange::Add(listener, callback) => { callbacks.entry.insert(listener, callback); }
+                            CallbackChange::Remove(listener) => { callbacks.entry.remove(&listener); }
+                        }
+                    }
+                    if state.pending.is_empty() { None } else { Some(state.pending.remove(0)) }
+                };
+
+                match next {
+                    Some((listener, notification)) => if let Some(callback) = callbacks.entry.get(&listener) {
+                        callback(notification);
+                    },
+                    None => break,
+                }
+            }
+
+            drop(callbacks);
+            // Another thread may have queued something after we last looked, but before we let go.
+            let state = self.lock();
+            if state.pending.is_empty() && state.changes.is_empty() { return; }
+        }
+    }
+
+    /// Get the current value of the entry named `name`.
+    pub fn value(&self, name: &str) -> Option<Value> {
+        let state = self.lock();
+        state.handles.get(name).and_then(|&handle| state.entry(handle)).and_then(|entry| entry.value.clone())
+    }
+
+    /// Get the current value of every entry, by name.
+    pub fn values(&self) -> BTreeMap<String, Value> {
+        self.lock().entries.iter()
+            .filter_map(|entry| entry.value.clone().map(|value| (entry.name.clone(), value)))
+            .collect()
+    }
+
+    /// Get every value that was set, in order, including ones that were later overwritten. Values
+    /// that were set to what they already were are included too.
+    pub fn history(&self) -> Vec<(String, Value)> {
+        self.lock().history.clone()
+    }
+
+    /// Get the values set on the entry named `name`, in order.
+    pub fn history_of(&self, name: &str) -> Vec<Value> {
+        self.lock().history.iter().filter(|(entry, _)| entry == name).map(|(_, value)| value.clone()).collect()
+    }
+
+    pub fn clear_history(&self) {
+        self.lock().history.clear();
+    }
+
+    /// Get the number of entry listeners that are currently registered.
+    pub fn listener_count(&self) -> usize {
+        self.lock().listeners.len()
+    }
+}
+
+impl Backend for MemoryBackend {
+    fn as_any(&self) -> &dyn Any { self }
+
+    // There is no network, so none of these have anything to do.
+    fn set_network_identity(&self, _name: &str) {}
+    fn start_server(&self, _persist_filename: &str, _listen_address: &str, _port: u32) -> Result<()> { Ok(()) }
+    fn stop_server(&self) {}
+    fn start_client_none(&self) {}
+    fn start_client(&self, _servers: &[(&str, u32)]) -> Result<()> { Ok(()) }
+    fn start_client_team(&self, _team: u32, _port: u32) {}
+    fn stop_client(&self) {}
+    fn set_server(&self, _servers: &[(&str, u32)]) -> Result<()> { Ok(()) }
+    fn set_server_team(&self, _team: u32, _port: u32) {}
+    fn set_update_rate(&self, _interval: f64) {}
+    fn flush(&self) {}
+
+    fn is_connected(&self) -> bool { false }
+    fn connections(&self) -> Vec<OwnedConnectionInfo> { Vec::new() }
+
+    fn entry(&self, name: &str) -> Handle {
+        let mut state = self.lock();
+        if let Some(&handle) = state.handles.get(name) { return handle; }
+
+        state.entries.push(MemoryEntry {
+            name: name.to_owned(),
+            value: None,
+            flags: EntryFlags::NONE,
+            last_change: NetworkTime(0),
+        });
+        let handle = state.entries.len() as Handle;
+        state.handles.insert(name.to_owned(), handle);
+        handle
+    }
+
+    fn entries(&self, prefix: &str, types: EntryMask) -> Result<Vec<Handle>> {
+        let state = self.lock();
+        Ok(state.entries.iter().enumerate()
+            .filter(|&(_, entry)| entry.name.starts_with(prefix))
+            .filter(|&(_, entry)| match entry.value {
+                Some(ref value) => types.0 == 0 || types.0 & value.entry_type() as u32 != 0,
+                None => false,
+            })
+            .map(|(index, _)| index as Handle + 1)
+            .collect())
+    }
+
+    fn entry_name(&self, entry: Handle) -> Vec<u8> {
+        self.lock().entry(entry).map(|entry| entry.name.clone().into_bytes()).unwrap_or_default()
+    }
+
+    fn entry_type(&self, entry: Handle) -> Result<EntryType> {
+        Ok(self.lock().entry(entry).and_then(|entry| entry.value.as_ref()).map_or(EntryType::Unassigned, Value::entry_type))
+    }
+
+    fn entry_value(&self, entry: Handle) -> Option<Value> {
+        self.lock().entry(entry).and_then(|entry| entry.value.clone())
+    }
+
+    fn set_entry_value(&self, entry: Handle, value: &Value) -> Result<()> {
+        self.update(|state| {
+            let expected = match state.entry(entry) {
+                Some(existing) => existing.value.as_ref().map_or(value.entry_type(), Value::entry_type),
+                None => EntryType::Unassigned,
+            };
+            if expected != value.entry_type() {
+                return Err(Error::TypeMismatch { expected, actual: value.entry_type() });
+            }
+            state.set_value(entry, value);
+            Ok(())
+        })
+    }
+
+    fn set_default_entry_value(&self, entry: Handle, value: &Value) -> Result<bool> {
+        self.update(|state| {
+            let existing = match state.entry(entry) {
+                Some(existing) => existing.value.as_ref().map(Value::entry_type),
+                None => Some(EntryType::Unassigned),
+            };
+            match existing {
+                Some(ty) if ty == value.entry_type() => Ok(false),
+                Some(ty) => Err(Error::TypeMismatch { expected: ty, actual: value.entry_type() }),
+                None => {
+                    state.set_value(entry, value);
+                    Ok(true)
+                }
+            }
+        })
+    }
+
+    fn entry_flags(&self, entry: Handle) -> EntryFlags {
+        self.lock().entry(entry).map_or(EntryFlags::NONE, |entry| entry.flags)
+    }
+
+    fn set_entry_flags(&self, entry: Handle, flags: EntryFlags) {
+        self.update(|state| {
+            let value = match state.entry_mut(entry) {
+                // Like ntcore, entries without a value can't have flags.
+                Some(existing) if existing.value.is_some() && existing.flags != flags => {
+                    existing.flags = flags;
+                    existing.value.clone()
+                }
+                _ => return,
+            };
+            state.notify(entry, NotifyFlags::FLAGS | NotifyFlags::LOCAL, value);
+        })
+    }
+
+    fn entry_last_change(&self, entry: Handle) -> NetworkTime {
+        self.lock().entry(entry).map_or(NetworkTime(0), |entry| entry.last_change)
+    }
+
+    fn delete_entry(&self, entry: Handle) {
+        self.update(|state| state.delete(entry))
+    }
+
+    fn delete_all_entries(&self) {
+        self.update(|state| {
+            // ntcore leaves persistent entries alone here.
+            let handles: Vec<Handle> = state.entries.iter().enumerate()
+                .filter(|&(_, entry)| !entry.flags.is_persistent())
+                .map(|(index, _)| index as Handle + 1)
+                .collect();
+            for handle in handles { state.delete(handle); }
+        })
+    }
+
+    fn add_entry_listener(&self, prefix: &str, flags: NotifyFlags, callback: NotificationCallback) -> Handle {
+        self.update(|state| state.add_listener(ListenerFilter { target: Target::Prefix(prefix.to_owned()), flags }, callback))
+    }
+
+    fn add_entry_listener_single(&self, entry: Handle, flags: NotifyFlags, callback: NotificationCallback) -> Handle {
+        self.update(|state| state.add_listener(ListenerFilter { target: Target::Entry(entry), flags }, callback))
+    }
+
+    fn remove_entry_listener(&self, listener: Handle) {
+        self.update(|state| {
+            state.listeners.remove(&listener);
+            state.pending.retain(|&(pending, _)| pending != listener);
+            state.changes.push(CallbackChange::Remove(listener));
+        })
+    }
+
+    fn add_connection_listener(&self, _immediate_notify: bool, callback: ConnectionCallback) -> Handle {
+        // Nothing ever connects, so the callback is only kept so that it can be removed.
+        let mut state = self.lock();
+        state.next_listener += 1;
+        let listener = state.next_listener;
+        state.connection_listeners.insert(listener, callback);
+        listener
+    }
+
+    fn remove_connection_listener(&self, listener: Handle) {
+        self.lock().connection_listeners.remove(&listener);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use super::*;
+
+    type Heard = Arc<Mutex<Vec<(String, Option<Value>, NotifyFlags)>>>;
+
+    /// A listener callback that records what it hears, and the record it adds to.
+    fn recorder() -> (NotificationCallback, Heard) {
+        let heard = Arc::new(Mutex::new(Vec::new()));
+        let callback_heard = heard.clone();
+        let callback = Box::new(move |n: Notification| callback_heard.lock().unwrap().push((n.name, n.value, n.flags)));
+        (callback, heard)
+    }
+
+    #[test]
+    fn values_keep_their_type() {
+        let backend = MemoryBackend::new();
+        let entry = backend.entry("/a");
+        assert_eq!(backend.entry_type(entry), Ok(EntryType::Unassigned));
+        backend.set_entry_value(entry, &Value::Double(1.0)).unwrap();
+        assert_eq!(backend.set_entry_value(entry, &Value::Bool(true)),
+                   Err(Error::TypeMismatch { expected: EntryType::Double, actual: EntryType::Boolean }));
+        assert_eq!(backend.value("/a"), Some(Value::Double(1.0)));
+
+        // Once deleted, the entry can take any type again.
+        backend.delete_entry(entry);
+        backend.set_entry_value(entry, &Value::Bool(true)).unwrap();
+        assert_eq!(backend.entry_type(entry), Ok(EntryType::Boolean));
+    }
+
+    #[test]
+    fn handles_are_stable_and_never_zero() {
+        let backend = MemoryBackend::new();
+        let a = backend.entry("/a");
+        assert_ne!(a, 0);
+        assert_eq!(backend.entry("/a"), a);
+        assert_ne!(backend.entry("/b"), a);
+        assert_eq!(backend.entry_name(a), b"/a".to_vec());
+
+        assert_eq!(backend.entry_value(0), None);
+        assert_eq!(backend.set_entry_value(0, &Value::Double(1.0)),
+                   Err(Error::TypeMismatch { expected: EntryType::Unassigned, actual: EntryType::Double }));
+    }
+
+    #[test]
+    fn defaults_only_apply_to_missing_entries() {
+        let backend = MemoryBackend::new();
+        let entry = backend.entry("/a");
+        assert_eq!(backend.set_default_entry_value(entry, &Value::Double(1.0)), Ok(true));
+        assert_eq!(backend.set_default_entry_value(entry, &Value::Double(2.0)), Ok(false));
+        assert_eq!(backend.set_default_entry_value(entry, &Value::Bool(true)),
+                   Err(Error::TypeMismatch { expected: EntryType::Double, actual: EntryType::Boolean }));
+        assert_eq!(backend.value("/a"), Some(Value::Double(1.0)));
+    }
+
+    #[test]
+    fn flags_need_a_value() {
+        let backend = MemoryBackend::new();
+        let entry = backend.entry("/a");
+        backend.set_entry_flags(entry, EntryFlags::PERSISTENT);
+        assert_eq!(backend.entry_flags(entry), EntryFlags::NONE);
+
+        backend.set_entry_value(entry, &Value::Double(1.0)).unwrap();
+        backend.set_entry_flags(entry, EntryFlags::PERSISTENT);
+        assert!(backend.entry_flags(entry).is_persistent());
+
+        // Deleting clears the flags along with the value.
+        backend.delete_entry(entry);
+        assert_eq!(backend.entry_flags(entry), EntryFlags::NONE);
+    }
+
+    #[test]
+    fn deleting_everything_keeps_persistent_entries() {
+        let backend = MemoryBackend::new();
+        let kept = backend.entry("/kept");
+        backend.set_entry_value(kept, &Value::Double(1.0)).unwrap();
+        backend.set_entry_flags(kept, EntryFlags::PERSISTENT);
+        backend.set_entry_value(backend.entry("/gone"), &Value::Double(2.0)).unwrap();
+
+        backend.delete_all_entries();
+        assert_eq!(backend.values().into_iter().collect::<Vec<_>>(), vec![("/kept".to_owned(), Value::Double(1.0))]);
+    }
+
+    #[test]
+    fn entries_are_filtered_by_prefix_and_type() {
+        let backend = MemoryBackend::new();
+        backend.set_entry_value(backend.entry("/a/x"), &Value::Double(1.0)).unwrap();
+        backend.set_entry_value(backend.entry("/a/y"), &Value::Bool(true)).unwrap();
+        backend.set_entry_value(backend.entry("/b/x"), &Value::Double(2.0)).unwrap();
+        // Entries without a value don't count.
+        backend.entry("/a/z");
+
+        let names = |prefix, types| -> Vec<Vec<u8>> {
+            backend.entries(prefix, types).unwrap().into_iter().map(|entry| backend.entry_name(entry)).collect()
+        };
+        assert_eq!(names("/a/", EntryMask(0)), vec![b"/a/x".to_vec(), b"/a/y".to_vec()]);
+        assert_eq!(names("/", EntryMask(EntryType::Double as u32)), vec![b"/a/x".to_vec(), b"/b/x".to_vec()]);
+    }
+
+    #[test]
+    fn listeners_run_before_the_change_returns() {
+        let backend = MemoryBackend::new();
+        let (callback, heard) = recorder();
+        let all = NotifyFlags::NEW | NotifyFlags::UPDATE | NotifyFlags::DELETE | NotifyFlags::FLAGS | NotifyFlags::LOCAL;
+        backend.add_entry_listener("/", all, callback);
+
+        let entry = backend.entry("/a");
+        backend.set_entry_value(entry, &Value::Double(1.0)).unwrap();
+        // Setting the same value again is quiet, like in ntcore.
+        backend.set_entry_value(entry, &Value::Double(1.0)).unwrap();
+        backend.set_entry_value(entry, &Value::Double(2.0)).unwrap();
+        backend.set_entry_flags(entry, EntryFlags::PERSISTENT);
+        backend.delete_entry(entry);
+
+        let value = Some(Value::Double(2.0));
+        assert_eq!(*heard.lock().unwrap(), vec![
+            ("/a".to_owned(), Some(Value::Double(1.0)), NotifyFlags::NEW | NotifyFlags::LOCAL),
+            ("/a".to_owned(), value.clone(), NotifyFlags::UPDATE | NotifyFlags::LOCAL),
+            ("/a".to_owned(), value.clone(), NotifyFlags::FLAGS | NotifyFlags::LOCAL),
+            ("/a".to_owned(), value, NotifyFlags::DELETE | NotifyFlags::LOCAL),
+        ]);
+        assert_eq!(backend.history_of("/a"), vec![Value::Double(1.0), Value::Double(1.0), Value::Double(2.0)]);
+    }
+
+    #[test]
+    fn listeners_need_the_local_flag() {
+        let backend = MemoryBackend::new();
+        let (callback, heard) = recorder();
+        backend.add_entry_listener("/", NotifyFlags::NEW | NotifyFlags::UPDATE, callback);
+        backend.set_entry_value(backend.entry("/a"), &Value::Double(1.0)).unwrap();
+        assert!(heard.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn immediate_listeners_hear_about_existing_entries() {
+        let backend = MemoryBackend::new();
+        backend.set_entry_value(backend.entry("/a/x"), &Value::Double(1.0)).unwrap();
+        backend.set_entry_value(backend.entry("/b/x"), &Value::Double(2.0)).unwrap();
+        backend.entry("/a/empty");
+
+        let (callback, heard) = recorder();
+        backend.add_entry_listener("/a/", NotifyFlags::IMMEDIATE | NotifyFlags::NEW, callback);
+        assert_eq!(*heard.lock().unwrap(),
+                   vec![("/a/x".to_owned(), Some(Value::Double(1.0)), NotifyFlags::IMMEDIATE | NotifyFlags::NEW)]);
+    }
+
+    #[test]
+    fn single_entry_listeners_ignore_other_entries() {
+        let backend = MemoryBackend::new();
+        let (callback, heard) = recorder();
+        let entry = backend.entry("/a");
+        backend.add_entry_listener_single(entry, NotifyFlags::NEW | NotifyFlags::LOCAL, callback);
+        // Shares a prefix, but isn't the same entry.
+        backend.set_entry_value(backend.entry("/ab"), &Value::Double(1.0)).unwrap();
+        backend.set_entry_value(entry, &Value::Double(2.0)).unwrap();
+        assert_eq!(heard.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn removed_listeners_are_dropped() {
+        let backend = MemoryBackend::new();
+        let (callback, heard) = recorder();
+        let listener = backend.add_entry_listener("/", NotifyFlags::NEW | NotifyFlags::LOCAL, callback);
+        assert_eq!(backend.listener_count(), 1);
+        backend.remove_entry_listener(listener);
+        assert_eq!(backend.listener_count(), 0);
+
+        backend.set_entry_value(backend.entry("/a"), &Value::Double(1.0)).unwrap();
+        assert!(heard.lock().unwrap().is_empty());
+        // The callback is gone too, so only our copy of the record is left.
+        assert_eq!(Arc::strong_count(&heard), 1);
+    }
+
+    #[test]
+    fn callbacks_can_change_entries() {
+        let backend = Arc::new(MemoryBackend::new());
+        let (callback, heard) = recorder();
+        backend.add_entry_listener("/echo", NotifyFlags::NEW | NotifyFlags::LOCAL, callback);
+
+        let callback_backend = backend.clone();
+        backend.add_entry_listener("/in", NotifyFlags::NEW | NotifyFlags::LOCAL, Box::new(move |n: Notification| {
+            let echo = callback_backend.entry("/echo");
+            callback_backend.set_entry_value(echo, n.value.as_ref().unwrap()).unwrap();
+        }));
+
+        backend.set_entry_value(backend.entry("/in"), &Value::Double(1.0)).unwrap();
+        // The nested change was delivered before the outer one returned.
+        assert_eq!(*heard.lock().unwrap(),
+                   vec![("/echo".to_owned(), Some(Value::Double(1.0)), NotifyFlags::NEW | NotifyFlags::LOCAL)]);
+    }
+
+    #[test]
+    fn there_is_no_network() {
+        let backend = MemoryBackend::new();
+        backend.start_client(&[("localhost", 1735)]).unwrap();
+        assert!(!backend.is_connected());
+        assert!(backend.connections().is_empty());
+    }
+}
@@ -0,0 +1,173 @@
+//! The interface between `Instance` and whatever actually stores entries and talks to the network.
+//!
+//! `Instance`, `Entry` and `NetworkTable` only ever go through a `Backend`, so robot code written
+//! against them works the same no matter which implementation is underneath. The ntcore backend
+//! is enabled by the `ntcore-sys` feature, which is on by default. `MemoryBackend` is always
+//! available, for tests that shouldn't touch the network.
+//!
+//! Entries and listeners are referred to by plain integer handles. A backend is free to pick
+//! whatever numbering it likes, but handles have to stay valid for as long as the backend lives.
+
+use std::any::Any;
+use std::fmt::Debug;
+use connection::{ConnectionEvent, OwnedConnectionInfo};
+use entry::{BorrowedValue, EntryFlags, EntryMask, EntryType, Value, ValueRef};
+use listener::NotifyFlags;
+use ::{NetworkTime, Result};
+
+pub mod memory;
+#[cfg(feature = "ntcore-sys")]
+pub mod native;
+pub(crate) mod network;
+
+pub use self::memory::MemoryBackend;
+
+#[cfg(feature = "ntcore-sys")]
+pub use self::native::NativeBackend;
+
+/// A handle to an entry or listener, as handed out by a backend.
+pub type Handle = u32;
+
+/// A change to an entry, as reported by a backend. `Instance` turns these into
+/// `EntryNotification`s before handing them to user callbacks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Notification {
+    pub entry: Handle,
+    pub name: String,
+    /// The new value of the entry. For deletions, this is the value the entry had before it was
+    /// deleted.
+    pub value: Option<Value>,
+    pub flags: NotifyFlags,
+}
+
+/// A snapshot of an entry's metadata, as reported by a backend. `Instance` turns these into
+/// `EntryInfo`s.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Info {
+    pub entry: Handle,
+    pub name: String,
+    pub entry_type: EntryType,
+    pub flags: EntryFlags,
+    pub last_change: NetworkTime,
+}
+
+/// What an entry listener listens to.
+#[derive(Debug)]
+pub(crate) enum Target {
+    Prefix(String),
+    Entry(Handle),
+}
+
+impl Target {
+    pub(crate) fn covers(&self, handle: Handle, name: &str) -> bool {
+        match *self {
+            Target::Prefix(ref prefix) => name.starts_with(prefix.as_str()),
+            Target::Entry(entry) => entry == handle,
+        }
+    }
+}
+
+/// Which notifications an entry listener wants, for backends that match them up themselves.
+#[derive(Debug)]
+pub(crate) struct ListenerFilter {
+    pub target: Target,
+    pub flags: NotifyFlags,
+}
+
+impl ListenerFilter {
+    pub(crate) fn matches(&self, handle: Handle, name: &str, flags: NotifyFlags) -> bool {
+        let kinds = NotifyFlags::NEW.0 | NotifyFlags::DELETE.0 | NotifyFlags::UPDATE.0 | NotifyFlags::FLAGS.0;
+        // Like ntcore, local changes only go to listeners that asked for them.
+        self.target.covers(handle, name) && self.flags.0 & flags.0 & kinds != 0 && (!flags.is_local() || self.flags.is_local())
+    }
+}
+
+pub type NotificationCallback = Box<dyn Fn(Notification) + Send + 'static>;
+pub type ConnectionCallback = Box<dyn Fn(ConnectionEvent) + Send + 'static>;
+
+/// An implementation of NetworkTables that an `Instance` can run on.
+pub trait Backend: Any + Debug + Send + Sync {
+    /// Used to get at the concrete backend, for features only some backends have.
+    fn as_any(&self) -> &dyn Any;
+
+    fn set_network_identity(&self, name: &str);
+
+    fn start_server(&self, persist_filename: &str, listen_address: &str, port: u32) -> Result<()>;
+    fn stop_server(&self);
+    /// Start in client mode without connecting to anything.
+    fn start_client_none(&self);
+    /// Start in client mode, trying each of `servers` in turn until one of them accepts.
+    fn start_client(&self, servers: &[(&str, u32)]) -> Result<()>;
+    fn start_client_team(&self, team: u32, port: u32);
+    fn stop_client(&self);
+    fn set_server(&self, servers: &[(&str, u32)]) -> Result<()>;
+    fn set_server_team(&self, team: u32, port: u32);
+    /// Set how often changes are sent to the network, in seconds.
+    fn set_update_rate(&self, interval: f64);
+    fn flush(&self);
+
+    fn is_connected(&self) -> bool;
+    fn connections(&self) -> Vec<OwnedConnectionInfo>;
+
+    /// Get the handle of the entry named `name`, whether it exists yet or not. Asking for the same
+    /// name twice gives the same handle.
+    fn entry(&self, name: &str) -> Handle;
+    /// Get every existing entry whose name starts with `prefix` and whose type is in `types`.
+    fn entries(&self, prefix: &str, types: EntryMask) -> Result<Vec<Handle>>;
+    fn entry_name(&self, entry: Handle) -> Vec<u8>;
+    /// `EntryType::Unassigned` for entries that don't exist.
+    fn entry_type(&self, entry: Handle) -> Result<EntryType>;
+    fn entry_value(&self, entry: Handle) -> Option<Value>;
+    /// Like `entry_value`, but lets a backend lend out its own copy of the value instead of
+    /// building a `Value`. By default this does build one.
+    fn entry_value_ref(&self, entry: Handle) -> Option<ValueRef<'_>> {
+        self.entry_value(entry).map(ValueRef::owned)
+    }
+    /// Fails with a type mismatch if the entry holds a value of a different type, or with whatever
+    /// else keeps the backend from taking the value.
+    fn set_entry_value(&self, entry: Handle, value: &Value) -> Result<()>;
+    /// Like `set_entry_value`, for backends that can take borrowed data without copying it into a
+    /// `Value` first. By default this does copy it.
+    fn set_entry_value_borrowed(&self, entry: Handle, value: BorrowedValue) -> Result<()> {
+        self.set_entry_value(entry, &value.to_value())
+    }
+    /// Set the value only if the entry doesn't have one. Returns whether the value was set, or a
+    /// type mismatch if the entry holds a value of a different type. The check and the set should
+    /// happen as one step, so that another thread or node can't change the answer in between.
+    fn set_default_entry_value(&self, entry: Handle, value: &Value) -> Result<bool>;
+    fn entry_flags(&self, entry: Handle) -> EntryFlags;
+    fn set_entry_flags(&self, entry: Handle, flags: EntryFlags);
+    fn entry_last_change(&self, entry: Handle) -> NetworkTime;
+    fn delete_entry(&self, entry: Handle);
+
+    /// Get the metadata of an entry all at once. `None` if the entry doesn't exist. By default
+    /// this asks for each piece separately.
+    fn entry_info(&self, entry: Handle) -> Option<Info> {
+        match self.entry_type(entry) {
+            Ok(EntryType::Unassigned) | Err(_) => None,
+            Ok(entry_type) => Some(Info {
+                entry,
+                name: String::from_utf8_lossy(&self.entry_name(entry)).into_owned(),
+                entry_type,
+                flags: self.entry_flags(entry),
+                last_change: self.entry_last_change(entry),
+            }),
+        }
+    }
+
+    /// Get the metadata of every entry `entries` would return.
+    fn entries_info(&self, prefix: &str, types: EntryMask) -> Result<Vec<Info>> {
+        Ok(self.entries(prefix, types)?.into_iter().filter_map(|entry| self.entry_info(entry)).collect())
+    }
+    fn delete_all_entries(&self);
+
+    /// Run `callback` for changes matching `flags` to every entry whose name starts with `prefix`.
+    fn add_entry_listener(&self, prefix: &str, flags: NotifyFlags, callback: NotificationCallback) -> Handle;
+    fn add_entry_listener_single(&self, entry: Handle, flags: NotifyFlags, callback: NotificationCallback) -> Handle;
+    /// Remove an entry listener, dropping its callback.
+    fn remove_entry_listener(&self, listener: Handle);
+    /// Run `callback` whenever a remote node connects or disconnects. If `immediate_notify` is
+    /// set, it is also run right away for every existing connection.
+    fn add_connection_listener(&self, immediate_notify: bool, callback: ConnectionCallback) -> Handle;
+    fn remove_connection_listener(&self, listener: Handle);
+}
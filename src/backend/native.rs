@@ -0,0 +1,389 @@
+//! The backend that runs on ntcore, through `ntcore-sys`.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fmt;
+use std::os::raw::{c_char, c_uint, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Mutex, PoisonError};
+use sys::{self, NT_ConnectionNotification, NT_EntryInfo, NT_EntryNotification, NT_Inst};
+use backend::{Backend, ConnectionCallback, Handle, Info, Notification, NotificationCallback};
+use connection::{ConnectionEvent, ConnectionInfo, OwnedConnectionInfo};
+use entry::{BorrowedValue, EntryFlags, EntryMask, EntryType, Value, ValueRef};
+use listener::NotifyFlags;
+use ::{Error, NetworkTime, NtString, Result};
+
+impl Notification {
+    pub(crate) unsafe fn from_raw(raw: &NT_EntryNotification) -> Self {
+        Notification {
+            entry: raw.entry,
+            name: NtString(raw.name).to_str_lossy().into_owned(),
+            value: Value::from_nt_value(&raw.value),
+            flags: NotifyFlags(raw.flags),
+        }
+    }
+}
+
+impl Info {
+    unsafe fn from_raw(raw: &NT_EntryInfo) -> Self {
+        Info {
+            entry: raw.entry,
+            name: NtString(raw.name).to_str_lossy().into_owned(),
+            entry_type: EntryType::try_from_raw(raw.type_).unwrap_or(EntryType::Unassigned),
+            flags: EntryFlags(raw.flags),
+            last_change: NetworkTime(raw.last_change),
+        }
+    }
+}
+
+unsafe extern "C" fn entry_listener_trampoline(data: *mut c_void, event: *const NT_EntryNotification) {
+    let callback = &*(data as *const NotificationCallback);
+    // Unwinding into C is UB, so anything that panics, whether copying the notification or the
+    // callback itself, has to stop here.
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| callback(Notification::from_raw(&*event))));
+}
+
+unsafe extern "C" fn connection_listener_trampoline(data: *mut c_void, event: *const NT_ConnectionNotification) {
+    let callback = &*(data as *const ConnectionCallback);
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| callback(ConnectionEvent::from_raw(&*event))));
+}
+
+fn c_servers(servers: &[(&str, u32)]) -> Result<(Vec<CString>, Vec<c_uint>)> {
+    // ntcore expects NUL terminated names here, and they have to outlive the call.
+    let names = servers.iter().map(|&(ip, _)| CString::new(ip)).collect::<::std::result::Result<Vec<_>, _>>()?;
+    let ports = servers.iter().map(|&(_, port)| port as c_uint).collect();
+    Ok((names, ports))
+}
+
+/// An ntcore instance. Instances other than the default one are stopped and destroyed when this is
+/// dropped.
+pub struct NativeBackend {
+    handle: NT_Inst,
+    // Callbacks are double boxed so that the pointer we hand to C is a thin pointer. They have to
+    // stay alive for as long as the listener is registered.
+    entry_callbacks: Mutex<HashMap<Handle, Box<NotificationCallback>>>,
+    connection_callbacks: Mutex<HashMap<Handle, Box<ConnectionCallback>>>,
+    // Removing a listener doesn't wait for a notification that is already being delivered, so
+    // removed callbacks are kept until the instance is destroyed and its notifier has stopped.
+    retired_callbacks: Mutex<Vec<Box<dyn Any + Send>>>,
+}
+
+impl NativeBackend {
+    fn from_handle(handle: NT_Inst) -> Self {
+        NativeBackend {
+            handle,
+            entry_callbacks: Mutex::new(HashMap::new()),
+            connection_callbacks: Mutex::new(HashMap::new()),
+            retired_callbacks: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Create a new ntcore instance.
+    pub fn new() -> Result<Self> {
+        let handle = unsafe { sys::NT_CreateInstance() };
+        // ntcore hands out a zero handle when it can't make any more instances.
+        if handle == 0 { Err(Error::InstanceStopped) } else { Ok(NativeBackend::from_handle(handle)) }
+    }
+
+    /// Wrap ntcore's default instance, which is never destroyed.
+    pub fn default_instance() -> Self {
+        NativeBackend::from_handle(unsafe { sys::NT_GetDefaultInstance() })
+    }
+
+    /// The raw ntcore instance handle, for use with `ntcore-sys` directly.
+    pub fn handle(&self) -> NT_Inst { self.handle }
+
+    /// ntcore only says that setting failed, which happens when the types don't match, so the
+    /// entry's current type is looked up to report.
+    fn type_mismatch(&self, entry: Handle, actual: EntryType) -> Error {
+        match self.entry_type(entry) {
+            Ok(expected) => Error::TypeMismatch { expected, actual },
+            Err(err) => err,
+        }
+    }
+
+    fn is_default_instance(&self) -> bool {
+        unsafe { self.handle == sys::NT_GetDefaultInstance() }
+    }
+
+    fn retire_callback<T: Send + 'static>(&self, callback: Option<Box<T>>) {
+        if let Some(callback) = callback {
+            self.retired_callbacks.lock().unwrap().push(callback);
+        }
+    }
+}
+
+impl fmt::Debug for NativeBackend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("NativeBackend").field("handle", &self.handle).finish()
+    }
+}
+
+impl Drop for NativeBackend {
+    fn drop(&mut self) {
+        if !self.is_default_instance() {
+            unsafe {
+                sys::NT_StopClient(self.handle);
+                sys::NT_DestroyInstance(self.handle);
+            }
+        } else {
+            // The default instance's notifier never stops, so it's never safe to free these.
+            let retired = self.retired_callbacks.get_mut().unwrap_or_else(PoisonError::into_inner);
+            ::std::mem::forget(::std::mem::take(retired));
+        }
+    }
+}
+
+impl Backend for NativeBackend {
+    fn as_any(&self) -> &dyn Any { self }
+
+    fn set_network_identity(&self, name: &str) {
+        unsafe { sys::NT_SetNetworkIdentity(self.handle, name.as_ptr() as *const c_char, name.len()) }
+    }
+
+    fn start_server(&self, persist_filename: &str, listen_address: &str, port: u32) -> Result<()> {
+        let persist_filename = CString::new(persist_filename)?;
+        let listen_address = CString::new(listen_address)?;
+        unsafe { sys::NT_StartServer(self.handle, persist_filename.as_ptr(), listen_address.as_ptr(), port as c_uint) }
+        Ok(())
+    }
+
+    fn stop_server(&self) {
+        unsafe { sys::NT_StopServer(self.handle) }
+    }
+
+    fn start_client_none(&self) {
+        unsafe { sys::NT_StartClientNone(self.handle) }
+    }
+
+    fn start_client(&self, servers: &[(&str, u32)]) -> Result<()> {
+        let (names, ports) = c_servers(servers)?;
+        let mut ips = names.iter().map(|name| name.as_ptr()).collect::<Vec<_>>();
+        unsafe { sys::NT_StartClientMulti(self.handle, ips.len(), ips.as_mut_ptr(), ports.as_ptr()) }
+        Ok(())
+    }
+
+    fn start_client_team(&self, team: u32, port: u32) {
+        unsafe { sys::NT_StartClientTeam(self.handle, team as c_uint, port as c_uint) }
+    }
+
+    fn stop_client(&self) {
+        unsafe { sys::NT_StopClient(self.handle) }
+    }
+
+    fn set_server(&self, servers: &[(&str, u32)]) -> Result<()> {
+        let (names, ports) = c_servers(servers)?;
+        let mut ips = names.iter().map(|name| name.as_ptr()).collect::<Vec<_>>();
+        unsafe { sys::NT_SetServerMulti(self.handle, ips.len(), ips.as_mut_ptr(), ports.as_ptr()) }
+        Ok(())
+    }
+
+    fn set_server_team(&self, team: u32, port: u32) {
+        unsafe { sys::NT_SetServerTeam(self.handle, team as c_uint, port as c_uint) }
+    }
+
+    fn set_update_rate(&self, interval: f64) {
+        unsafe { sys::NT_SetUpdateRate(self.handle, interval) }
+    }
+
+    fn flush(&self) {
+        unsafe { sys::NT_Flush(self.handle) }
+    }
+
+    fn is_connected(&self) -> bool {
+        unsafe { sys::NT_IsConnected(self.handle) != 0 }
+    }
+
+    fn connections(&self) -> Vec<OwnedConnectionInfo> {
+        let info = unsafe {
+            let mut len = 0;
+            let ptr = sys::NT_GetConnections(self.handle, &mut len);
+            ConnectionInfo::from_raw(ptr, len)
+        };
+        let conns = (&info).into_iter().map(|conn| conn.to_owned()).collect();
+        conns
+    }
+
+    fn entry(&self, name: &str) -> Handle {
+        unsafe { sys::NT_GetEntry(self.handle, name.as_ptr() as *const c_char, name.len()) }
+    }
+
+    fn entries(&self, prefix: &str, types: EntryMask) -> Result<Vec<Handle>> {
+        // TODO: submit issue on wpilibsuite/ntcore; this currently causes UB on OOM
+        unsafe {
+            // Get entries from C
+            let mut len = 0;
+            let ptr = sys::NT_GetEntries(self.handle, prefix.as_ptr() as *const c_char,
+                                         prefix.len(), types.0 as c_uint, &mut len);
+            if ptr.is_null() {
+                return if len != 0 { Err(Error::AllocationFailure) } else { Ok(Vec::new()) };
+            }
+            let ret = ::std::slice::from_raw_parts(ptr, len).to_vec();
+            // Free the C entry array; we've cloned it all.
+            sys::NT_DisposeEntryArray(ptr, len);
+            Ok(ret)
+        }
+    }
+
+    fn entry_name(&self, entry: Handle) -> Vec<u8> {
+        let mut len = 0;
+        let char_ptr = unsafe { sys::NT_GetEntryName(entry, &mut len) };
+        // Invalid handles give a null name.
+        if char_ptr.is_null() { return Vec::new(); }
+        unsafe { ::std::slice::from_raw_parts(char_ptr, len).iter().map(|&ch| ch as u8).collect() }
+    }
+
+    fn entry_type(&self, entry: Handle) -> Result<EntryType> {
+        EntryType::try_from_raw(unsafe { sys::NT_GetEntryType(entry) })
+    }
+
+    fn entry_value(&self, entry: Handle) -> Option<Value> {
+        unsafe {
+            let mut value = ::std::mem::zeroed();
+            sys::NT_GetEntryValue(entry, &mut value);
+            let val = Value::from_nt_value(&value);
+
+            // We've copied all the data from the union in one way or another; we can dispose of it now
+            sys::NT_DisposeValue(&mut value);
+
+            val
+        }
+    }
+
+    fn entry_value_ref(&self, entry: Handle) -> Option<ValueRef<'_>> {
+        unsafe {
+            let mut value: sys::NT_Value = ::std::mem::zeroed();
+            sys::NT_GetEntryValue(entry, &mut value);
+            if EntryType::try_from_raw(value.type_).unwrap_or(EntryType::Unassigned) == EntryType::Unassigned {
+                sys::NT_DisposeValue(&mut value);
+                None
+            } else {
+                // Disposed when the `ValueRef` is dropped.
+                Some(ValueRef::from_nt_value(value))
+            }
+        }
+    }
+
+    fn set_entry_value(&self, entry: Handle, value: &Value) -> Result<()> {
+        if value.with_nt_value(0, |nt_value| unsafe { sys::NT_SetEntryValue(entry, nt_value) != 0 }) {
+            Ok(())
+        } else {
+            Err(self.type_mismatch(entry, value.entry_type()))
+        }
+    }
+
+    fn set_entry_value_borrowed(&self, entry: Handle, value: BorrowedValue) -> Result<()> {
+        if value.with_nt_value(0, |nt_value| unsafe { sys::NT_SetEntryValue(entry, nt_value) != 0 }) {
+            Ok(())
+        } else {
+            Err(self.type_mismatch(entry, value.entry_type()))
+        }
+    }
+
+    fn set_default_entry_value(&self, entry: Handle, value: &Value) -> Result<bool> {
+        // ntcore only reports whether the types matched, not whether the default was used, so
+        // existing values are looked for first. An entry that appears with the same type between
+        // the two calls is still reported as set by us; anything else is caught by ntcore.
+        let actual = value.entry_type();
+        match self.entry_type(entry)? {
+            EntryType::Unassigned => {}
+            expected if expected == actual => return Ok(false),
+            expected => return Err(Error::TypeMismatch { expected, actual }),
+        }
+        if value.with_nt_value(0, |nt_value| unsafe { sys::NT_SetDefaultEntryValue(entry, nt_value) != 0 }) {
+            Ok(true)
+        } else {
+            Err(Error::TypeMismatch { expected: self.entry_type(entry)?, actual })
+        }
+    }
+
+    fn entry_flags(&self, entry: Handle) -> EntryFlags {
+        unsafe { EntryFlags(sys::NT_GetEntryFlags(entry)) }
+    }
+
+    fn set_entry_flags(&self, entry: Handle, flags: EntryFlags) {
+        unsafe { sys::NT_SetEntryFlags(entry, flags.0) }
+    }
+
+    fn entry_last_change(&self, entry: Handle) -> NetworkTime {
+        unsafe { NetworkTime(sys::NT_GetEntryLastChange(entry)) }
+    }
+
+    fn delete_entry(&self, entry: Handle) {
+        unsafe { sys::NT_DeleteEntry(entry) }
+    }
+
+    fn entry_info(&self, entry: Handle) -> Option<Info> {
+        unsafe {
+            let mut raw: NT_EntryInfo = ::std::mem::zeroed();
+            if sys::NT_GetEntryInfoHandle(entry, &mut raw) == 0 { return None; }
+            let info = Info::from_raw(&raw);
+            sys::NT_DisposeEntryInfo(&mut raw);
+            Some(info)
+        }
+    }
+
+    fn entries_info(&self, prefix: &str, types: EntryMask) -> Result<Vec<Info>> {
+        unsafe {
+            let mut len = 0;
+            let ptr = sys::NT_GetEntryInfo(self.handle, prefix.as_ptr() as *const c_char, prefix.len(),
+                                           types.0 as c_uint, &mut len);
+            if ptr.is_null() {
+                return if len != 0 { Err(Error::AllocationFailure) } else { Ok(Vec::new()) };
+            }
+            let ret = ::std::slice::from_raw_parts(ptr, len).iter().map(|raw| Info::from_raw(raw)).collect();
+            sys::NT_DisposeEntryInfoArray(ptr, len);
+            Ok(ret)
+        }
+    }
+
+    fn delete_all_entries(&self) {
+        unsafe { sys::NT_DeleteAllEntries(self.handle) }
+    }
+
+    fn add_entry_listener(&self, prefix: &str, flags: NotifyFlags, callback: NotificationCallback) -> Handle {
+        let mut callback = Box::new(callback);
+        let data = &mut *callback as *mut NotificationCallback as *mut c_void;
+        let handle = unsafe {
+            sys::NT_AddEntryListener(self.handle, prefix.as_ptr() as *const c_char, prefix.len(), data,
+                                     Some(entry_listener_trampoline), flags.0)
+        };
+        self.entry_callbacks.lock().unwrap().insert(handle, callback);
+        handle
+    }
+
+    fn add_entry_listener_single(&self, entry: Handle, flags: NotifyFlags, callback: NotificationCallback) -> Handle {
+        let mut callback = Box::new(callback);
+        let data = &mut *callback as *mut NotificationCallback as *mut c_void;
+        let handle = unsafe {
+            sys::NT_AddEntryListenerSingle(entry, data, Some(entry_listener_trampoline), flags.0)
+        };
+        self.entry_callbacks.lock().unwrap().insert(handle, callback);
+        handle
+    }
+
+    fn remove_entry_listener(&self, listener: Handle) {
+        // Polled listeners go through here too, they just don't have a callback to drop.
+        unsafe { sys::NT_RemoveEntryListener(listener) }
+        let callback = self.entry_callbacks.lock().unwrap().remove(&listener);
+        self.retire_callback(callback);
+    }
+
+    fn add_connection_listener(&self, immediate_notify: bool, callback: ConnectionCallback) -> Handle {
+        let mut callback = Box::new(callback);
+        let data = &mut *callback as *mut ConnectionCallback as *mut c_void;
+        let handle = unsafe {
+            sys::NT_AddConnectionListener(self.handle, data, Some(connection_listener_trampoline),
+                                          immediate_notify as sys::NT_Bool)
+        };
+        self.connection_callbacks.lock().unwrap().insert(handle, callback);
+        handle
+    }
+
+    fn remove_connection_listener(&self, listener: Handle) {
+        unsafe { sys::NT_RemoveConnectionListener(listener) }
+        let callback = self.connection_callbacks.lock().unwrap().remove(&listener);
+        self.retire_callback(callback);
+    }
+}
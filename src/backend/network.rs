@@ -0,0 +1,206 @@
+//! Entry handles and listeners for the backends of the pure-Rust protocol implementations. Those
+//! keep their entries by name and report every change as an `Event`; a `Notifier` hands out
+//! handles for the names and runs listeners on a thread of its own, like ntcore does, so that a
+//! callback can never run while the network code holds its locks.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use backend::{ConnectionCallback, Handle, ListenerFilter, Notification, NotificationCallback, Target};
+use connection::{ConnectionEvent, OwnedConnectionInfo};
+use entry::Value;
+use listener::NotifyFlags;
+use ::{Error, Result};
+
+/// Something for the notifier thread to do.
+pub(crate) enum Event {
+    /// An entry changed. `value` is the new value, or for deletions, the old one.
+    Entry { name: String, value: Option<Value>, flags: NotifyFlags },
+    Connection(ConnectionEvent),
+    AddEntryListener(Handle, ListenerFilter, NotificationCallback),
+    AddConnectionListener(Handle, ConnectionCallback),
+    /// Notifications for a listener that was just added, about the state things were already in.
+    Immediate(Handle, Vec<Notification>),
+    ImmediateConnections(Handle, Vec<ConnectionEvent>),
+    RemoveEntryListener(Handle),
+    RemoveConnectionListener(Handle),
+}
+
+/// Where network code sends its events. Sending never blocks, so it can be done under a lock.
+pub(crate) type EventSender = Sender<Event>;
+
+/// Report a change to an entry.
+pub(crate) fn notify(events: &EventSender, name: &str, value: Option<Value>, flags: NotifyFlags) {
+    // The notifier thread only stops once the backend is gone.
+    let _ = events.send(Event::Entry { name: name.to_owned(), value, flags });
+}
+
+#[derive(Debug, Default)]
+struct Names {
+    handles: HashMap<String, Handle>,
+    /// Names by handle, less one, so that 0 is never a valid handle.
+    names: Vec<String>,
+}
+
+impl Names {
+    fn handle(&mut self, name: &str) -> Handle {
+        if let Some(&handle) = self.handles.get(name) { return handle; }
+        self.names.push(name.to_owned());
+        let handle = self.names.len() as Handle;
+        self.handles.insert(name.to_owned(), handle);
+        handle
+    }
+}
+
+/// Hands out entry and listener handles, and runs listeners on a background thread. The thread
+/// stops once the notifier and every `EventSender` it gave out are gone.
+#[derive(Debug)]
+pub(crate) struct Notifier {
+    names: Arc<Mutex<Names>>,
+    sender: Mutex<EventSender>,
+    next_listener: Mutex<Handle>,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // A panicking callback shouldn't break the instance for everyone else.
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl Notifier {
+    pub fn new() -> Notifier {
+        let names = Arc::new(Mutex::new(Names::default()));
+        let (sender, receiver) = mpsc::channel();
+        let thread_names = names.clone();
+        thread::spawn(move || run(receiver, thread_names));
+        Notifier { names, sender: Mutex::new(sender), next_listener: Mutex::new(0) }
+    }
+
+    /// A sender for the network code to report changes with.
+    pub fn sender(&self) -> EventSender {
+        lock(&self.sender).clone()
+    }
+
+    fn send(&self, event: Event) {
+        // The thread only goes away along with the notifier.
+        let _ = lock(&self.sender).send(event);
+    }
+
+    pub fn entry(&self, name: &str) -> Handle {
+        lock(&self.names).handle(name)
+    }
+
+    pub fn name(&self, entry: Handle) -> Option<String> {
+        lock(&self.names).names.get((entry as usize).wrapping_sub(1)).cloned()
+    }
+
+    fn next_listener(&self) -> Handle {
+        let mut next = lock(&self.next_listener);
+        *next += 1;
+        *next
+    }
+
+    /// Add an entry listener. `existing` gives the current value of every entry, for listeners
+    /// that asked to hear about those right away.
+    pub fn add_entry_listener<F>(&self, target: Target, flags: NotifyFlags, callback: NotificationCallback, existing: F)
+        -> Handle
+        where F: FnOnce() -> Vec<(String, Value)>
+    {
+        let listener = self.next_listener();
+        self.send(Event::AddEntryListener(listener, ListenerFilter { target, flags }, callback));
+        if flags.is_immediate() {
+            // Looked at after the listener is added, so a change in between is seen one way or
+            // the other.
+            let flags = NotifyFlags::IMMEDIATE | NotifyFlags::NEW;
+            let notifications = existing().into_iter()
+                .map(|(name, value)| Notification { entry: self.entry(&name), name, value: Some(value), flags })
+                .collect();
+            self.send(Event::Immediate(listener, notifications));
+        }
+        listener
+    }
+
+    pub fn remove_entry_listener(&self, listener: Handle) {
+        self.send(Event::RemoveEntryListener(listener));
+    }
+
+    pub fn add_connection_listener(&self, immediate_notify: bool, callback: ConnectionCallback,
+                                   connections: Vec<OwnedConnectionInfo>) -> Handle {
+        let listener = self.next_listener();
+        self.send(Event::AddConnectionListener(listener, callback));
+        if immediate_notify {
+            let events = connections.into_iter().map(ConnectionEvent::Connected).collect();
+            self.send(Event::ImmediateConnections(listener, events));
+        }
+        listener
+    }
+
+    pub fn remove_connection_listener(&self, listener: Handle) {
+        self.send(Event::RemoveConnectionListener(listener));
+    }
+}
+
+fn run(receiver: Receiver<Event>, names: Arc<Mutex<Names>>) {
+    let mut entry_listeners: HashMap<Handle, (ListenerFilter, NotificationCallback)> = HashMap::new();
+    let mut connection_listeners: HashMap<Handle, ConnectionCallback> = HashMap::new();
+
+    // A panicking callback is stopped here, so it doesn't take every other listener with it.
+    fn call<T, F: Fn(T) + ?Sized>(callback: &F, arg: T) {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| callback(arg)));
+    }
+
+    for event in receiver {
+        match event {
+            Event::Entry { name, value, flags } => {
+                let entry = lock(&names).handle(&name);
+                for (filter, callback) in entry_listeners.values() {
+                    if filter.matches(entry, &name, flags) {
+                        call(&**callback, Notification { entry, name: name.clone(), value: value.clone(), flags });
+                    }
+                }
+            }
+            Event::Connection(event) => {
+                for callback in connection_listeners.values() {
+                    call(&**callback, event.clone());
+                }
+            }
+            Event::AddEntryListener(listener, filter, callback) => { entry_listeners.insert(listener, (filter, callback)); }
+            Event::AddConnectionListener(listener, callback) => { connection_listeners.insert(listener, callback); }
+            Event::Immediate(listener, notifications) => if let Some((filter, callback)) = entry_listeners.get(&listener) {
+                for notification in notifications {
+                    if filter.target.covers(notification.entry, &notification.name) {
+                        call(&**callback, notification);
+                    }
+                }
+            },
+            Event::ImmediateConnections(listener, events) => if let Some(callback) = connection_listeners.get(&listener) {
+                for event in events { call(&**callback, event); }
+            },
+            Event::RemoveEntryListener(listener) => { entry_listeners.remove(&listener); }
+            Event::RemoveConnectionListener(listener) => { connection_listeners.remove(&listener); }
+        }
+    }
+}
+
+/// The addresses ntcore tries for a team's robot, in the order it tries them.
+pub(crate) fn team_servers(team: u32) -> Vec<String> {
+    vec![
+        format!("10.{}.{}.2", team / 100, team % 100),
+        format!("roborio-{}-frc.local", team),
+        format!("roborio-{}-frc.lan", team),
+        format!("roborio-{}-frc.frc-field.local", team),
+        "172.22.11.2".to_owned(),
+    ]
+}
+
+/// Check that every port fits in 16 bits, like the ones ntcore takes.
+pub(crate) fn parse_servers(servers: &[(&str, u32)]) -> Result<Vec<(String, u16)>> {
+    servers.iter()
+        .map(|&(host, port)| match u16::try_from(port) {
+            Ok(port) => Ok((host.to_owned(), port)),
+            Err(_) => Err(Error::InvalidAddress(format!("{}:{}", host, port))),
+        })
+        .collect()
+}
@@ -0,0 +1,40 @@
+//! Binding the fields of a struct to the keys of a table.
+//!
+//! With the `derive` feature, `#[derive(NtTable)]` implements `NtTable` for structs with named
+//! fields. Every field is stored under its own name, and has to implement `Clone`, `IntoValue` and
+//! `FromValue`. Fields can be tweaked with `#[nt(...)]` attributes:
+//!
+//! - `rename = "key"` stores the field under `key` instead.
+//! - `persistent` marks the entry persistent whenever it is published.
+//! - `read_only` publishes the field, but never loads it back; `load` uses `Default::default()`
+//!   for it instead. Use this for values the dashboard should see but not change.
+//! - `subtable` stores a field whose type is itself an `NtTable` in a subtable.
+//!
+//! ```rs
+//! #[derive(NtTable)]
+//! struct Shooter {
+//!     #[nt(persistent)]
+//!     target_rpm: f64,
+//!     #[nt(rename = "atSpeed", read_only)]
+//!     at_speed: bool,
+//! }
+//!
+//! shooter.publish(&mut inst.get_table("/Shooter".into()))?;
+//! ```
+
+use table::NetworkTable;
+use ::Result;
+
+/// A struct whose fields are stored as the entries of a table.
+pub trait NtTable: Sized {
+    /// Set every field's entry to the field's value.
+    fn publish(&self, table: &mut NetworkTable) -> Result<()>;
+
+    /// Read every field back out of the table. Fails if an entry is missing or holds a value that
+    /// doesn't convert to the field's type.
+    fn load(table: &mut NetworkTable) -> Result<Self>;
+
+    /// Set every field's entry to the field's value, but only if the entry doesn't have a value
+    /// yet.
+    fn set_defaults(&self, table: &mut NetworkTable) -> Result<()>;
+}
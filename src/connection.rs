@@ -1,44 +1,132 @@
-use sys::{self, NT_ConnectionInfo};
+#[cfg(feature = "ntcore-sys")]
+use sys::{self, NT_ConnectionInfo, NT_ConnectionNotification};
+#[cfg(feature = "ntcore-sys")]
+use std::borrow::Cow;
 use std::net::IpAddr;
+use ::{Error, NetworkTime, Result};
+#[cfg(feature = "ntcore-sys")]
 use ::NtString;
 
+fn parse_ip(addr: &str) -> Result<IpAddr> {
+    addr.parse().map_err(|_| Error::InvalidAddress(addr.to_owned()))
+}
+
+#[cfg(feature = "ntcore-sys")]
 #[derive(Debug)]
 pub struct ConnectionInfoEntry<'c>(&'c NT_ConnectionInfo);
 
+#[cfg(feature = "ntcore-sys")]
 impl<'c> ConnectionInfoEntry<'c> {
-    // Strings live as long as the connection info.
-    pub fn remote_id(&self) -> &str { unsafe { NtString(self.0.remote_id).as_str() } }
-    pub fn remote_ip_str(&self) -> &str { unsafe { NtString(self.0.remote_ip).as_str() } }
-    pub fn remote_ip(&self) -> IpAddr { self.remote_ip_str().parse().unwrap() }
+    /// Copy the connection info out so it can outlive the array it came from.
+    pub fn to_owned(&self) -> OwnedConnectionInfo {
+        OwnedConnectionInfo {
+            remote_id: self.remote_id().into_owned(),
+            remote_ip: self.remote_ip_str().into_owned(),
+            remote_port: self.remote_port(),
+            last_update: self.last_update(),
+            protocol_version: self.protocol_version(),
+        }
+    }
+
+    // Strings live as long as the connection info. They come from the remote node, so invalid
+    // UTF-8 is replaced.
+    pub fn remote_id(&self) -> Cow<'c, str> { unsafe { NtString(self.0.remote_id).to_str_lossy() } }
+    pub fn remote_ip_str(&self) -> Cow<'c, str> { unsafe { NtString(self.0.remote_ip).to_str_lossy() } }
+    /// Fails if the address can't be parsed. `remote_ip_str` has it as ntcore reported it.
+    pub fn remote_ip(&self) -> Result<IpAddr> { parse_ip(&self.remote_ip_str()) }
     pub fn remote_port(&self) -> u32 { self.0.remote_port as u32 }
-    pub fn last_update(&self) -> ::NetworkTime { ::NetworkTime(self.0.last_update) }
+    pub fn last_update(&self) -> NetworkTime { NetworkTime(self.0.last_update) }
     pub fn protocol_version(&self) -> u32 { self.0.protocol_version as u32 }
 }
 
+#[cfg(feature = "ntcore-sys")]
 impl<'c> PartialEq for ConnectionInfoEntry<'c> {
     fn eq(&self, other: &ConnectionInfoEntry) -> bool {
         self.last_update() == other.last_update() &&
         self.protocol_version() == other.protocol_version() &&
         self.remote_id() == other.remote_id() &&
-        self.remote_ip() == other.remote_ip() &&
+        self.remote_ip_str() == other.remote_ip_str() &&
         self.remote_port() == other.remote_port()
     }
 }
 
+#[cfg(feature = "ntcore-sys")]
 impl<'c> Eq for ConnectionInfoEntry<'c> {}
 
+/// An owned copy of the information in a `ConnectionInfoEntry`.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct OwnedConnectionInfo {
+    remote_id: String,
+    remote_ip: String,
+    remote_port: u32,
+    last_update: NetworkTime,
+    protocol_version: u32,
+}
+
+impl OwnedConnectionInfo {
+    pub fn new(remote_id: String, remote_ip: String, remote_port: u32, last_update: NetworkTime,
+               protocol_version: u32) -> Self {
+        OwnedConnectionInfo { remote_id, remote_ip, remote_port, last_update, protocol_version }
+    }
+
+    #[cfg(feature = "ntcore-sys")]
+    pub(crate) unsafe fn from_raw(raw: &NT_ConnectionInfo) -> Self {
+        ConnectionInfoEntry(raw).to_owned()
+    }
+
+    pub fn remote_id(&self) -> &str { &self.remote_id }
+    pub fn remote_ip_str(&self) -> &str { &self.remote_ip }
+    /// Fails if the address can't be parsed. `remote_ip_str` has it as it was reported.
+    pub fn remote_ip(&self) -> Result<IpAddr> { parse_ip(&self.remote_ip) }
+    pub fn remote_port(&self) -> u32 { self.remote_port }
+    pub fn last_update(&self) -> NetworkTime { self.last_update }
+    pub fn protocol_version(&self) -> u32 { self.protocol_version }
+}
+
+/// A change in the connection state of a remote node, as delivered to connection listeners.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ConnectionEvent {
+    Connected(OwnedConnectionInfo),
+    Disconnected(OwnedConnectionInfo),
+}
+
+impl ConnectionEvent {
+    #[cfg(feature = "ntcore-sys")]
+    pub(crate) unsafe fn from_raw(raw: &NT_ConnectionNotification) -> Self {
+        let info = OwnedConnectionInfo::from_raw(&raw.conn);
+        if raw.connected != 0 { ConnectionEvent::Connected(info) } else { ConnectionEvent::Disconnected(info) }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        match *self {
+            ConnectionEvent::Connected(_) => true,
+            ConnectionEvent::Disconnected(_) => false,
+        }
+    }
+
+    /// The connection this event is about.
+    pub fn info(&self) -> &OwnedConnectionInfo {
+        match *self {
+            ConnectionEvent::Connected(ref info) | ConnectionEvent::Disconnected(ref info) => info,
+        }
+    }
+}
+
+#[cfg(feature = "ntcore-sys")]
 #[derive(Debug, Eq, PartialEq, Hash)]
 pub struct ConnectionInfo {
     ptr: *mut NT_ConnectionInfo,
     len: usize,
 }
 
+#[cfg(feature = "ntcore-sys")]
 impl Drop for ConnectionInfo {
     fn drop(&mut self) {
         unsafe { sys::NT_DisposeConnectionInfoArray(self.ptr, self.len) }
     }
 }
 
+#[cfg(feature = "ntcore-sys")]
 impl ConnectionInfo {
     pub(crate) fn from_raw(ptr: *mut NT_ConnectionInfo, len: usize) -> Self {
         ConnectionInfo { ptr, len }
@@ -54,6 +142,7 @@ impl ConnectionInfo {
     }
 }
 
+#[cfg(feature = "ntcore-sys")]
 impl<'c> IntoIterator for &'c ConnectionInfo {
     type Item = ConnectionInfoEntry<'c>;
     type IntoIter = ConnectionInfoIter<'c>;
@@ -63,9 +152,11 @@ impl<'c> IntoIterator for &'c ConnectionInfo {
     }
 }
 
+#[cfg(feature = "ntcore-sys")]
 #[derive(Debug, Eq, PartialEq, Hash)]
 pub struct ConnectionInfoIter<'c>(&'c ConnectionInfo, usize);
 
+#[cfg(feature = "ntcore-sys")]
 impl<'c> Iterator for ConnectionInfoIter<'c> {
     type Item = ConnectionInfoEntry<'c>;
     fn next(&mut self) -> Option<Self::Item> {
@@ -73,3 +164,33 @@ impl<'c> Iterator for ConnectionInfoIter<'c> {
         self.0.get(self.1 - 1)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(remote_ip: &str) -> OwnedConnectionInfo {
+        OwnedConnectionInfo::new("dashboard".to_owned(), remote_ip.to_owned(), 1735, NetworkTime(0), 0x0300)
+    }
+
+    #[test]
+    fn remote_ip_parses_v4_and_v6() {
+        assert_eq!(info("10.12.34.5").remote_ip(), Ok("10.12.34.5".parse().unwrap()));
+        assert_eq!(info("::1").remote_ip(), Ok("::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn remote_ip_reports_bad_addresses() {
+        let info = info("roborio-1234-frc.local");
+        assert_eq!(info.remote_ip(), Err(Error::InvalidAddress("roborio-1234-frc.local".to_owned())));
+        assert_eq!(info.remote_ip_str(), "roborio-1234-frc.local");
+    }
+
+    #[test]
+    fn events_expose_their_connection() {
+        let event = ConnectionEvent::Disconnected(info("127.0.0.1"));
+        assert!(!event.is_connected());
+        assert_eq!(event.info().remote_port(), 1735);
+        assert!(ConnectionEvent::Connected(info("127.0.0.1")).is_connected());
+    }
+}
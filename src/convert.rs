@@ -0,0 +1,263 @@
+//! Conversions between `Value` and ordinary Rust types.
+//!
+//! `FromValue` takes a payload out of a `Value`. Besides the exact payload types, integers and
+//! `f32` can be read from doubles, as long as the double fits exactly. Going the other way, any
+//! type that `Value` implements `From` for is an `IntoValue`, including string and number slices
+//! and arrays. `Option<T>` converts both ways too: reading one wraps the payload in `Some`, and
+//! setting `None` deletes the entry.
+
+use std::convert::TryFrom;
+use entry::{EntryType, Value};
+use ::{Error, Result};
+
+/// Types that can be read out of a `Value`.
+pub trait FromValue: Sized {
+    /// Fails with a type mismatch if `value` holds a different type, or an invalid conversion if
+    /// it holds the right type but can't be represented, like `1.5` as an `i32`.
+    fn from_value(value: Value) -> Result<Self>;
+}
+
+/// Types that can be turned into a `Value`, for setting entries. Implemented for everything
+/// `Value` implements `From` for, and for `Option`s of those.
+pub trait IntoValue {
+    /// `None` means there is no value, and setting it deletes the entry.
+    fn into_value(self) -> Option<Value>;
+}
+
+impl<T: Into<Value>> IntoValue for T {
+    fn into_value(self) -> Option<Value> { Some(self.into()) }
+}
+
+impl<T: Into<Value>> IntoValue for Option<T> {
+    fn into_value(self) -> Option<Value> { self.map(Into::into) }
+}
+
+impl FromValue for Value {
+    fn from_value(value: Value) -> Result<Self> { Ok(value) }
+}
+
+macro_rules! impl_from_value {
+    ($($type:ty => $name:ident($entry_type:ident),)*) => {$(
+        impl FromValue for $type {
+            fn from_value(value: Value) -> Result<Self> {
+                match value {
+                    Value::$name(inner) => Ok(inner),
+                    other => Err(Error::TypeMismatch { expected: other.entry_type(), actual: EntryType::$entry_type }),
+                }
+            }
+        }
+    )*}
+}
+
+impl_from_value! {
+    bool => Bool(Boolean),
+    f64 => Double(Double),
+    String => String(String),
+    Vec<bool> => BoolArray(BooleanArray),
+    Vec<f64> => DoubleArray(DoubleArray),
+    Vec<String> => StringArray(StringArray),
+    Vec<u8> => Raw(Raw),
+}
+
+macro_rules! impl_from_value_int {
+    ($($type:ty,)*) => {$(
+        impl FromValue for $type {
+            fn from_value(value: Value) -> Result<Self> {
+                let double = f64::from_value(value)?;
+                // `as` would silently truncate fractions and saturate out of range values. The
+                // upper bound rounds up to a power of two for wide types, hence the `<`.
+                if double.fract() == 0.0 && double >= <$type>::MIN as f64 && double < <$type>::MAX as f64 + 1.0 {
+                    Ok(double as $type)
+                } else {
+                    Err(Error::InvalidConversion { value: Value::Double(double), target: stringify!($type) })
+                }
+            }
+        }
+    )*}
+}
+
+impl_from_value_int! { i8, i16, i32, i64, isize, u8, u16, u32, u64, usize, }
+
+impl FromValue for f32 {
+    fn from_value(value: Value) -> Result<Self> {
+        let double = f64::from_value(value)?;
+        // Precision is lost either way, but finite doubles shouldn't turn into infinities.
+        if double.is_finite() && double.abs() > f32::MAX as f64 {
+            Err(Error::InvalidConversion { value: Value::Double(double), target: "f32" })
+        } else {
+            Ok(double as f32)
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: Value) -> Result<Self> { T::from_value(value).map(Some) }
+}
+
+impl<T, const N: usize> FromValue for [T; N] where Vec<T>: FromValue + Into<Value> {
+    fn from_value(value: Value) -> Result<Self> {
+        <[T; N]>::try_from(Vec::<T>::from_value(value)?)
+            .map_err(|vec| Error::InvalidConversion { value: vec.into(), target: "array of that length" })
+    }
+}
+
+macro_rules! impl_try_from {
+    ($($type:ty,)*) => {$(
+        impl TryFrom<Value> for $type {
+            type Error = Error;
+
+            fn try_from(value: Value) -> Result<Self> { <$type as FromValue>::from_value(value) }
+        }
+    )*}
+}
+
+impl_try_from! {
+    bool, f64, f32, String, Vec<bool>, Vec<f64>, Vec<String>, Vec<u8>,
+    i8, i16, i32, i64, isize, u8, u16, u32, u64, usize,
+}
+
+macro_rules! impl_from_number {
+    ($($type:ty,)*) => {$(
+        impl From<$type> for Value {
+            fn from(val: $type) -> Self { Value::Double(val.into()) }
+        }
+    )*}
+}
+
+// Only types that always fit in a double exactly.
+impl_from_number! { f32, i8, i16, i32, u8, u16, u32, }
+
+impl<'a> From<&'a str> for Value {
+    fn from(val: &'a str) -> Self { Value::String(val.to_owned()) }
+}
+
+impl<'a> From<&'a [bool]> for Value {
+    fn from(val: &'a [bool]) -> Self { Value::BoolArray(val.to_vec()) }
+}
+
+impl<'a> From<&'a [f64]> for Value {
+    fn from(val: &'a [f64]) -> Self { Value::DoubleArray(val.to_vec()) }
+}
+
+impl<'a, 'b> From<&'a [&'b str]> for Value {
+    fn from(val: &'a [&'b str]) -> Self { Value::StringArray(val.iter().map(|&s| s.to_owned()).collect()) }
+}
+
+impl<'a> From<Vec<&'a str>> for Value {
+    fn from(val: Vec<&'a str>) -> Self { Value::from(&val[..]) }
+}
+
+impl<'a> From<&'a [String]> for Value {
+    fn from(val: &'a [String]) -> Self { Value::StringArray(val.to_vec()) }
+}
+
+impl<'a> From<&'a [u8]> for Value {
+    fn from(val: &'a [u8]) -> Self { Value::Raw(val.to_vec()) }
+}
+
+impl<T, const N: usize> From<[T; N]> for Value where Vec<T>: Into<Value> {
+    fn from(val: [T; N]) -> Self { Vec::from(val).into() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invalid(value: f64, target: &'static str) -> Error {
+        Error::InvalidConversion { value: Value::Double(value), target }
+    }
+
+    #[test]
+    fn exact_payloads_go_both_ways() {
+        assert_eq!(bool::from_value(true.into_value().unwrap()), Ok(true));
+        assert_eq!(f64::from_value(1.5.into_value().unwrap()), Ok(1.5));
+        assert_eq!(String::from_value("hi".into_value().unwrap()), Ok("hi".to_owned()));
+        assert_eq!(Vec::<bool>::from_value([true, false].into_value().unwrap()), Ok(vec![true, false]));
+        assert_eq!(Vec::<f64>::from_value((&[1.0, 2.0][..]).into_value().unwrap()), Ok(vec![1.0, 2.0]));
+        assert_eq!(Vec::<String>::from_value(vec!["a", "b"].into_value().unwrap()), Ok(vec!["a".to_owned(), "b".to_owned()]));
+        assert_eq!(Vec::<u8>::from_value((&b"raw"[..]).into_value().unwrap()), Ok(b"raw".to_vec()));
+        assert_eq!(Value::from_value(Value::Bool(true)), Ok(Value::Bool(true)));
+    }
+
+    #[test]
+    fn other_types_are_mismatches() {
+        assert_eq!(bool::from_value(Value::Double(1.0)),
+                   Err(Error::TypeMismatch { expected: EntryType::Double, actual: EntryType::Boolean }));
+        assert_eq!(i32::from_value(Value::String("1".to_owned())),
+                   Err(Error::TypeMismatch { expected: EntryType::String, actual: EntryType::Double }));
+    }
+
+    #[test]
+    fn small_integers_widen_exactly() {
+        assert_eq!(i8::MIN.into_value(), Some(Value::Double(-128.0)));
+        assert_eq!(u32::MAX.into_value(), Some(Value::Double(4_294_967_295.0)));
+        assert_eq!(i32::MIN.into_value(), Some(Value::Double(-2_147_483_648.0)));
+        assert_eq!(1.5f32.into_value(), Some(Value::Double(1.5)));
+    }
+
+    #[test]
+    fn integers_must_fit_their_type() {
+        assert_eq!(i8::from_value(Value::Double(-128.0)), Ok(i8::MIN));
+        assert_eq!(i8::from_value(Value::Double(127.0)), Ok(i8::MAX));
+        assert_eq!(i8::from_value(Value::Double(-129.0)), Err(invalid(-129.0, "i8")));
+        assert_eq!(i8::from_value(Value::Double(128.0)), Err(invalid(128.0, "i8")));
+
+        assert_eq!(u8::from_value(Value::Double(255.0)), Ok(u8::MAX));
+        assert_eq!(u8::from_value(Value::Double(256.0)), Err(invalid(256.0, "u8")));
+        assert_eq!(u8::from_value(Value::Double(-1.0)), Err(invalid(-1.0, "u8")));
+
+        assert_eq!(u32::from_value(Value::Double(4_294_967_295.0)), Ok(u32::MAX));
+        assert_eq!(u32::from_value(Value::Double(4_294_967_296.0)), Err(invalid(4_294_967_296.0, "u32")));
+        assert_eq!(i32::from_value(Value::Double(1.5)), Err(invalid(1.5, "i32")));
+    }
+
+    #[test]
+    fn wide_integers_stop_below_the_next_power_of_two() {
+        // The MAX of these types isn't a double, and rounds up to 2^63 or 2^64, which don't fit.
+        let two_63 = 9_223_372_036_854_775_808.0;
+        assert_eq!(i64::from_value(Value::Double(-two_63)), Ok(i64::MIN));
+        assert_eq!(i64::from_value(Value::Double(two_63)), Err(invalid(two_63, "i64")));
+        let below_two_63 = 9_223_372_036_854_774_784.0;
+        assert_eq!(i64::from_value(Value::Double(below_two_63)), Ok(9_223_372_036_854_774_784));
+
+        let two_64 = 18_446_744_073_709_551_616.0;
+        assert_eq!(u64::from_value(Value::Double(two_64)), Err(invalid(two_64, "u64")));
+        let below_two_64 = 18_446_744_073_709_549_568.0;
+        assert_eq!(u64::from_value(Value::Double(below_two_64)), Ok(18_446_744_073_709_549_568));
+
+        assert_eq!(i64::from_value(Value::Double(f64::INFINITY)), Err(invalid(f64::INFINITY, "i64")));
+        assert!(i64::from_value(Value::Double(f64::NAN)).is_err());
+    }
+
+    #[test]
+    fn floats_must_fit_in_range() {
+        assert_eq!(f32::from_value(Value::Double(f32::MAX as f64)), Ok(f32::MAX));
+        assert_eq!(f32::from_value(Value::Double(1e39)), Err(invalid(1e39, "f32")));
+        assert_eq!(f32::from_value(Value::Double(-1e39)), Err(invalid(-1e39, "f32")));
+        // Infinities were infinite to begin with.
+        assert_eq!(f32::from_value(Value::Double(f64::INFINITY)), Ok(f32::INFINITY));
+        assert!(f32::from_value(Value::Double(f64::NAN)).unwrap().is_nan());
+    }
+
+    #[test]
+    fn arrays_must_have_the_right_length() {
+        assert_eq!(<[f64; 2]>::from_value(Value::DoubleArray(vec![1.0, 2.0])), Ok([1.0, 2.0]));
+        assert_eq!(<[f64; 3]>::from_value(Value::DoubleArray(vec![1.0, 2.0])),
+                   Err(Error::InvalidConversion { value: Value::DoubleArray(vec![1.0, 2.0]), target: "array of that length" }));
+    }
+
+    #[test]
+    fn options_go_both_ways() {
+        assert_eq!(Option::<f64>::from_value(Value::Double(1.5)), Ok(Some(1.5)));
+        assert_eq!(Option::<bool>::from_value(Value::Double(1.5)),
+                   Err(Error::TypeMismatch { expected: EntryType::Double, actual: EntryType::Boolean }));
+        assert_eq!(Some("hi").into_value(), Some(Value::String("hi".to_owned())));
+        assert_eq!(None::<f64>.into_value(), None);
+    }
+
+    #[test]
+    fn try_from_matches_from_value() {
+        assert_eq!(u16::try_from(Value::Double(7.0)), Ok(7));
+        assert_eq!(u16::try_from(Value::Double(-7.0)), Err(invalid(-7.0, "u16")));
+    }
+}
@@ -1,13 +1,50 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::sync::Arc;
+#[cfg(feature = "ntcore-sys")]
 use std::os::raw::c_char;
-use sys::{self, NT_Entry, NT_Value, NT_Type, NT_Bool, NT_String};
-use ::{NetworkTime, NtString};
-
+#[cfg(feature = "ntcore-sys")]
+use sys::{self, NT_Entry, NT_Value, NT_Bool, NT_String};
+use backend::{Backend, Handle, Info};
+use ::{Error, NetworkTime, Result};
+#[cfg(feature = "ntcore-sys")]
+use ::NtString;
+use listener::{EntryListener, EntryNotification, NotifyFlags};
+use convert::{FromValue, IntoValue};
+use typed::TypedEntry;
+#[cfg(feature = "async")]
+use stream::EntryChanges;
+#[cfg(feature = "ntcore-sys")]
+use backend::NativeBackend;
+#[cfg(feature = "ntcore-sys")]
+use rpc::{self, RpcAnswer, RpcArgs, RpcCall, RpcCallPoller, RpcDefinition, RpcError, RpcResult};
+
+#[cfg(feature = "ntcore-sys")]
 type ValueUnionBoolArr = sys::NT_Value__bindgen_ty_1__bindgen_ty_1;
+#[cfg(feature = "ntcore-sys")]
 type ValueUnionDoubleArr = sys::NT_Value__bindgen_ty_1__bindgen_ty_2;
+#[cfg(feature = "ntcore-sys")]
 type ValueUnionStringArr = sys::NT_Value__bindgen_ty_1__bindgen_ty_3;
 
+#[cfg(feature = "ntcore-sys")]
+macro_rules! value {($ty:ident, $last_change:expr, $what:ident: $what2:expr) => {
+    NT_Value {
+        type_: sys::$ty, last_change: $last_change,
+        data: sys::NT_Value__bindgen_ty_1 { $what: $what2 }
+    }
+}}
+
+/// Build a C string that borrows from `val`. ntcore copies strings it is handed, so they don't
+/// need to be NUL terminated.
+#[cfg(feature = "ntcore-sys")]
+fn to_nt_string<S: AsRef<[u8]> + ?Sized>(val: &S) -> NT_String {
+    NT_String { len: val.as_ref().len(), str: val.as_ref().as_ptr() as *mut c_char }
+}
+
 /// Owned value from a network table entry. The data is cloned from the table.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Value {
     Bool(bool),
     BoolArray(Vec<bool>),
@@ -16,6 +53,8 @@ pub enum Value {
     String(String),
     StringArray(Vec<String>),
     Raw(Vec<u8>),
+    /// The packed definition of a remote procedure.
+    Rpc(Vec<u8>),
 }
 
 macro_rules! map_value {
@@ -31,47 +70,85 @@ macro_rules! map_value {
 }
 
 impl Value {
-    fn to_nt_value(self, last_change: u64) -> NT_Value {
-        macro_rules! value {($ty:ident, $last_change:expr, $what:ident: $what2:expr) => {
-            NT_Value {
-                type_: sys::$ty, last_change: $last_change,
-                data: sys::NT_Value__bindgen_ty_1 { $what: $what2 }
-            }
-        }}
-
-        fn to_nt_string<S: AsRef<[u8]>>(val: S) -> NT_String {
-            NT_String { len: val.as_ref().len(), str: val.as_ref().as_ptr() as *mut c_char }
+    /// The type an entry holding this value would have.
+    pub fn entry_type(&self) -> EntryType {
+        match *self {
+            Value::Bool(_) => EntryType::Boolean,
+            Value::BoolArray(_) => EntryType::BooleanArray,
+            Value::Double(_) => EntryType::Double,
+            Value::DoubleArray(_) => EntryType::DoubleArray,
+            Value::String(_) => EntryType::String,
+            Value::StringArray(_) => EntryType::StringArray,
+            Value::Raw(_) => EntryType::Raw,
+            Value::Rpc(_) => EntryType::Rpc,
         }
+    }
 
-        fn bool_array(val: Vec<bool>, last_change: u64) -> NT_Value {
-            // C code has a different representation for bools, we need to allocate here :(
-            let mut c_arr = val.into_iter().map(|val| val as i32).collect::<Vec<_>>();
-            value!(NT_Type_NT_BOOLEAN_ARRAY, last_change, arr_boolean: ValueUnionBoolArr {
-                size: c_arr.len(), arr: c_arr.as_mut_ptr()
-            })
+    /// Build a C value that borrows from this one and pass it to `func`. The C value is only valid
+    /// for the duration of the call.
+    #[cfg(feature = "ntcore-sys")]
+    pub(crate) fn with_nt_value<R, F: FnOnce(&NT_Value) -> R>(&self, last_change: u64, func: F) -> R {
+        match *self {
+            Value::Bool(val) => func(&value!(NT_Type_NT_BOOLEAN, last_change, v_boolean: val as NT_Bool)),
+            Value::BoolArray(ref val) => {
+                // C code has a different representation for bools, we need to allocate here :(
+                let mut c_arr = val.iter().map(|&val| val as NT_Bool).collect::<Vec<_>>();
+                func(&value!(NT_Type_NT_BOOLEAN_ARRAY, last_change, arr_boolean: ValueUnionBoolArr {
+                    size: c_arr.len(), arr: c_arr.as_mut_ptr()
+                }))
+            }
+            Value::Double(val) => func(&value!(NT_Type_NT_DOUBLE, last_change, v_double: val)),
+            Value::DoubleArray(ref val) => {
+                func(&value!(NT_Type_NT_DOUBLE_ARRAY, last_change, arr_double: ValueUnionDoubleArr {
+                    size: val.len(), arr: val.as_ptr() as *mut f64
+                }))
+            }
+            Value::String(ref val) => func(&value!(NT_Type_NT_STRING, last_change, v_string: to_nt_string(val))),
+            Value::StringArray(ref val) => {
+                let mut c_arr = val.iter().map(to_nt_string).collect::<Vec<_>>();
+                func(&value!(NT_Type_NT_STRING_ARRAY, last_change, arr_string: ValueUnionStringArr {
+                    size: c_arr.len(), arr: c_arr.as_mut_ptr()
+                }))
+            }
+            Value::Raw(ref val) => func(&value!(NT_Type_NT_RAW, last_change, v_raw: to_nt_string(val))),
+            Value::Rpc(ref val) => func(&value!(NT_Type_NT_RPC, last_change, v_raw: to_nt_string(val))),
         }
+    }
 
-        fn string_array(val: Vec<String>, last_change: u64) -> NT_Value {
-            let mut c_arr = val.into_iter().map(to_nt_string).collect::<Vec<_>>();
-            value!(NT_Type_NT_STRING_ARRAY, last_change, arr_string: ValueUnionStringArr {
-                size: c_arr.len(), arr: c_arr.as_mut_ptr()
-            })
-        }
+    /// Copy the contents of a C value into an owned `Value`. Returns `None` if the value is
+    /// unassigned. The C value is not disposed.
+    #[cfg(feature = "ntcore-sys")]
+    pub(crate) unsafe fn from_nt_value(value: &NT_Value) -> Option<Value> {
+        // Types we don't know about are treated the same as unassigned values.
+        Some(match EntryType::try_from_raw(value.type_).unwrap_or(EntryType::Unassigned) {
+            EntryType::Boolean => Value::Bool(value.data.v_boolean != 0),
+            EntryType::Double => Value::Double(value.data.v_double as f64),
+            EntryType::String => Value::String(NtString(value.data.v_string).to_str_lossy().into_owned()),
+            EntryType::Raw => Value::Raw(NtString(value.data.v_raw).as_bytes().to_owned()),
+
+            // We have to write this out 3 times because bindgen generates 3 types here.
+            // We could use a macro but *ehhhh*
+            EntryType::BooleanArray => {
+                let bool_arr = value.data.arr_boolean;
+                let slice = raw_slice(bool_arr.arr as *const sys::NT_Bool, bool_arr.size);
+                Value::BoolArray(slice.iter().map(|&val| val != 0).collect())
+            }
 
-        fn double_array(mut val: Vec<f64>, last_change: u64) -> NT_Value {
-            let arr_double = ValueUnionDoubleArr { size: val.len(), arr: val.as_mut_ptr() };
-            value!(NT_Type_NT_DOUBLE_ARRAY, last_change, arr_double: arr_double)
-        }
+            EntryType::DoubleArray => {
+                let double_arr = value.data.arr_double;
+                let slice = raw_slice(double_arr.arr as *const f64, double_arr.size);
+                Value::DoubleArray(slice.iter().map(|&val| val as f64).collect())
+            }
 
-        match self {
-            Value::Bool(val) => value!(NT_Type_NT_BOOLEAN, last_change, v_boolean: val as NT_Bool),
-            Value::BoolArray(val) => bool_array(val, last_change),
-            Value::Double(val) => value!(NT_Type_NT_DOUBLE, last_change, v_double: val),
-            Value::DoubleArray(val) => double_array(val, last_change),
-            Value::String(val) => value!(NT_Type_NT_STRING, last_change, v_string: to_nt_string(val)),
-            Value::StringArray(val) => string_array(val, last_change),
-            Value::Raw(val) => value!(NT_Type_NT_RAW, last_change, v_raw: to_nt_string(val))
-        }
+            EntryType::StringArray => {
+                let string_arr = value.data.arr_string;
+                let slice = raw_slice(string_arr.arr as *const sys::NT_String, string_arr.size);
+                Value::StringArray(slice.iter().map(|&val| NtString(val).to_str_lossy().into_owned()).collect())
+            }
+
+            EntryType::Unassigned => return None,
+            EntryType::Rpc => Value::Rpc(NtString(value.data.v_raw).as_bytes().to_owned()),
+        })
     }
 
     map_value!(map_bool, Bool: bool);
@@ -99,6 +176,321 @@ impl_from!(DoubleArray: Vec<f64>);
 impl_from!(StringArray: Vec<String>);
 impl_from!(Raw: Vec<u8>);
 
+/// A value that borrows its contents, for setting entries without allocating. Only types that
+/// ntcore can take without converting them first are included.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BorrowedValue<'a> {
+    Bool(bool),
+    Double(f64),
+    Str(&'a str),
+    BoolSlice(&'a [bool]),
+    DoubleSlice(&'a [f64]),
+    Raw(&'a [u8]),
+}
+
+impl<'a> BorrowedValue<'a> {
+    pub fn entry_type(&self) -> EntryType {
+        match *self {
+            BorrowedValue::Bool(_) => EntryType::Boolean,
+            BorrowedValue::Double(_) => EntryType::Double,
+            BorrowedValue::Str(_) => EntryType::String,
+            BorrowedValue::BoolSlice(_) => EntryType::BooleanArray,
+            BorrowedValue::DoubleSlice(_) => EntryType::DoubleArray,
+            BorrowedValue::Raw(_) => EntryType::Raw,
+        }
+    }
+
+    /// Copy the contents into an owned `Value`.
+    pub fn to_value(&self) -> Value {
+        match *self {
+            BorrowedValue::Bool(val) => Value::Bool(val),
+            BorrowedValue::Double(val) => Value::Double(val),
+            BorrowedValue::Str(val) => Value::String(val.to_owned()),
+            BorrowedValue::BoolSlice(val) => Value::BoolArray(val.to_vec()),
+            BorrowedValue::DoubleSlice(val) => Value::DoubleArray(val.to_vec()),
+            BorrowedValue::Raw(val) => Value::Raw(val.to_vec()),
+        }
+    }
+
+    /// Like `Value::with_nt_value`, but only allocates for boolean arrays too long to convert on
+    /// the stack.
+    #[cfg(feature = "ntcore-sys")]
+    pub(crate) fn with_nt_value<R, F: FnOnce(&NT_Value) -> R>(&self, last_change: u64, func: F) -> R {
+        match *self {
+            BorrowedValue::Bool(val) => func(&value!(NT_Type_NT_BOOLEAN, last_change, v_boolean: val as NT_Bool)),
+            BorrowedValue::Double(val) => func(&value!(NT_Type_NT_DOUBLE, last_change, v_double: val)),
+            BorrowedValue::Str(val) => func(&value!(NT_Type_NT_STRING, last_change, v_string: to_nt_string(val))),
+            BorrowedValue::BoolSlice(val) => {
+                // ntcore wants its own bool representation, so short arrays are converted on the stack.
+                let mut stack: [NT_Bool; 64] = [0; 64];
+                let mut heap;
+                let c_arr = if val.len() <= stack.len() {
+                    &mut stack[..val.len()]
+                } else {
+                    heap = vec![0; val.len()];
+                    &mut heap[..]
+                };
+                for (c_val, &val) in c_arr.iter_mut().zip(val) { *c_val = val as NT_Bool; }
+                func(&value!(NT_Type_NT_BOOLEAN_ARRAY, last_change, arr_boolean: ValueUnionBoolArr {
+                    size: c_arr.len(), arr: c_arr.as_mut_ptr()
+                }))
+            }
+            BorrowedValue::DoubleSlice(val) => {
+                func(&value!(NT_Type_NT_DOUBLE_ARRAY, last_change, arr_double: ValueUnionDoubleArr {
+                    size: val.len(), arr: val.as_ptr() as *mut f64
+                }))
+            }
+            BorrowedValue::Raw(val) => func(&value!(NT_Type_NT_RAW, last_change, v_raw: to_nt_string(val))),
+        }
+    }
+}
+
+/// A value read from an entry without copying it into a `Value`, for reading large arrays often
+/// without allocating. ntcore's copy of the value is freed when this is dropped.
+///
+/// Backends other than ntcore hand out an owned `Value` behind the same interface.
+pub struct ValueRef<'a> {
+    inner: ValueRefInner,
+    _entry: PhantomData<&'a ()>,
+}
+
+enum ValueRefInner {
+    #[cfg(feature = "ntcore-sys")]
+    Native(NT_Value),
+    Owned(Value),
+}
+
+impl<'a> ValueRef<'a> {
+    /// Wrap an owned value, for backends that don't have anything to lend out.
+    pub fn owned(value: Value) -> Self {
+        ValueRef { inner: ValueRefInner::Owned(value), _entry: PhantomData }
+    }
+
+    /// Take ownership of a C value, which is disposed when this is dropped. The value must not be
+    /// unassigned.
+    #[cfg(feature = "ntcore-sys")]
+    pub(crate) unsafe fn from_nt_value(value: NT_Value) -> Self {
+        ValueRef { inner: ValueRefInner::Native(value), _entry: PhantomData }
+    }
+
+    pub fn entry_type(&self) -> EntryType {
+        match self.inner {
+            #[cfg(feature = "ntcore-sys")]
+            ValueRefInner::Native(ref value) => EntryType::try_from_raw(value.type_).unwrap_or(EntryType::Unassigned),
+            ValueRefInner::Owned(ref value) => value.entry_type(),
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self.inner {
+            #[cfg(feature = "ntcore-sys")]
+            ValueRefInner::Native(ref value) if value.type_ == sys::NT_Type_NT_BOOLEAN => unsafe { Some(value.data.v_boolean != 0) },
+            ValueRefInner::Owned(Value::Bool(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self.inner {
+            #[cfg(feature = "ntcore-sys")]
+            ValueRefInner::Native(ref value) if value.type_ == sys::NT_Type_NT_DOUBLE => unsafe { Some(value.data.v_double) },
+            ValueRefInner::Owned(Value::Double(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Invalid UTF-8 is replaced, in which case the string is copied.
+    pub fn as_str(&self) -> Option<Cow<'_, str>> {
+        match self.inner {
+            #[cfg(feature = "ntcore-sys")]
+            ValueRefInner::Native(ref value) if value.type_ == sys::NT_Type_NT_STRING => unsafe { Some(NtString(value.data.v_string).to_str_lossy()) },
+            ValueRefInner::Owned(Value::String(ref value)) => Some(Cow::Borrowed(value)),
+            _ => None,
+        }
+    }
+
+    /// The contents of a raw value, or the packed definition of a remote procedure.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self.inner {
+            #[cfg(feature = "ntcore-sys")]
+            ValueRefInner::Native(ref value) if value.type_ == sys::NT_Type_NT_RAW || value.type_ == sys::NT_Type_NT_RPC =>
+                unsafe { Some(NtString(value.data.v_raw).as_bytes()) },
+            ValueRefInner::Owned(Value::Raw(ref value)) | ValueRefInner::Owned(Value::Rpc(ref value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64_slice(&self) -> Option<&[f64]> {
+        match self.inner {
+            #[cfg(feature = "ntcore-sys")]
+            ValueRefInner::Native(ref value) if value.type_ == sys::NT_Type_NT_DOUBLE_ARRAY =>
+                unsafe { Some(raw_slice(value.data.arr_double.arr, value.data.arr_double.size)) },
+            ValueRefInner::Owned(Value::DoubleArray(ref value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Iterate over a boolean array. ntcore stores booleans as integers, so they can't be lent out
+    /// as a slice.
+    pub fn bools(&self) -> Option<Bools<'_>> {
+        match self.inner {
+            #[cfg(feature = "ntcore-sys")]
+            ValueRefInner::Native(ref value) if value.type_ == sys::NT_Type_NT_BOOLEAN_ARRAY =>
+                unsafe { Some(Bools::Native(raw_slice(value.data.arr_boolean.arr, value.data.arr_boolean.size).iter())) },
+            ValueRefInner::Owned(Value::BoolArray(ref value)) => Some(Bools::Owned(value.iter())),
+            _ => None,
+        }
+    }
+
+    /// Iterate over a string array. Like `as_str`, invalid UTF-8 is replaced.
+    pub fn strs(&self) -> Option<Strs<'_>> {
+        match self.inner {
+            #[cfg(feature = "ntcore-sys")]
+            ValueRefInner::Native(ref value) if value.type_ == sys::NT_Type_NT_STRING_ARRAY =>
+                unsafe { Some(Strs::Native(raw_slice(value.data.arr_string.arr, value.data.arr_string.size).iter())) },
+            ValueRefInner::Owned(Value::StringArray(ref value)) => Some(Strs::Owned(value.iter())),
+            _ => None,
+        }
+    }
+
+    /// Copy this into an owned `Value`.
+    pub fn to_owned(&self) -> Value {
+        match self.inner {
+            #[cfg(feature = "ntcore-sys")]
+            ValueRefInner::Native(ref value) => unsafe { Value::from_nt_value(value).expect("value is assigned") },
+            ValueRefInner::Owned(ref value) => value.clone(),
+        }
+    }
+}
+
+impl<'a> fmt::Debug for ValueRef<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("ValueRef").field(&self.to_owned()).finish()
+    }
+}
+
+impl<'a> PartialEq<Value> for ValueRef<'a> {
+    fn eq(&self, other: &Value) -> bool { self.to_owned() == *other }
+}
+
+#[cfg(feature = "ntcore-sys")]
+impl<'a> Drop for ValueRef<'a> {
+    fn drop(&mut self) {
+        if let ValueRefInner::Native(ref mut value) = self.inner {
+            unsafe { sys::NT_DisposeValue(value) }
+        }
+    }
+}
+
+/// ntcore may hand out null pointers for empty arrays, which `slice::from_raw_parts` doesn't allow.
+#[cfg(feature = "ntcore-sys")]
+unsafe fn raw_slice<'a, T>(ptr: *const T, len: usize) -> &'a [T] {
+    if len == 0 { &[] } else { ::std::slice::from_raw_parts(ptr, len) }
+}
+
+/// The elements of a boolean array in a `ValueRef`.
+#[derive(Debug)]
+pub enum Bools<'a> {
+    #[cfg(feature = "ntcore-sys")]
+    #[doc(hidden)]
+    Native(::std::slice::Iter<'a, NT_Bool>),
+    #[doc(hidden)]
+    Owned(::std::slice::Iter<'a, bool>),
+}
+
+impl<'a> Iterator for Bools<'a> {
+    type Item = bool;
+
+    fn next(&mut self) -> Option<bool> {
+        match *self {
+            #[cfg(feature = "ntcore-sys")]
+            Bools::Native(ref mut iter) => iter.next().map(|&val| val != 0),
+            Bools::Owned(ref mut iter) => iter.next().cloned(),
+        }
+    }
+}
+
+/// The elements of a string array in a `ValueRef`.
+#[derive(Debug)]
+pub enum Strs<'a> {
+    #[cfg(feature = "ntcore-sys")]
+    #[doc(hidden)]
+    Native(::std::slice::Iter<'a, NT_String>),
+    #[doc(hidden)]
+    Owned(::std::slice::Iter<'a, String>),
+}
+
+impl<'a> Iterator for Strs<'a> {
+    type Item = Cow<'a, str>;
+
+    fn next(&mut self) -> Option<Cow<'a, str>> {
+        match *self {
+            #[cfg(feature = "ntcore-sys")]
+            Strs::Native(ref mut iter) => iter.next().map(|&val| unsafe { NtString(val).to_str_lossy() }),
+            Strs::Owned(ref mut iter) => iter.next().map(|val| Cow::Borrowed(val.as_str())),
+        }
+    }
+}
+
+/// A snapshot of an entry's metadata.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntryInfo {
+    pub entry: Entry,
+    pub name: String,
+    pub entry_type: EntryType,
+    pub flags: EntryFlags,
+    pub last_change: NetworkTime,
+}
+
+impl EntryInfo {
+    pub(crate) fn new(backend: &Arc<dyn Backend>, info: Info) -> Self {
+        EntryInfo {
+            entry: Entry::new(backend.clone(), info.entry),
+            name: info.name,
+            entry_type: info.entry_type,
+            flags: info.flags,
+            last_change: info.last_change,
+        }
+    }
+}
+
+/// Flags that can be set on an entry. Flags can be combined like so:
+/// ```rs
+/// let flags = EntryFlags::NONE | EntryFlags::PERSISTENT;
+/// ```
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct EntryFlags(pub(crate) u32);
+
+impl EntryFlags {
+    pub const NONE: EntryFlags = EntryFlags(0);
+    /// The entry is saved to the server's persistent file, and restored when the server restarts.
+    pub const PERSISTENT: EntryFlags = EntryFlags(0x01);
+
+    pub fn contains(&self, other: EntryFlags) -> bool { self.0 & other.0 == other.0 }
+    pub fn is_persistent(&self) -> bool { self.contains(EntryFlags::PERSISTENT) }
+}
+
+impl ::std::ops::BitOr for EntryFlags {
+    type Output = EntryFlags;
+    fn bitor(self, rhs: EntryFlags) -> EntryFlags {
+        EntryFlags(self.0 | rhs.0)
+    }
+}
+
+impl ::std::ops::BitAnd for EntryFlags {
+    type Output = EntryFlags;
+    fn bitand(self, rhs: EntryFlags) -> EntryFlags {
+        EntryFlags(self.0 & rhs.0)
+    }
+}
+
+impl ::std::ops::Not for EntryFlags {
+    type Output = EntryFlags;
+    fn not(self) -> EntryFlags {
+        EntryFlags(!self.0)
+    }
+}
+
 /// Struct used for filtering entry types. You can create the mask like so:
 /// ```rs
 /// let mask = EntryMask::new(EntryType::Boolean) | EntryType::Double | EntryType::String;
@@ -120,129 +512,290 @@ impl ::std::ops::BitOr<EntryType> for EntryMask {
     }
 }
 
+/// The type of an entry. The discriminants are the same as ntcore's `NT_Type`.
 #[repr(u32)]
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub enum EntryType {
-    Boolean = sys::NT_Type_NT_BOOLEAN,
-    BooleanArray = sys::NT_Type_NT_BOOLEAN_ARRAY,
-    Double = sys::NT_Type_NT_DOUBLE,
-    DoubleArray = sys::NT_Type_NT_DOUBLE_ARRAY,
-    Raw = sys::NT_Type_NT_RAW,
-    Rpc = sys::NT_Type_NT_RPC,
-    String = sys::NT_Type_NT_STRING,
-    StringArray = sys::NT_Type_NT_STRING_ARRAY,
+    Boolean = 0x01,
+    BooleanArray = 0x10,
+    Double = 0x02,
+    DoubleArray = 0x20,
+    Raw = 0x08,
+    Rpc = 0x80,
+    String = 0x04,
+    StringArray = 0x40,
     /// Entry does not exist
-    Unassigned = sys::NT_Type_NT_UNASSIGNED,
+    Unassigned = 0x00,
 }
 
-impl From<NT_Type> for EntryType {
-    /// Panics if the type is not one of the values of EntryType
-    fn from(ty: NT_Type) -> Self {
-        match ty {
-            sys::NT_Type_NT_BOOLEAN => EntryType::Boolean,
-            sys::NT_Type_NT_BOOLEAN_ARRAY => EntryType::BooleanArray,
-            sys::NT_Type_NT_DOUBLE => EntryType::Double,
-            sys::NT_Type_NT_DOUBLE_ARRAY => EntryType::DoubleArray,
-            sys::NT_Type_NT_RAW => EntryType::Raw,
-            sys::NT_Type_NT_RPC => EntryType::Rpc,
-            sys::NT_Type_NT_STRING => EntryType::String,
-            sys::NT_Type_NT_STRING_ARRAY => EntryType::StringArray,
-            sys::NT_Type_NT_UNASSIGNED => EntryType::Unassigned,
-            ty => panic!("Invalid NT_Type: {}", ty),
-        }
+impl EntryType {
+    /// Convert a raw ntcore type, failing if it isn't one of the values of `EntryType`.
+    pub fn try_from_raw(ty: u32) -> Result<Self> {
+        Ok(match ty {
+            0x01 => EntryType::Boolean,
+            0x10 => EntryType::BooleanArray,
+            0x02 => EntryType::Double,
+            0x20 => EntryType::DoubleArray,
+            0x08 => EntryType::Raw,
+            0x80 => EntryType::Rpc,
+            0x04 => EntryType::String,
+            0x40 => EntryType::StringArray,
+            0x00 => EntryType::Unassigned,
+            ty => return Err(Error::UnknownType(ty)),
+        })
     }
 }
 
-/// A handle to a possibly existant network table entry. 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+impl From<u32> for EntryType {
+    /// Panics if the type is not one of the values of EntryType. Use `EntryType::try_from_raw` to
+    /// handle unknown types.
+    fn from(ty: u32) -> Self {
+        EntryType::try_from_raw(ty).expect("Invalid NT_Type")
+    }
+}
+
+/// A handle to a possibly existant network table entry. Entries keep the instance they came from
+/// alive.
+#[derive(Clone)]
 pub struct Entry {
-    pub(crate) handle: NT_Entry,
+    pub(crate) backend: Arc<dyn Backend>,
+    pub(crate) handle: Handle,
+}
+
+impl fmt::Debug for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Entry").field("handle", &self.handle).finish()
+    }
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Entry) -> bool {
+        self.handle == other.handle && Arc::ptr_eq(&self.backend, &other.backend)
+    }
+}
+
+impl Eq for Entry {}
+
+impl Hash for Entry {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (&*self.backend as *const dyn Backend as *const u8 as usize).hash(state);
+        self.handle.hash(state);
+    }
 }
 
 impl Entry {
-    pub(crate) fn new(handle: NT_Entry) -> Self { Entry { handle } }
+    pub(crate) fn new(backend: Arc<dyn Backend>, handle: Handle) -> Self { Entry { backend, handle } }
+
+    /// The ntcore handle of this entry. Fails with `Error::Unsupported` if the entry doesn't
+    /// belong to an ntcore instance.
+    #[cfg(feature = "ntcore-sys")]
+    pub(crate) fn native_handle(&self) -> Result<NT_Entry> {
+        if self.backend.as_any().is::<NativeBackend>() { Ok(self.handle) } else { Err(Error::Unsupported) }
+    }
 
+    /// Panics if the backend reports a type this crate doesn't know about. Use `try_entry_type` to
+    /// handle that case.
     pub fn entry_type(&self) -> EntryType {
-        unsafe { sys::NT_GetEntryType(self.handle).into() }
+        self.try_entry_type().expect("Invalid NT_Type")
+    }
+
+    pub fn try_entry_type(&self) -> Result<EntryType> {
+        self.backend.entry_type(self.handle)
     }
 
     pub fn exists(&self) -> bool {
-        self.entry_type() != EntryType::Unassigned
+        // Types we don't know about still mean the entry exists.
+        self.try_entry_type() != Ok(EntryType::Unassigned)
     }
 
     pub fn last_changed(&self) -> NetworkTime {
-        unsafe { NetworkTime(sys::NT_GetEntryLastChange(self.handle)) }
+        self.backend.entry_last_change(self.handle)
+    }
+
+    pub fn flags(&self) -> EntryFlags {
+        self.backend.entry_flags(self.handle)
+    }
+
+    /// Replace all the flags of this entry with `flags`.
+    pub fn set_flags(&self, flags: EntryFlags) {
+        self.backend.set_entry_flags(self.handle, flags)
+    }
+
+    /// Clear only the given flags, leaving any others set.
+    pub fn clear_flags(&self, flags: EntryFlags) {
+        self.set_flags(self.flags() & !flags)
+    }
+
+    pub fn is_persistent(&self) -> bool {
+        self.flags().is_persistent()
+    }
+
+    /// Make this entry persistent, so it is saved by the server and survives restarts.
+    pub fn set_persistent(&self) {
+        self.set_flags(self.flags() | EntryFlags::PERSISTENT)
+    }
+
+    pub fn clear_persistent(&self) {
+        self.clear_flags(EntryFlags::PERSISTENT)
     }
 
     pub fn name_bytes(&self) -> Vec<u8> {
-        let mut len = 0;
-        let char_ptr = unsafe { sys::NT_GetEntryName(self.handle, &mut len) };
-        unsafe { ::std::slice::from_raw_parts(char_ptr, len).iter().map(|&ch| ch as u8).collect() }
+        self.backend.entry_name(self.handle)
     }
 
     pub fn name(&self) -> Option<String> {
         ::std::string::String::from_utf8(self.name_bytes()).ok()
     }
 
-    pub fn set<V: Into<Value>>(&self, value: V) -> Result<(), EntryType> {
-        if unsafe { sys::NT_SetEntryValue(self.handle, &value.into().to_nt_value(0)) != 0 } {
-            Ok(())
-        } else {
-            // SetEntryValue returns false if there was a type mismatch, so we return what the type
-            // of the current entry is.
-            Err(EntryType::from(unsafe { sys::NT_GetEntryType(self.handle) }))
+    /// Set the value of this entry. Setting `None` deletes it.
+    pub fn set<V: IntoValue>(&self, value: V) -> Result<()> {
+        match value.into_value() {
+            Some(value) => self.backend.set_entry_value(self.handle, &value),
+            None => {
+                self.delete();
+                Ok(())
+            }
+        }
+    }
+
+    /// Set the value of this entry from borrowed data. Unlike `set`, this doesn't allocate on
+    /// ntcore, except for long boolean arrays.
+    pub fn set_borrowed(&self, value: BorrowedValue) -> Result<()> {
+        self.backend.set_entry_value_borrowed(self.handle, value)
+    }
+
+    pub fn set_bool(&self, value: bool) -> Result<()> { self.set_borrowed(BorrowedValue::Bool(value)) }
+    pub fn set_double(&self, value: f64) -> Result<()> { self.set_borrowed(BorrowedValue::Double(value)) }
+    pub fn set_str(&self, value: &str) -> Result<()> { self.set_borrowed(BorrowedValue::Str(value)) }
+    pub fn set_bool_slice(&self, value: &[bool]) -> Result<()> { self.set_borrowed(BorrowedValue::BoolSlice(value)) }
+    pub fn set_double_slice(&self, value: &[f64]) -> Result<()> { self.set_borrowed(BorrowedValue::DoubleSlice(value)) }
+    pub fn set_raw(&self, value: &[u8]) -> Result<()> { self.set_borrowed(BorrowedValue::Raw(value)) }
+
+    /// Set the value of this entry only if it doesn't have one yet. Returns `Ok(true)` if the
+    /// default was applied, `Ok(false)` if the entry already holds a value of the same type, and a
+    /// type mismatch error if it holds a value of a different type. A `None` default is never
+    /// applied.
+    pub fn set_default<V: IntoValue>(&self, value: V) -> Result<bool> {
+        match value.into_value() {
+            Some(value) => self.backend.set_default_entry_value(self.handle, &value),
+            None => Ok(false),
         }
     }
 
+    set_default_value!(set_default_bool: bool);
+    set_default_value!(set_default_double: f64);
+    set_default_value!(set_default_string: String);
+    set_default_value!(set_default_bool_array: Vec<bool>);
+    set_default_value!(set_default_double_array: Vec<f64>);
+    set_default_value!(set_default_string_array: Vec<String>);
+    set_default_value!(set_default_raw: Vec<u8>);
+
+    /// Replace the value of this entry with the result of `func`. Returns false if the entry
+    /// doesn't exist or the new value couldn't be set.
     pub fn edit<F: Fn(Value) -> Value>(&self, func: F) -> bool {
-        self.value().map(func).map(|val| self.set(val)).is_some()
+        self.value().map(func).is_some_and(|val| self.set(val).is_ok())
+    }
+
+    /// View this entry as one that only holds `T`s.
+    pub fn typed<T: FromValue + IntoValue>(&self) -> TypedEntry<T> {
+        TypedEntry::new(self.clone())
     }
 
     /// Get the value of this entry, if this entry does point to something.
     pub fn value(&self) -> Option<Value> {
-        if !self.exists() { return None; }
-
-        unsafe {
-            let mut value = ::std::mem::zeroed();
-            sys::NT_GetEntryValue(self.handle, &mut value);
-
-            // No need to check if value's type is unassigned because we did that check up
-            // at the top of the function.
-            let val = match value.type_.into() {
-                EntryType::Boolean => Value::Bool(value.data.v_boolean != 0),
-                EntryType::Double => Value::Double(value.data.v_double as f64),
-                EntryType::String => Value::String(NtString(value.data.v_string).as_str().to_owned()),
-                EntryType::Raw => Value::Raw(NtString(value.data.v_string).as_bytes().to_owned()),
-
-                // We have to write this out 3 times because bindgen generates 3 types here.
-                // We could use a macro but *ehhhh*
-                EntryType::BooleanArray => {
-                    let bool_arr = value.data.arr_boolean;
-                    let slice = ::std::slice::from_raw_parts(bool_arr.arr as *mut sys::NT_Bool, bool_arr.size);
-                    Value::BoolArray(slice.iter().map(|&val| val != 0).collect())
-                }
-
-                EntryType::DoubleArray => {
-                    let bool_arr = value.data.arr_double;
-                    let slice = ::std::slice::from_raw_parts(bool_arr.arr as *mut f64, bool_arr.size);
-                    Value::DoubleArray(slice.iter().map(|&val| val as f64).collect())
-                }
-
-                EntryType::StringArray => {
-                    let bool_arr = value.data.arr_boolean;
-                    let slice = ::std::slice::from_raw_parts(bool_arr.arr as *mut sys::NT_String, bool_arr.size);
-                    Value::StringArray(slice.iter().map(|&val| NtString(val).as_str().to_owned()).collect())
-                }
-
-                // unassigned case
-                EntryType::Unassigned => unreachable!(),
-                EntryType::Rpc => unimplemented!(),
-            };
-
-            // We've copied all the data from the union in one way or another; we can dispose of it now
-            sys::NT_DisposeValue(&mut value);
-
-            Some(val)
-        }
+        self.backend.entry_value(self.handle)
+    }
+
+    /// Get this entry's name, type, flags and last change time all at once. Returns `None` if the
+    /// entry doesn't exist.
+    pub fn info(&self) -> Option<EntryInfo> {
+        self.backend.entry_info(self.handle).map(|info| EntryInfo::new(&self.backend, info))
+    }
+
+    /// Get the value of this entry without copying it, if this entry does point to something.
+    /// Prefer this over `value` for reading large arrays often.
+    pub fn value_ref(&self) -> Option<ValueRef<'_>> {
+        self.backend.entry_value_ref(self.handle)
+    }
+
+    /// Get the value of this entry as a `T`. Returns `Ok(None)` if the entry doesn't exist, and an
+    /// error if it holds something that can't be converted to a `T`.
+    pub fn value_as<T: FromValue>(&self) -> Result<Option<T>> {
+        self.value().map(T::from_value).transpose()
+    }
+
+    /// Delete this entry. The handle stays usable, and setting a value creates the entry again.
+    pub fn delete(&self) {
+        self.backend.delete_entry(self.handle)
+    }
+
+    /// Register a callback that is run on the backend's notifier thread whenever this entry
+    /// changes in a way matching `flags`. The listener is removed when the returned guard is
+    /// dropped.
+    pub fn add_listener<F>(&self, flags: NotifyFlags, callback: F) -> EntryListener
+        where F: Fn(EntryNotification) + Send + 'static
+    {
+        EntryListener::for_entry(self, flags, callback)
+    }
+
+    /// A stream of this entry's value, starting with the current one if it has one, then every
+    /// change to it, local or remote.
+    #[cfg(feature = "async")]
+    pub fn changes(&self) -> EntryChanges {
+        self.changes_with(NotifyFlags::IMMEDIATE | NotifyFlags::NEW | NotifyFlags::UPDATE | NotifyFlags::LOCAL)
+    }
+
+    /// A stream of the changes to this entry that match `flags`.
+    #[cfg(feature = "async")]
+    pub fn changes_with(&self, flags: NotifyFlags) -> EntryChanges {
+        EntryChanges::new(|callback| self.add_listener(flags, callback))
+    }
+
+    /// Turn this entry into a remote procedure with the given packed definition. `handler` is run
+    /// on ntcore's RPC thread for every call, and its return value is sent back to the caller.
+    ///
+    /// ntcore has no way to unregister a procedure, so the handler lives as long as the program.
+    /// Fails with `Error::Unsupported` if the entry doesn't belong to an ntcore instance.
+    #[cfg(feature = "ntcore-sys")]
+    pub fn create_rpc<F>(&self, definition: &[u8], handler: F) -> Result<()>
+        where F: Fn(&RpcAnswer) -> Vec<u8> + Send + 'static
+    {
+        rpc::create_rpc(self, definition, handler)
+    }
+
+    /// Turn this entry into a remote procedure whose calls are queued onto `poller` instead of
+    /// being handled by a callback. Fails with `Error::Unsupported` if the entry doesn't belong
+    /// to an ntcore instance.
+    #[cfg(feature = "ntcore-sys")]
+    pub fn create_polled_rpc(&self, definition: &[u8], poller: &RpcCallPoller) -> Result<()> {
+        rpc::create_polled_rpc(self.native_handle()?, definition, poller);
+        Ok(())
+    }
+
+    /// Call the remote procedure this entry refers to with packed parameters. The result is
+    /// retrieved through the returned `RpcCall`. Fails with `Error::Unsupported` if the entry
+    /// doesn't belong to an ntcore instance.
+    #[cfg(feature = "ntcore-sys")]
+    pub fn call_rpc(&self, params: &[u8]) -> Result<RpcCall> {
+        Ok(RpcCall::new(self.native_handle()?, params))
+    }
+
+    /// Turn this entry into a remote procedure with a typed definition. Parameters are decoded
+    /// against the definition before `handler` is run, and the results it returns are checked
+    /// against it. Calls that don't match the definition, or that `handler` fails, are answered
+    /// with the error, which `RpcDefinition::unpack_results` hands back to the caller.
+    #[cfg(feature = "ntcore-sys")]
+    pub fn create_typed_rpc<F>(&self, definition: RpcDefinition, handler: F) -> Result<(), RpcError>
+        where F: Fn(RpcArgs) -> RpcResult + Send + 'static
+    {
+        rpc::create_typed_rpc(self, definition, handler)
+    }
+
+    /// Call a remote procedure with typed parameters. The parameters are checked against
+    /// `definition` before anything is sent, and the result can be decoded with
+    /// `RpcDefinition::unpack_results`.
+    #[cfg(feature = "ntcore-sys")]
+    pub fn call_typed_rpc(&self, definition: &RpcDefinition, args: &[Value]) -> Result<RpcCall, RpcError> {
+        Ok(self.call_rpc(&definition.pack_params(args)?)?)
     }
 }
@@ -0,0 +1,98 @@
+use std::error;
+use std::ffi::NulError;
+use std::fmt;
+use std::io;
+use entry::{EntryType, Value};
+
+/// Errors that can happen when talking to ntcore.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// A value couldn't be set because the entry already holds a value of a different type.
+    TypeMismatch { expected: EntryType, actual: EntryType },
+    /// A string passed to ntcore as a C string contained a NUL byte.
+    InteriorNul(String),
+    /// An address couldn't be parsed.
+    InvalidAddress(String),
+    /// ntcore reported a value type this crate doesn't know about.
+    UnknownType(u32),
+    /// ntcore failed to allocate memory.
+    AllocationFailure,
+    /// The instance was stopped or could not be created.
+    InstanceStopped,
+    /// Saving or loading a persistent file failed. Holds the message from ntcore.
+    Persistence(String),
+    /// Something that was waited for didn't happen in time. Holds what was being waited for.
+    Timeout(String),
+    /// A value was of the right type, but couldn't be converted to the type that was asked for.
+    InvalidConversion { value: Value, target: &'static str },
+    /// An entry that had to have a value didn't. Holds the name of the entry.
+    MissingEntry(String),
+    /// The instance's backend can't do what was asked, like starting a server on a client that
+    /// isn't backed by ntcore.
+    Unsupported,
+    /// An I/O operation failed, like binding a socket. Holds the kind and message of the
+    /// `io::Error`, which itself can't be cloned or compared.
+    Io { kind: io::ErrorKind, message: String },
+}
+
+pub type Result<T, E = Error> = ::std::result::Result<T, E>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::TypeMismatch { expected, actual } =>
+                write!(f, "entry holds a value of type {:?}, but a {:?} was given", expected, actual),
+            Error::InteriorNul(ref string) => write!(f, "{:?} contains a NUL byte", string),
+            Error::InvalidAddress(ref addr) => write!(f, "{:?} is not a valid address", addr),
+            Error::UnknownType(ty) => write!(f, "unknown entry type {}", ty),
+            Error::AllocationFailure => write!(f, "ntcore failed to allocate memory"),
+            Error::InstanceStopped => write!(f, "the instance is not running"),
+            Error::Persistence(ref msg) => write!(f, "persistent file error: {}", msg),
+            Error::Timeout(ref what) => write!(f, "timed out waiting for {}", what),
+            Error::InvalidConversion { ref value, target } => write!(f, "{:?} can't be converted to {}", value, target),
+            Error::MissingEntry(ref name) => write!(f, "{:?} has no value", name),
+            Error::Unsupported => write!(f, "not supported by this instance's backend"),
+            Error::Io { ref message, .. } => write!(f, "I/O error: {}", message),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<NulError> for Error {
+    fn from(err: NulError) -> Self {
+        Error::InteriorNul(String::from_utf8_lossy(&err.into_vec()).into_owned())
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io { kind: err.kind(), message: err.to_string() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+    use super::*;
+
+    #[test]
+    fn nul_errors_keep_the_string() {
+        let err = Error::from(CString::new("bad\0name").unwrap_err());
+        assert_eq!(err, Error::InteriorNul("bad\0name".to_owned()));
+        assert_eq!(err.to_string(), "\"bad\\0name\" contains a NUL byte");
+    }
+
+    #[test]
+    fn type_mismatches_name_both_types() {
+        let err = Error::TypeMismatch { expected: EntryType::Double, actual: EntryType::String };
+        assert_eq!(err.to_string(), "entry holds a value of type Double, but a String was given");
+    }
+
+    #[test]
+    fn io_errors_keep_their_kind() {
+        let err = Error::from(io::Error::new(io::ErrorKind::AddrInUse, "port taken"));
+        assert_eq!(err, Error::Io { kind: io::ErrorKind::AddrInUse, message: "port taken".to_owned() });
+        assert_eq!(err.to_string(), "I/O error: port taken");
+    }
+}
@@ -1,137 +1,325 @@
 use table::NetworkTable;
 use entry::EntryMask;
-use std::os::raw::*;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+#[cfg(feature = "ntcore-sys")]
 use std::net::Ipv4Addr;
-use std::ffi::CString;
+#[cfg(feature = "ntcore-sys")]
+use std::os::raw::{c_char, c_void};
+#[cfg(feature = "ntcore-sys")]
+use std::cell::Cell;
+#[cfg(feature = "ntcore-sys")]
+use std::panic::{self, AssertUnwindSafe};
+#[cfg(feature = "ntcore-sys")]
+use std::ptr;
+#[cfg(feature = "ntcore-sys")]
+use std::ffi::{CStr, CString};
+#[cfg(feature = "ntcore-sys")]
 use sys::{self, NT_Inst};
+use ::backend::{Backend, MemoryBackend};
+#[cfg(feature = "ntcore-sys")]
+use ::backend::NativeBackend;
 use ::connection::*;
-use ::entry::Entry;
+#[cfg(feature = "async")]
+use ::stream::{ConnectionEvents, WaitConnected};
+use ::entry::{Entry, EntryInfo};
+use ::listener::{ConnectionListener, EntryListener, EntryNotification, NotifyFlags};
+#[cfg(feature = "ntcore-sys")]
+use ::listener::{ConnectionListenerPoller, EntryListenerPoller};
+#[cfg(feature = "ntcore-sys")]
+use ::rpc::RpcCallPoller;
+#[cfg(feature = "ntcore-sys")]
+use ::Error;
+use ::Result;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct NetworkMode(u32);
 
 impl NetworkMode {
-    pub fn client(&self) -> bool { self.0 & sys::NT_NetworkMode_NT_NET_MODE_CLIENT != 0 }
-    pub fn failure(&self) -> bool { self.0 & sys::NT_NetworkMode_NT_NET_MODE_FAILURE != 0 }
-    pub fn none(&self) -> bool { self.0 & sys::NT_NetworkMode_NT_NET_MODE_NONE != 0 }
-    pub fn server(&self) -> bool { self.0 & sys::NT_NetworkMode_NT_NET_MODE_SERVER != 0 }
-    pub fn starting(&self) -> bool { self.0 & sys::NT_NetworkMode_NT_NET_MODE_STARTING != 0 }
+    pub fn client(&self) -> bool { self.0 & 0x02 != 0 }
+    pub fn failure(&self) -> bool { self.0 & 0x08 != 0 }
+    pub fn none(&self) -> bool { self.0 == 0 }
+    pub fn server(&self) -> bool { self.0 & 0x01 != 0 }
+    pub fn starting(&self) -> bool { self.0 & 0x04 != 0 }
 }
 
-#[derive(Debug, Eq, PartialEq, Hash)]
+/// A line of a persistent file that couldn't be loaded. ntcore skips these lines and carries on
+/// with the rest of the file.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct LoadWarning {
+    pub line: usize,
+    pub message: String,
+}
+
+#[cfg(feature = "ntcore-sys")]
+thread_local! {
+    // ntcore's warning callback doesn't take a user pointer, but it is called synchronously on the
+    // loading thread, so this points at the callback of the load in progress on this thread. It
+    // is a `&mut dyn FnMut(LoadWarning)`, behind a thin pointer.
+    static LOAD_WARNING_CALLBACK: Cell<*mut c_void> = Cell::new(ptr::null_mut());
+}
+
+#[cfg(feature = "ntcore-sys")]
+unsafe extern "C" fn forward_load_warning(line: usize, msg: *const c_char) {
+    let callback = LOAD_WARNING_CALLBACK.with(Cell::get) as *mut &mut dyn FnMut(LoadWarning);
+    if callback.is_null() { return; }
+    // Unwinding into C is UB, so a panicking callback has to stop here.
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+        let message = CStr::from_ptr(msg).to_string_lossy().into_owned();
+        (*callback)(LoadWarning { line, message })
+    }));
+}
+
+#[cfg(feature = "ntcore-sys")]
+fn persistence_result(err: *const c_char) -> Result<()> {
+    if err.is_null() { return Ok(()); }
+    Err(Error::Persistence(unsafe { CStr::from_ptr(err) }.to_string_lossy().into_owned()))
+}
+
+/// Run `load`, passing every warning it reports to `warn` as it happens, and collect them.
+#[cfg(feature = "ntcore-sys")]
+fn collect_load_warnings<W, F>(mut warn: W, load: F) -> Result<Vec<LoadWarning>>
+    where W: FnMut(&LoadWarning), F: FnOnce() -> *const c_char
+{
+    let mut warnings = Vec::new();
+    let err = {
+        let mut collect = |warning: LoadWarning| {
+            warn(&warning);
+            warnings.push(warning);
+        };
+        let mut callback: &mut dyn FnMut(LoadWarning) = &mut collect;
+        let data = &mut callback as *mut &mut dyn FnMut(LoadWarning) as *mut c_void;
+        let previous = LOAD_WARNING_CALLBACK.with(|current| current.replace(data));
+        let err = load();
+        LOAD_WARNING_CALLBACK.with(|current| current.set(previous));
+        err
+    };
+    persistence_result(err).map(|_| warnings)
+}
+
+/// A NetworkTables node. Everything an instance does goes through its `Backend`, which is ntcore
+/// unless the instance was made with `Instance::from_backend`.
+#[derive(Clone, Debug)]
 pub struct Instance {
-    handle: NT_Inst,
-    server_mode: bool,
+    backend: Arc<dyn Backend>,
 }
 
+impl PartialEq for Instance {
+    fn eq(&self, other: &Instance) -> bool {
+        Arc::ptr_eq(&self.backend, &other.backend)
+    }
+}
+
+impl Eq for Instance {}
+
+impl Hash for Instance {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (&*self.backend as *const dyn Backend as *const u8 as usize).hash(state);
+    }
+}
+
+#[cfg(feature = "ntcore-sys")]
 lazy_static! {
-    static ref DEFAULT_INSTANCE: Instance = {
-        Instance { handle: unsafe { sys::NT_GetDefaultInstance() }, server_mode: true }
-    };
+    static ref DEFAULT_INSTANCE: Instance = Instance::from_backend(Arc::new(NativeBackend::default_instance()));
 }
 
 impl Instance {
-    fn create_instance(server_mode: bool) -> Self {
-        let handle = unsafe { sys::NT_CreateInstance() };
-        Instance { handle, server_mode }
+    /// Make an instance that runs on `backend`. The backend should already be started.
+    pub fn from_backend(backend: Arc<dyn Backend>) -> Instance {
+        Instance { backend }
+    }
+
+    pub fn backend(&self) -> &Arc<dyn Backend> { &self.backend }
+
+    /// Make an instance that keeps its entries in memory and never touches the network, for unit
+    /// testing robot code. See `backend::memory` for how it differs from ntcore.
+    pub fn in_memory() -> Instance {
+        Instance::from_backend(Arc::new(MemoryBackend::new()))
+    }
+
+    /// The in-memory backend of this instance, for inspecting what was set. `None` if the instance
+    /// wasn't made with `in_memory`.
+    pub fn memory_backend(&self) -> Option<&MemoryBackend> {
+        self.backend.as_any().downcast_ref::<MemoryBackend>()
+    }
+
+    #[cfg(feature = "ntcore-sys")]
+    fn try_create_instance() -> Result<Self> {
+        Ok(Instance::from_backend(Arc::new(NativeBackend::new()?)))
+    }
+
+    /// The ntcore handle of this instance. Fails with `Error::Unsupported` if the instance doesn't
+    /// run on ntcore.
+    #[cfg(feature = "ntcore-sys")]
+    fn native_handle(&self) -> Result<NT_Inst> {
+        self.backend.as_any().downcast_ref::<NativeBackend>()
+            .map(NativeBackend::handle)
+            .ok_or(Error::Unsupported)
     }
 
     /// Get a reference to the default instance
+    #[cfg(feature = "ntcore-sys")]
     pub fn default_instance() -> &'static Instance {
         &*DEFAULT_INSTANCE
     }
 
     pub fn set_network_identity(&self, name: &str) {
-        unsafe { sys::NT_SetNetworkIdentity(self.handle, name.as_ptr() as *const c_char, name.len()) }
+        self.backend.set_network_identity(name)
     }
     // fn get_network_mode(&self) -> NetworkMode { unimplemented!() }
 
+    /// Panics if the instance can't be created or `persist_filename` contains a NUL byte. Use
+    /// `try_start_server` to handle those cases.
+    #[cfg(feature = "ntcore-sys")]
     pub fn start_server(persist_filename: String, listen_address: Ipv4Addr, port: u32) -> Instance {
-        let inst = Instance::create_instance(true);
-        let c_string = CString::new(persist_filename).unwrap(); // TODO
-        // IP address should never have a null byte in the middle
-        let addr_name = CString::new(listen_address.to_string()).unwrap();
-        unsafe { sys::NT_StartServer(inst.handle, c_string.as_ptr(), addr_name.as_ptr(), port as c_uint) }
-        inst
-    }
-    
+        Instance::try_start_server(persist_filename, listen_address, port).expect("failed to start server")
+    }
+
+    #[cfg(feature = "ntcore-sys")]
+    pub fn try_start_server(persist_filename: String, listen_address: Ipv4Addr, port: u32) -> Result<Instance> {
+        let inst = Instance::try_create_instance()?;
+        inst.backend.start_server(&persist_filename, &listen_address.to_string(), port)?;
+        Ok(inst)
+    }
+
+    /// Panics if the instance can't be created. Use `try_start_client_none` to handle that case.
+    #[cfg(feature = "ntcore-sys")]
     pub fn start_client_none() -> Instance {
-        let inst = Instance::create_instance(false);
-        unsafe { sys::NT_StartClientNone(inst.handle) }
-        inst
+        Instance::try_start_client_none().expect("failed to start client")
+    }
+
+    #[cfg(feature = "ntcore-sys")]
+    pub fn try_start_client_none() -> Result<Instance> {
+        let inst = Instance::try_create_instance()?;
+        inst.backend.start_client_none();
+        Ok(inst)
     }
-    
+
+    /// Panics if the instance can't be created. Use `try_start_client` to handle that case.
+    #[cfg(feature = "ntcore-sys")]
     pub fn start_client(server_ip: Ipv4Addr, port: u32) -> Instance {
-        Instance::start_client_multi(vec![(&server_ip.to_string(), port)])
+        Instance::try_start_client(server_ip, port).expect("failed to start client")
+    }
+
+    #[cfg(feature = "ntcore-sys")]
+    pub fn try_start_client(server_ip: Ipv4Addr, port: u32) -> Result<Instance> {
+        Instance::try_start_client_multi(vec![(&server_ip.to_string(), port)])
     }
-    
+
+    /// Panics if the instance can't be created or a server name contains a NUL byte. Use
+    /// `try_start_client_multi` to handle those cases.
+    #[cfg(feature = "ntcore-sys")]
     pub fn start_client_multi(servers: Vec<(&str, u32)>) -> Instance {
-        let (mut ips, ports): (Vec<_>, Vec<_>) = servers.into_iter()
-            .map(|(ip, port)| (ip.as_ptr() as *const c_char, port as c_uint))
-            .unzip();
-        let inst = Instance::create_instance(false);
-        unsafe { sys::NT_StartClientMulti(inst.handle, ips.len(), ips.as_mut_slice().as_mut_ptr(), ports.as_slice().as_ptr()); }
-        inst
-    }
-    
+        Instance::try_start_client_multi(servers).expect("failed to start client")
+    }
+
+    #[cfg(feature = "ntcore-sys")]
+    pub fn try_start_client_multi(servers: Vec<(&str, u32)>) -> Result<Instance> {
+        let inst = Instance::try_create_instance()?;
+        inst.backend.start_client(&servers)?;
+        Ok(inst)
+    }
+
+    /// Panics if the instance can't be created. Use `try_start_client_team` to handle that case.
+    #[cfg(feature = "ntcore-sys")]
     pub fn start_client_team(team: u32, port: u32) -> Instance {
-        let inst = Instance::create_instance(false);
-        unsafe { sys::NT_StartClientTeam(inst.handle, team as c_uint, port as c_uint) }
-        inst
+        Instance::try_start_client_team(team, port).expect("failed to start client")
+    }
 
+    #[cfg(feature = "ntcore-sys")]
+    pub fn try_start_client_team(team: u32, port: u32) -> Result<Instance> {
+        let inst = Instance::try_create_instance()?;
+        inst.backend.start_client_team(team, port);
+        Ok(inst)
     }
 
+    /// Panics if `server_name` contains a NUL byte. Use `try_set_server` to handle that case.
     pub fn set_server(&self, server_name: String, port: u32) {
-        let c_string = CString::new(server_name).unwrap(); // TODO
-        unsafe { sys::NT_SetServer(self.handle, c_string.as_ptr(), port as c_uint) }
+        self.try_set_server(server_name, port).expect("invalid server name")
     }
 
-    // fn set_server_multi(&self, servers: Vec<(&str, u32)>) {
-    //     unsafe { sys::NT_SetServerMulti }
-    // }
+    pub fn try_set_server(&self, server_name: String, port: u32) -> Result<()> {
+        self.backend.set_server(&[(&server_name, port)])
+    }
+
+    /// Set the servers to try in turn when connecting as a client.
+    pub fn set_server_multi(&self, servers: Vec<(&str, u32)>) -> Result<()> {
+        self.backend.set_server(&servers)
+    }
 
     pub fn set_server_team(&self, team: u32, port: u32) {
-        unsafe { sys::NT_SetServerTeam(self.handle, team as c_uint, port as c_uint) }
+        self.backend.set_server_team(team, port)
     }
 
     // fn start_DS_client(&self, port: u32) {}
     // fn stop_DS_client(&self) {}
 
     pub fn set_update_interval(&self, interval: f64) {
-        unsafe { sys::NT_SetUpdateRate(self.handle, interval); }
+        self.backend.set_update_rate(interval)
     }
 
     /// Forces a flush of all entries to the network. This is usually done automatically, but this
     /// forces an immediate flush. However, to avoid network traffic, the flush may be delayed to
     /// some minimal interval between flushes.
     pub fn flush(&self) {
-        unsafe { sys::NT_Flush(self.handle); }
+        self.backend.flush()
     }
 
     /// Get all the connections on this instance. This will usually be one or zero on the client side
     /// but can be any number on the server side.
-    pub fn get_connections(&self) -> ConnectionInfo {
-        unsafe {
-            let mut len = 0;
-            let ptr = sys::NT_GetConnections(self.handle, &mut len);
-
-            ConnectionInfo::from_raw(ptr, len)
-        }
+    ///
+    /// This used to return a `ConnectionInfo`, a view of ntcore's own array of connections. Not
+    /// every backend has such an array to lend out, so it now returns owned copies, which have the
+    /// same getters as `ConnectionInfoEntry`.
+    pub fn get_connections(&self) -> Vec<OwnedConnectionInfo> {
+        self.backend.connections()
     }
 
     /// Get whether or not the instance is connected to another node
     pub fn is_connected(&self) -> bool {
-        unsafe { sys::NT_IsConnected(self.handle) != 0 }
+        self.backend.is_connected()
     }
 
-    fn is_default_instance(&self) -> bool {
-        unsafe { self.handle == sys::NT_GetDefaultInstance() }
+    /// Register a callback that is run on the backend's notifier thread whenever a remote node
+    /// connects or disconnects. If `immediate_notify` is set, the callback is also run right away
+    /// for every existing connection. The listener is removed when the returned guard is dropped.
+    pub fn add_connection_listener<F>(&self, immediate_notify: bool, callback: F) -> ConnectionListener
+        where F: Fn(ConnectionEvent) + Send + 'static
+    {
+        ConnectionListener::new(&self.backend, immediate_notify, callback)
+    }
+
+    /// A stream of remote nodes connecting and disconnecting. If `immediate_notify` is set, it
+    /// starts with a `Connected` event for every existing connection.
+    #[cfg(feature = "async")]
+    pub fn connection_events(&self, immediate_notify: bool) -> ConnectionEvents {
+        ConnectionEvents::new(&self.backend, immediate_notify)
+    }
+
+    /// A future that completes once this instance is connected to another node, or right away if
+    /// it already is.
+    #[cfg(feature = "async")]
+    pub fn wait_connected(&self) -> WaitConnected {
+        WaitConnected::new(&self.backend)
+    }
+
+    /// Create a poller for receiving connection events on a thread of your choosing. Fails with
+    /// `Error::Unsupported` if the instance doesn't run on ntcore.
+    #[cfg(feature = "ntcore-sys")]
+    pub fn connection_listener_poller(&self) -> Result<ConnectionListenerPoller> {
+        Ok(ConnectionListenerPoller::new(self.backend.clone(), self.native_handle()?))
+    }
+
+    /// Create a poller for handling RPC calls on a thread of your choosing. Procedures are bound to
+    /// the poller with `Entry::create_polled_rpc`. Fails with `Error::Unsupported` if the instance
+    /// doesn't run on ntcore.
+    #[cfg(feature = "ntcore-sys")]
+    pub fn rpc_call_poller(&self) -> Result<RpcCallPoller> {
+        Ok(RpcCallPoller::new(self.backend.clone(), self.native_handle()?))
     }
 
-    // NT_Entry NT_GetEntry(NT_Inst inst, const char *name, size_t name_len);
     pub fn get_entry(&self, key: &str) -> Entry {
-        let handle = unsafe { sys::NT_GetEntry(self.handle, key.as_ptr() as *const c_char, key.len()) };
-        Entry::new(handle)
+        Entry::new(self.backend.clone(), self.backend.entry(key))
     }
 
     pub fn get_all_entries(&self) -> Vec<Entry> {
@@ -139,19 +327,89 @@ impl Instance {
         self.get_entries_filtered("", EntryMask::all())
     }
 
+    /// Panics if the backend runs out of memory. Use `try_get_entries_filtered` to handle that
+    /// case.
     pub fn get_entries_filtered(&self, prefix: &str, types: EntryMask) -> Vec<Entry> {
-        // TODO: submit issue on wpilibsuite/ntcore; this currently causes UB on OOM
-        unsafe {
-            // Get entries from C
-            let mut len = 0;
-            let ptr = sys::NT_GetEntries(self.handle, prefix.as_ptr() as *const c_char,
-                                         prefix.len(), types.0 as c_uint, &mut len);
-            if ptr.is_null() && len != 0 { panic!("get_entries_filtered ran out of memory."); }
-            let ret = ::std::slice::from_raw_parts(ptr, len).iter().map(|&handle| Entry { handle }).collect();
-            // Free the C entry array; we've cloned it all.
-            sys::NT_DisposeEntryArray(ptr, len);
-            ret
-        }
+        self.try_get_entries_filtered(prefix, types).expect("get_entries_filtered ran out of memory.")
+    }
+
+    pub fn try_get_entries_filtered(&self, prefix: &str, types: EntryMask) -> Result<Vec<Entry>> {
+        let handles = self.backend.entries(prefix, types)?;
+        Ok(handles.into_iter().map(|handle| Entry::new(self.backend.clone(), handle)).collect())
+    }
+
+    /// Get the metadata of every entry whose name starts with `prefix` and whose type is in
+    /// `types`, in one call. Panics if the backend runs out of memory. Use `try_get_entry_info` to
+    /// handle that case.
+    pub fn get_entry_info(&self, prefix: &str, types: EntryMask) -> Vec<EntryInfo> {
+        self.try_get_entry_info(prefix, types).expect("get_entry_info ran out of memory.")
+    }
+
+    pub fn try_get_entry_info(&self, prefix: &str, types: EntryMask) -> Result<Vec<EntryInfo>> {
+        let infos = self.backend.entries_info(prefix, types)?;
+        Ok(infos.into_iter().map(|info| EntryInfo::new(&self.backend, info)).collect())
+    }
+
+    /// Create a poller for receiving entry notifications on a thread of your choosing. Listeners
+    /// are added to the poller with `EntryListenerPoller::add_listener`. Fails with
+    /// `Error::Unsupported` if the instance doesn't run on ntcore.
+    #[cfg(feature = "ntcore-sys")]
+    pub fn entry_listener_poller(&self) -> Result<EntryListenerPoller> {
+        Ok(EntryListenerPoller::new(self.backend.clone(), self.native_handle()?))
+    }
+
+    /// Register a callback for changes to every entry whose name starts with `prefix`. The
+    /// callback runs on the backend's notifier thread, and is removed when the returned guard is
+    /// dropped.
+    pub fn add_entry_listener<F>(&self, prefix: &str, flags: NotifyFlags, callback: F) -> EntryListener
+        where F: Fn(EntryNotification) + Send + 'static
+    {
+        EntryListener::for_prefix(&self.backend, prefix, flags, callback)
+    }
+
+    /// Save all persistent entries to `path`. The server does this automatically to the file given
+    /// to `start_server`, but this can be used to take snapshots. Fails with `Error::Unsupported`
+    /// if the instance doesn't run on ntcore.
+    #[cfg(feature = "ntcore-sys")]
+    pub fn save_persistent(&self, path: &str) -> Result<()> {
+        let handle = self.native_handle()?;
+        let path = CString::new(path)?;
+        persistence_result(unsafe { sys::NT_SavePersistent(handle, path.as_ptr()) })
+    }
+
+    /// Load persistent entries from `path`. Lines that can't be parsed are skipped. Each one is
+    /// passed to `warn` as it is found, and they are all returned at the end.
+    #[cfg(feature = "ntcore-sys")]
+    pub fn load_persistent<F: FnMut(&LoadWarning)>(&self, path: &str, warn: F) -> Result<Vec<LoadWarning>> {
+        let path = CString::new(path)?;
+        let handle = self.native_handle()?;
+        collect_load_warnings(warn, || unsafe {
+            sys::NT_LoadPersistent(handle, path.as_ptr(), Some(forward_load_warning))
+        })
+    }
+
+    /// Save all entries whose names start with `prefix` to `path`, whether they are persistent or not.
+    #[cfg(feature = "ntcore-sys")]
+    pub fn save_entries(&self, path: &str, prefix: &str) -> Result<()> {
+        let handle = self.native_handle()?;
+        let path = CString::new(path)?;
+        persistence_result(unsafe {
+            sys::NT_SaveEntries(handle, path.as_ptr(), prefix.as_ptr() as *const c_char, prefix.len())
+        })
+    }
+
+    /// Load only the entries whose names start with `prefix` from `path`. Lines that can't be
+    /// parsed are skipped and passed to `warn`, like with `load_persistent`.
+    #[cfg(feature = "ntcore-sys")]
+    pub fn load_entries<F>(&self, path: &str, prefix: &str, warn: F) -> Result<Vec<LoadWarning>>
+        where F: FnMut(&LoadWarning)
+    {
+        let path = CString::new(path)?;
+        let handle = self.native_handle()?;
+        collect_load_warnings(warn, || unsafe {
+            sys::NT_LoadEntries(handle, path.as_ptr(), prefix.as_ptr() as *const c_char, prefix.len(),
+                                Some(forward_load_warning))
+        })
     }
 
     pub fn get_table(&self, name: String) -> NetworkTable {
@@ -160,24 +418,6 @@ impl Instance {
 
     /// Delete ALL entries. Use with caution.
     pub fn delete_all_entries(&self) {
-        unsafe { sys::NT_DeleteAllEntries(self.handle) }
-    }
-}
-
-impl Drop for Instance {
-    fn drop(&mut self) {
-        unsafe {
-            if !self.is_default_instance() {
-                if self.server_mode {
-                    // We're a server.
-                    sys::NT_StopClient(self.handle);
-                } else {
-                    // We're a client
-                    sys::NT_StopClient(self.handle);
-                }
-
-                sys::NT_DestroyInstance(self.handle);
-            }
-        }
+        self.backend.delete_all_entries()
     }
 }
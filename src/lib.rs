@@ -1,8 +1,23 @@
 #![deny(missing_debug_implementations)]
 
+#[cfg(feature = "ntcore-sys")]
 extern crate ntcore_sys as sys;
-#[macro_use]
+#[cfg_attr(feature = "ntcore-sys", macro_use)]
 extern crate lazy_static;
+#[cfg(feature = "nt4")]
+extern crate tungstenite;
+#[cfg(feature = "nt4")]
+#[macro_use]
+extern crate serde_json;
+#[cfg(feature = "nt4")]
+extern crate rmpv;
+#[cfg(feature = "async")]
+extern crate futures_core;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(feature = "derive")]
+extern crate ntcore_derive;
 
 pub(crate) mod sealed {
     pub trait Sealed {}
@@ -12,21 +27,89 @@ pub(crate) mod sealed {
 pub struct NetworkTime(u64);
 
 /// Helper for turning NT_String into things
+#[cfg(feature = "ntcore-sys")]
 #[derive(Debug)]
 pub(crate) struct NtString(sys::NT_String);
 
+#[cfg(feature = "ntcore-sys")]
 impl NtString {
-    // NT docs say NT_String is UTF-8, so unwrapping is fine
-    unsafe fn as_str<'a>(self) -> &'a str { ::std::str::from_utf8(self.as_bytes()).unwrap() }
-    unsafe fn as_bytes<'a>(self) -> &'a [u8] { ::std::slice::from_raw_parts(self.0.str as *mut u8, self.0.len) }
+    // NT docs say NT_String is UTF-8, but the contents can come from any remote node, so invalid
+    // sequences are replaced instead of trusted.
+    unsafe fn to_str_lossy<'a>(self) -> ::std::borrow::Cow<'a, str> { String::from_utf8_lossy(self.as_bytes()) }
+    unsafe fn as_bytes<'a>(self) -> &'a [u8] {
+        // Empty strings may have a null pointer, which `from_raw_parts` doesn't allow.
+        if self.0.len == 0 { &[] } else { ::std::slice::from_raw_parts(self.0.str as *mut u8, self.0.len) }
+    }
 }
 
+#[cfg(feature = "ntcore-sys")]
 pub fn now() -> NetworkTime { unsafe { NetworkTime(sys::NT_Now()) } }
 
+/// Without ntcore, time is measured in microseconds since the Unix epoch.
+#[cfg(not(feature = "ntcore-sys"))]
+pub fn now() -> NetworkTime {
+    let since_epoch = ::std::time::SystemTime::now().duration_since(::std::time::UNIX_EPOCH).unwrap_or_default();
+    NetworkTime(since_epoch.as_secs() * 1_000_000 + since_epoch.subsec_micros() as u64)
+}
+
+/// ntcore takes timeouts as fractional seconds.
+#[cfg(feature = "ntcore-sys")]
+pub(crate) fn duration_secs(duration: ::std::time::Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1e-9
+}
+
+/// Typed shorthands for `set_default`, shared by `Entry` and `NetworkTable`.
+macro_rules! set_default_value {
+    ($name:ident: $type:ty) => {
+        pub fn $name(&self, value: $type) -> ::Result<bool> { self.set_default(value) }
+    };
+    ($name:ident(key): $type:ty) => {
+        pub fn $name(&mut self, key: &str, value: $type) -> ::Result<bool> { self.set_default(key, value) }
+    };
+}
+
+pub mod backend;
+pub mod bind;
+pub mod error;
 pub mod instance;
 pub mod connection;
+pub mod convert;
 pub mod table;
+pub mod typed;
 pub mod entry;
+pub mod listener;
+pub mod rpc;
+#[cfg(feature = "serde")]
+pub mod serialization;
+pub mod persistent;
+#[cfg(feature = "async")]
+pub mod stream;
+pub mod nt3;
+pub mod testing;
+#[cfg(feature = "nt4")]
+pub mod nt4;
+mod wire;
 
+pub use error::{Error, Result};
 pub use instance::Instance;
 pub use table::NetworkTable;
+#[cfg(feature = "derive")]
+pub use ntcore_derive::NtTable;
+
+#[cfg(all(test, feature = "ntcore-sys"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_utf8_is_replaced() {
+        let mut bytes = *b"ok\xff";
+        let string = NtString(sys::NT_String { str: bytes.as_mut_ptr() as *mut _, len: bytes.len() });
+        assert_eq!(unsafe { string.to_str_lossy() }, "ok\u{fffd}");
+    }
+
+    #[test]
+    fn empty_strings_may_be_null() {
+        let string = NtString(sys::NT_String { str: ::std::ptr::null_mut(), len: 0 });
+        assert_eq!(unsafe { string.as_bytes() }, b"");
+    }
+}
@@ -0,0 +1,301 @@
+use std::fmt;
+use std::sync::Arc;
+#[cfg(feature = "ntcore-sys")]
+use std::os::raw::c_char;
+#[cfg(feature = "ntcore-sys")]
+use std::time::Duration;
+#[cfg(feature = "ntcore-sys")]
+use sys::{self, NT_EntryListenerPoller, NT_EntryNotification, NT_Inst};
+#[cfg(feature = "ntcore-sys")]
+use sys::{NT_ConnectionListenerPoller, NT_ConnectionNotification};
+use backend::{Backend, Handle, Notification};
+use connection::ConnectionEvent;
+use entry::{Entry, Value};
+#[cfg(feature = "ntcore-sys")]
+use ::{duration_secs, Result};
+
+/// Set of events an entry listener cares about, and the reason a notification was sent. Flags
+/// can be combined like so:
+/// ```rs
+/// let flags = NotifyFlags::NEW | NotifyFlags::UPDATE | NotifyFlags::IMMEDIATE;
+/// ```
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct NotifyFlags(pub(crate) u32);
+
+impl NotifyFlags {
+    pub const NONE: NotifyFlags = NotifyFlags(0x00);
+    /// Notify right away for every entry that already exists when the listener is added.
+    pub const IMMEDIATE: NotifyFlags = NotifyFlags(0x01);
+    /// Notify for changes made by this instance, not just ones coming from the network.
+    pub const LOCAL: NotifyFlags = NotifyFlags(0x02);
+    pub const NEW: NotifyFlags = NotifyFlags(0x04);
+    pub const DELETE: NotifyFlags = NotifyFlags(0x08);
+    pub const UPDATE: NotifyFlags = NotifyFlags(0x10);
+    pub const FLAGS: NotifyFlags = NotifyFlags(0x20);
+
+    pub fn contains(&self, other: NotifyFlags) -> bool { self.0 & other.0 == other.0 }
+
+    pub fn is_immediate(&self) -> bool { self.contains(NotifyFlags::IMMEDIATE) }
+    pub fn is_local(&self) -> bool { self.contains(NotifyFlags::LOCAL) }
+    pub fn is_new(&self) -> bool { self.contains(NotifyFlags::NEW) }
+    pub fn is_delete(&self) -> bool { self.contains(NotifyFlags::DELETE) }
+    pub fn is_update(&self) -> bool { self.contains(NotifyFlags::UPDATE) }
+    pub fn is_flags(&self) -> bool { self.contains(NotifyFlags::FLAGS) }
+}
+
+impl ::std::ops::BitOr for NotifyFlags {
+    type Output = NotifyFlags;
+    fn bitor(self, rhs: NotifyFlags) -> NotifyFlags {
+        NotifyFlags(self.0 | rhs.0)
+    }
+}
+
+/// An owned copy of a change notification sent to an entry listener.
+#[derive(Debug, PartialEq)]
+pub struct EntryNotification {
+    pub entry: Entry,
+    pub name: String,
+    /// The new value of the entry. For deletions, this is the value the entry had before it was
+    /// deleted.
+    pub value: Option<Value>,
+    pub flags: NotifyFlags,
+}
+
+impl EntryNotification {
+    pub(crate) fn new(backend: &Arc<dyn Backend>, notification: Notification) -> Self {
+        EntryNotification {
+            entry: Entry::new(backend.clone(), notification.entry),
+            name: notification.name,
+            value: notification.value,
+            flags: notification.flags,
+        }
+    }
+
+    #[cfg(feature = "ntcore-sys")]
+    pub(crate) unsafe fn from_raw(backend: &Arc<dyn Backend>, raw: &NT_EntryNotification) -> Self {
+        EntryNotification::new(backend, Notification::from_raw(raw))
+    }
+}
+
+/// Guard for a registered entry listener. The listener is removed when this is dropped.
+#[must_use]
+pub struct EntryListener {
+    backend: Arc<dyn Backend>,
+    handle: Handle,
+}
+
+impl EntryListener {
+    /// Wrap a user callback into one the backend can call, which hands out entries of `backend`.
+    fn wrap<F>(backend: &Arc<dyn Backend>, callback: F) -> Box<dyn Fn(Notification) + Send + 'static>
+        where F: Fn(EntryNotification) + Send + 'static
+    {
+        let backend = backend.clone();
+        Box::new(move |notification| callback(EntryNotification::new(&backend, notification)))
+    }
+
+    pub(crate) fn for_prefix<F>(backend: &Arc<dyn Backend>, prefix: &str, flags: NotifyFlags, callback: F) -> Self
+        where F: Fn(EntryNotification) + Send + 'static
+    {
+        let handle = backend.add_entry_listener(prefix, flags, EntryListener::wrap(backend, callback));
+        EntryListener { backend: backend.clone(), handle }
+    }
+
+    pub(crate) fn for_entry<F>(entry: &Entry, flags: NotifyFlags, callback: F) -> Self
+        where F: Fn(EntryNotification) + Send + 'static
+    {
+        let backend = &entry.backend;
+        let handle = backend.add_entry_listener_single(entry.handle, flags, EntryListener::wrap(backend, callback));
+        EntryListener { backend: backend.clone(), handle }
+    }
+}
+
+impl fmt::Debug for EntryListener {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EntryListener").field("handle", &self.handle).finish()
+    }
+}
+
+impl Drop for EntryListener {
+    fn drop(&mut self) {
+        self.backend.remove_entry_listener(self.handle)
+    }
+}
+
+/// A queue of entry notifications that is drained manually, instead of having callbacks run on
+/// ntcore's notifier thread. The underlying poller is destroyed when this is dropped.
+#[cfg(feature = "ntcore-sys")]
+pub struct EntryListenerPoller {
+    backend: Arc<dyn Backend>,
+    handle: NT_EntryListenerPoller,
+}
+
+#[cfg(feature = "ntcore-sys")]
+impl EntryListenerPoller {
+    pub(crate) fn new(backend: Arc<dyn Backend>, inst: NT_Inst) -> Self {
+        EntryListenerPoller { backend, handle: unsafe { sys::NT_CreateEntryListenerPoller(inst) } }
+    }
+
+    /// Queue notifications for every entry whose name starts with `prefix` onto this poller.
+    pub fn add_listener(&self, prefix: &str, flags: NotifyFlags) -> EntryListener {
+        let handle = unsafe {
+            sys::NT_AddPolledEntryListener(self.handle, prefix.as_ptr() as *const c_char, prefix.len(), flags.0)
+        };
+        EntryListener { backend: self.backend.clone(), handle }
+    }
+
+    /// Queue notifications for a single entry onto this poller. Fails with `Error::Unsupported` if
+    /// the entry doesn't belong to an ntcore instance.
+    pub fn add_entry_listener(&self, entry: &Entry, flags: NotifyFlags) -> Result<EntryListener> {
+        let handle = unsafe { sys::NT_AddPolledEntryListenerSingle(self.handle, entry.native_handle()?, flags.0) };
+        Ok(EntryListener { backend: self.backend.clone(), handle })
+    }
+
+    /// Block until there is at least one notification in the queue, then return everything in it.
+    /// Returns an empty list if the poll was cancelled with `cancel`.
+    pub fn poll(&self) -> Vec<EntryNotification> {
+        unsafe {
+            let mut len = 0;
+            let ptr = sys::NT_PollEntryListener(self.handle, &mut len);
+            take_notifications(&self.backend, ptr, len)
+        }
+    }
+
+    /// Like `poll`, but gives up after `timeout`. Returns `None` if the timeout expired.
+    pub fn poll_timeout(&self, timeout: Duration) -> Option<Vec<EntryNotification>> {
+        unsafe {
+            let mut len = 0;
+            let mut timed_out = 0;
+            let ptr = sys::NT_PollEntryListenerTimeout(self.handle, &mut len, duration_secs(timeout), &mut timed_out);
+            if timed_out != 0 { None } else { Some(take_notifications(&self.backend, ptr, len)) }
+        }
+    }
+
+    /// Wake up any thread blocked in `poll` or `poll_timeout`.
+    pub fn cancel(&self) {
+        unsafe { sys::NT_CancelPollEntryListener(self.handle) }
+    }
+}
+
+#[cfg(feature = "ntcore-sys")]
+impl fmt::Debug for EntryListenerPoller {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EntryListenerPoller").field("handle", &self.handle).finish()
+    }
+}
+
+#[cfg(feature = "ntcore-sys")]
+impl Drop for EntryListenerPoller {
+    fn drop(&mut self) {
+        unsafe { sys::NT_DestroyEntryListenerPoller(self.handle) }
+    }
+}
+
+/// Copy out and free a notification array returned from one of the poll functions.
+#[cfg(feature = "ntcore-sys")]
+unsafe fn take_notifications(backend: &Arc<dyn Backend>, ptr: *mut NT_EntryNotification, len: usize) -> Vec<EntryNotification> {
+    if ptr.is_null() { return Vec::new(); }
+    let ret = ::std::slice::from_raw_parts(ptr, len).iter()
+        .map(|raw| EntryNotification::from_raw(backend, raw))
+        .collect();
+    sys::NT_DisposeEntryNotificationArray(ptr, len);
+    ret
+}
+
+/// Guard for a registered connection listener. The listener is removed when this is dropped.
+#[must_use]
+pub struct ConnectionListener {
+    backend: Arc<dyn Backend>,
+    handle: Handle,
+}
+
+impl ConnectionListener {
+    pub(crate) fn new<F>(backend: &Arc<dyn Backend>, immediate_notify: bool, callback: F) -> Self
+        where F: Fn(ConnectionEvent) + Send + 'static
+    {
+        let handle = backend.add_connection_listener(immediate_notify, Box::new(callback));
+        ConnectionListener { backend: backend.clone(), handle }
+    }
+}
+
+impl fmt::Debug for ConnectionListener {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ConnectionListener").field("handle", &self.handle).finish()
+    }
+}
+
+impl Drop for ConnectionListener {
+    fn drop(&mut self) {
+        self.backend.remove_connection_listener(self.handle)
+    }
+}
+
+/// A queue of connection events that is drained manually. The underlying poller is destroyed when
+/// this is dropped.
+#[cfg(feature = "ntcore-sys")]
+pub struct ConnectionListenerPoller {
+    backend: Arc<dyn Backend>,
+    handle: NT_ConnectionListenerPoller,
+}
+
+#[cfg(feature = "ntcore-sys")]
+impl ConnectionListenerPoller {
+    pub(crate) fn new(backend: Arc<dyn Backend>, inst: NT_Inst) -> Self {
+        ConnectionListenerPoller { backend, handle: unsafe { sys::NT_CreateConnectionListenerPoller(inst) } }
+    }
+
+    /// Start queueing connection events onto this poller. If `immediate_notify` is set, a
+    /// `Connected` event is queued right away for every existing connection.
+    pub fn add_listener(&self, immediate_notify: bool) -> ConnectionListener {
+        let handle = unsafe { sys::NT_AddPolledConnectionListener(self.handle, immediate_notify as sys::NT_Bool) };
+        ConnectionListener { backend: self.backend.clone(), handle }
+    }
+
+    /// Block until there is at least one event in the queue, then return everything in it.
+    /// Returns an empty list if the poll was cancelled with `cancel`.
+    pub fn poll(&self) -> Vec<ConnectionEvent> {
+        unsafe {
+            let mut len = 0;
+            let ptr = sys::NT_PollConnectionListener(self.handle, &mut len);
+            take_connection_events(ptr, len)
+        }
+    }
+
+    /// Like `poll`, but gives up after `timeout`. Returns `None` if the timeout expired.
+    pub fn poll_timeout(&self, timeout: Duration) -> Option<Vec<ConnectionEvent>> {
+        unsafe {
+            let mut len = 0;
+            let mut timed_out = 0;
+            let ptr = sys::NT_PollConnectionListenerTimeout(self.handle, &mut len, duration_secs(timeout), &mut timed_out);
+            if timed_out != 0 { None } else { Some(take_connection_events(ptr, len)) }
+        }
+    }
+
+    /// Wake up any thread blocked in `poll` or `poll_timeout`.
+    pub fn cancel(&self) {
+        unsafe { sys::NT_CancelPollConnectionListener(self.handle) }
+    }
+}
+
+#[cfg(feature = "ntcore-sys")]
+impl fmt::Debug for ConnectionListenerPoller {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ConnectionListenerPoller").field("handle", &self.handle).finish()
+    }
+}
+
+#[cfg(feature = "ntcore-sys")]
+impl Drop for ConnectionListenerPoller {
+    fn drop(&mut self) {
+        unsafe { sys::NT_DestroyConnectionListenerPoller(self.handle) }
+    }
+}
+
+#[cfg(feature = "ntcore-sys")]
+unsafe fn take_connection_events(ptr: *mut NT_ConnectionNotification, len: usize) -> Vec<ConnectionEvent> {
+    if ptr.is_null() { return Vec::new(); }
+    let ret = ::std::slice::from_raw_parts(ptr, len).iter()
+        .map(|raw| ConnectionEvent::from_raw(raw))
+        .collect();
+    sys::NT_DisposeConnectionNotificationArray(ptr, len);
+    ret
+}
@@ -0,0 +1,822 @@
+//! A NetworkTables 3 client that runs over plain TCP on a background thread.
+//!
+//! ```rs
+//! let client = Client::start_client(Ipv4Addr::new(10, 0, 0, 2), 1735);
+//! let speed = client.get_entry("/Shooter/speed");
+//! speed.set(3000.0)?;
+//! println!("{:?}", client.get_entry("/Shooter/ready").value());
+//! ```
+//!
+//! A client is also a `Backend`, so it can be put under an `Instance` with `Instance::from_backend`.
+//! It can't be made into a server.
+
+use std::any::Any;
+use std::collections::hash_map::{self, HashMap};
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::io::{self, Write};
+use std::mem;
+use std::net::{Ipv4Addr, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use backend::network::{self, notify, Event, EventSender, Notifier};
+use backend::{Backend, ConnectionCallback, Handle, NotificationCallback, Target};
+use connection::{ConnectionEvent, OwnedConnectionInfo};
+use entry::{EntryFlags, EntryMask, EntryType, Value};
+use listener::NotifyFlags;
+use nt3::codec::{Decoder, Message, UNASSIGNED_ID};
+use nt3::{self, SequenceNumber, PROTOCOL_REVISION};
+use ::{now, Error, NetworkTime, Result};
+
+/// How long the network thread waits for data before sending queued changes. This matches ntcore's
+/// default update rate.
+const UPDATE_INTERVAL: Duration = Duration::from_millis(100);
+/// A keep alive is sent when nothing else has been sent for this long.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(1);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+const RECONNECT_DELAY: Duration = Duration::from_millis(500);
+
+#[derive(Debug)]
+struct EntryState {
+    /// `UNASSIGNED_ID` until the server tells us the id of this entry.
+    id: u16,
+    seq: SequenceNumber,
+    flags: u8,
+    value: Value,
+    /// We sent an assignment for this entry and are waiting for the server to give it an id.
+    assigning: bool,
+    /// Local time of the last change to the value.
+    last_change: NetworkTime,
+}
+
+impl EntryState {
+    fn assignment(&self, name: &str) -> Message {
+        Message::EntryAssignment {
+            name: name.to_owned(),
+            id: UNASSIGNED_ID,
+            seq: self.seq.0,
+            flags: self.flags,
+            value: self.value.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    identity: String,
+    servers: Vec<(String, u16)>,
+    entries: HashMap<String, EntryState>,
+    ids: HashMap<u16, String>,
+    /// Messages waiting to be sent by the network thread. Only filled while connected.
+    outgoing: Vec<Message>,
+    /// Entries changed while disconnected. Their values win over the server's during the next
+    /// handshake, and are sent to it once the handshake is done.
+    dirty: HashSet<String>,
+    /// Like `dirty`, for entries whose flags changed.
+    dirty_flags: HashSet<String>,
+    /// The server we're connected to, if any.
+    connection: Option<OwnedConnectionInfo>,
+    /// Where changes are reported, for listeners.
+    events: EventSender,
+}
+
+impl State {
+    fn new(servers: Vec<(String, u16)>, events: EventSender) -> State {
+        State {
+            identity: String::new(),
+            servers,
+            entries: HashMap::new(),
+            ids: HashMap::new(),
+            outgoing: Vec::new(),
+            dirty: HashSet::new(),
+            dirty_flags: HashSet::new(),
+            connection: None,
+            events,
+        }
+    }
+
+    fn handle_message(&mut self, msg: Message) {
+        let State { ref mut entries, ref mut ids, ref mut outgoing, ref mut dirty, ref mut dirty_flags, ref events, .. } = *self;
+
+        match msg {
+            Message::EntryAssignment { name, id, seq, flags, value } => {
+                let seq = SequenceNumber(seq);
+                ids.insert(id, name.clone());
+                let keep_ours = dirty.contains(&name)
+                    && entries.get(&name).is_some_and(|entry| entry.value.entry_type() == value.entry_type());
+                if !keep_ours { dirty.remove(&name); }
+                match entries.entry(name.clone()) {
+                    hash_map::Entry::Occupied(mut occupied) => {
+                        let entry = occupied.get_mut();
+                        entry.id = id;
+                        if keep_ours {
+                            // Our value is sent once the handshake is done, and has to be newer
+                            // than the server's to be accepted.
+                            entry.seq = seq.next();
+                        } else if entry.assigning && entry.value != value {
+                            // We changed the value again while waiting for the id, so the server
+                            // needs to catch up.
+                            entry.seq = seq.next();
+                            outgoing.push(Message::EntryUpdate { id, seq: entry.seq.0, value: entry.value.clone() });
+                        } else {
+                            // Otherwise the server's value wins, including during the handshake.
+                            entry.seq = seq;
+                            if entry.value != value {
+                                entry.value = value;
+                                entry.last_change = now();
+                                notify(events, &name, Some(entry.value.clone()), NotifyFlags::UPDATE);
+                            }
+                        }
+
+                        // Flags work the same way, with ours sent once the handshake is done.
+                        if entry.flags != flags && !dirty_flags.contains(&name) {
+                            if entry.assigning {
+                                outgoing.push(Message::FlagsUpdate { id, flags: entry.flags });
+                            } else {
+                                entry.flags = flags;
+                                notify(events, &name, Some(entry.value.clone()), NotifyFlags::FLAGS);
+                            }
+                        }
+                        entry.assigning = false;
+                    }
+                    hash_map::Entry::Vacant(vacant) => {
+                        notify(events, &name, Some(value.clone()), NotifyFlags::NEW);
+                        vacant.insert(EntryState { id, seq, flags, value, assigning: false, last_change: now() });
+                    }
+                }
+            }
+            Message::EntryUpdate { id, seq, value } => {
+                let seq = SequenceNumber(seq);
+                let name = match ids.get(&id) {
+                    Some(name) => name,
+                    None => return,
+                };
+                if let Some(entry) = entries.get_mut(name) {
+                    // Updates can't change the type of an entry, and stale updates are dropped.
+                    if seq.is_newer_than(entry.seq) && value.entry_type() == entry.value.entry_type() {
+                        entry.seq = seq;
+                        if entry.value != value {
+                            entry.value = value;
+                            entry.last_change = now();
+                            notify(events, name, Some(entry.value.clone()), NotifyFlags::UPDATE);
+                        }
+                    }
+                }
+            }
+            Message::FlagsUpdate { id, flags } => {
+                let name = match ids.get(&id) {
+                    Some(name) => name,
+                    None => return,
+                };
+                if let Some(entry) = entries.get_mut(name) {
+                    if entry.flags != flags {
+                        entry.flags = flags;
+                        dirty_flags.remove(name);
+                        notify(events, name, Some(entry.value.clone()), NotifyFlags::FLAGS);
+                    }
+                }
+            }
+            Message::EntryDelete { id } => {
+                if let Some(name) = ids.remove(&id) {
+                    dirty.remove(&name);
+                    dirty_flags.remove(&name);
+                    if let Some(entry) = entries.remove(&name) {
+                        notify(events, &name, Some(entry.value), NotifyFlags::DELETE);
+                    }
+                }
+            }
+            Message::ClearAllEntries => {
+                for (name, entry) in entries.drain() {
+                    notify(events, &name, Some(entry.value), NotifyFlags::DELETE);
+                }
+                ids.clear();
+                dirty.clear();
+                dirty_flags.clear();
+            }
+            // Keep alives need no answer, and RPCs aren't supported by this client.
+            _ => {}
+        }
+    }
+
+    /// Forget everything the server told us about ids, so that our entries are assigned again on
+    /// the next connection. Changes that were still waiting to be sent are kept for then.
+    fn disconnect(&mut self) {
+        if let Some(info) = self.connection.take() {
+            let _ = self.events.send(Event::Connection(ConnectionEvent::Disconnected(info)));
+        }
+        for msg in self.outgoing.drain(..) {
+            match msg {
+                Message::EntryAssignment { name, .. } => { self.dirty.insert(name); }
+                Message::EntryUpdate { id, .. } => if let Some(name) = self.ids.get(&id) {
+                    self.dirty.insert(name.clone());
+                },
+                Message::FlagsUpdate { id, .. } => if let Some(name) = self.ids.get(&id) {
+                    self.dirty_flags.insert(name.clone());
+                },
+                _ => {}
+            }
+        }
+        self.ids.clear();
+        for entry in self.entries.values_mut() {
+            entry.id = UNASSIGNED_ID;
+            entry.assigning = false;
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    state: Mutex<State>,
+    running: AtomicBool,
+    connected: AtomicBool,
+    /// Set to make the network thread drop the current connection, after the servers change.
+    reconnect: AtomicBool,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // A panic on the network thread shouldn't take the robot code down with it.
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn is_running(&self) -> bool { self.running.load(Ordering::SeqCst) }
+
+    fn set_value(&self, name: &str, value: Value) -> Result<()> {
+        let mut state = self.lock();
+        // Read under the lock so this can't race with the handshake finishing.
+        let connected = self.connected.load(Ordering::SeqCst);
+        let State { ref mut entries, ref mut outgoing, ref mut dirty, ref events, .. } = *state;
+
+        match entries.entry(name.to_owned()) {
+            hash_map::Entry::Occupied(mut occupied) => {
+                let entry = occupied.get_mut();
+                let expected = entry.value.entry_type();
+                if expected != value.entry_type() {
+                    return Err(Error::TypeMismatch { expected, actual: value.entry_type() });
+                }
+                if entry.value == value { return Ok(()); }
+
+                entry.seq = entry.seq.next();
+                entry.value = value;
+                entry.last_change = now();
+                notify(events, name, Some(entry.value.clone()), NotifyFlags::UPDATE | NotifyFlags::LOCAL);
+                if !connected {
+                    dirty.insert(name.to_owned());
+                } else if entry.id != UNASSIGNED_ID {
+                    outgoing.push(Message::EntryUpdate { id: entry.id, seq: entry.seq.0, value: entry.value.clone() });
+                }
+            }
+            hash_map::Entry::Vacant(vacant) => {
+                notify(events, name, Some(value.clone()), NotifyFlags::NEW | NotifyFlags::LOCAL);
+                let entry = EntryState {
+                    id: UNASSIGNED_ID, seq: SequenceNumber(0), flags: 0, value, assigning: connected, last_change: now(),
+                };
+                if connected {
+                    outgoing.push(entry.assignment(name));
+                } else {
+                    dirty.insert(name.to_owned());
+                }
+                vacant.insert(entry);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Set the value only if the entry doesn't have one, as one step.
+    fn set_default(&self, name: &str, value: Value) -> Result<bool> {
+        let existing = self.lock().entries.get(name).map(|entry| entry.value.entry_type());
+        match existing {
+            Some(ty) if ty == value.entry_type() => Ok(false),
+            Some(ty) => Err(Error::TypeMismatch { expected: ty, actual: value.entry_type() }),
+            None => self.set_value(name, value).map(|()| true),
+        }
+    }
+
+    fn set_flags(&self, name: &str, flags: u8) {
+        let mut state = self.lock();
+        let connected = self.connected.load(Ordering::SeqCst);
+        let State { ref mut entries, ref mut outgoing, ref mut dirty_flags, ref events, .. } = *state;
+
+        if let Some(entry) = entries.get_mut(name) {
+            if entry.flags == flags { return; }
+            entry.flags = flags;
+            notify(events, name, Some(entry.value.clone()), NotifyFlags::FLAGS | NotifyFlags::LOCAL);
+            if !connected {
+                dirty_flags.insert(name.to_owned());
+            } else if entry.id != UNASSIGNED_ID {
+                outgoing.push(Message::FlagsUpdate { id: entry.id, flags });
+            }
+        }
+    }
+
+    /// Delete an entry here and on the server. Entries deleted while disconnected come back if
+    /// the server still has them.
+    fn delete(&self, name: &str) {
+        let mut state = self.lock();
+        let connected = self.connected.load(Ordering::SeqCst);
+        let State { ref mut entries, ref mut ids, ref mut outgoing, ref mut dirty, ref mut dirty_flags, ref events, .. } = *state;
+
+        if let Some(entry) = entries.remove(name) {
+            dirty.remove(name);
+            dirty_flags.remove(name);
+            if entry.id != UNASSIGNED_ID {
+                ids.remove(&entry.id);
+                if connected { outgoing.push(Message::EntryDelete { id: entry.id }); }
+            }
+            notify(events, name, Some(entry.value), NotifyFlags::DELETE | NotifyFlags::LOCAL);
+        }
+    }
+
+    fn values(&self) -> Vec<(String, Value)> {
+        self.lock().entries.iter().map(|(name, entry)| (name.clone(), entry.value.clone())).collect()
+    }
+
+    /// Change the servers to connect to, dropping the current connection.
+    fn set_servers(&self, servers: Vec<(String, u16)>) {
+        self.lock().servers = servers;
+        self.reconnect.store(true, Ordering::SeqCst);
+    }
+}
+
+/// A socket to the server, plus whatever partial message has been read from it.
+struct Connection {
+    stream: TcpStream,
+    decoder: Decoder,
+    last_sent: Instant,
+}
+
+impl Connection {
+    fn send(&mut self, msgs: &[Message]) -> io::Result<()> {
+        let mut buf = Vec::new();
+        for msg in msgs { msg.encode(&mut buf); }
+        self.stream.write_all(&buf)?;
+        self.last_sent = Instant::now();
+        Ok(())
+    }
+
+    /// Wait up to one update interval for data, and return every complete message received.
+    fn receive(&mut self) -> io::Result<Vec<Message>> {
+        nt3::receive(&mut self.stream, &mut self.decoder)
+    }
+}
+
+fn connect(host: &str, port: u16) -> io::Result<TcpStream> {
+    let mut last_err = io::Error::new(io::ErrorKind::NotFound, "server name did not resolve to any address");
+    for addr in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_err = err,
+        }
+    }
+    Err(last_err)
+}
+
+fn run_connection(shared: &Shared, stream: TcpStream, identity: String) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(UPDATE_INTERVAL))?;
+    let mut conn = Connection { stream, decoder: Decoder::new(), last_sent: Instant::now() };
+    conn.send(&[Message::ClientHello { protocol_revision: PROTOCOL_REVISION, identity }])?;
+
+    // The server starts by sending every entry it has, followed by `ServerHelloComplete`.
+    let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+    let mut hello_complete = false;
+    let mut server_identity = String::new();
+    while !hello_complete {
+        if !shared.is_running() { return Ok(()); }
+        if Instant::now() > deadline {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "server did not finish the handshake"));
+        }
+
+        for msg in conn.receive()? {
+            match msg {
+                Message::ServerHelloComplete => hello_complete = true,
+                Message::ServerHello { identity, .. } => server_identity = identity,
+                Message::ProtocolUnsupported { .. } =>
+                    return Err(io::Error::other("server does not support protocol 3.0")),
+                msg => shared.lock().handle_message(msg),
+            }
+        }
+    }
+
+    let peer = conn.stream.peer_addr()?;
+    let info = OwnedConnectionInfo::new(server_identity, peer.ip().to_string(), peer.port() as u32, now(),
+                                        PROTOCOL_REVISION as u32);
+
+    // Then we tell the server about the entries it doesn't know about yet, and the ones we changed
+    // while we were away.
+    let mut hello = Vec::new();
+    {
+        let mut state = shared.lock();
+        let State { ref mut entries, ref mut dirty, ref mut dirty_flags, .. } = *state;
+        for (name, entry) in entries {
+            if entry.id == UNASSIGNED_ID {
+                entry.assigning = true;
+                hello.push(entry.assignment(name));
+                continue;
+            }
+            if dirty.contains(name) {
+                hello.push(Message::EntryUpdate { id: entry.id, seq: entry.seq.0, value: entry.value.clone() });
+            }
+            if dirty_flags.contains(name) {
+                hello.push(Message::FlagsUpdate { id: entry.id, flags: entry.flags });
+            }
+        }
+        dirty.clear();
+        dirty_flags.clear();
+        shared.connected.store(true, Ordering::SeqCst);
+        state.connection = Some(info.clone());
+        let _ = state.events.send(Event::Connection(ConnectionEvent::Connected(info)));
+    }
+    hello.push(Message::ClientHelloComplete);
+    conn.send(&hello)?;
+
+    while shared.is_running() && !shared.reconnect.load(Ordering::SeqCst) {
+        let outgoing = mem::take(&mut shared.lock().outgoing);
+        if !outgoing.is_empty() {
+            conn.send(&outgoing)?;
+        } else if conn.last_sent.elapsed() >= KEEP_ALIVE_INTERVAL {
+            conn.send(&[Message::KeepAlive])?;
+        }
+
+        let msgs = conn.receive()?;
+        if !msgs.is_empty() {
+            let mut state = shared.lock();
+            for msg in msgs { state.handle_message(msg); }
+        }
+    }
+
+    Ok(())
+}
+
+fn sleep_while_running(shared: &Shared, duration: Duration) {
+    let deadline = Instant::now() + duration;
+    while shared.is_running() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+fn run(shared: Arc<Shared>) {
+    let mut attempt = 0;
+    while shared.is_running() {
+        // Servers changed before this point are picked up below.
+        shared.reconnect.store(false, Ordering::SeqCst);
+        let (servers, identity) = {
+            let state = shared.lock();
+            (state.servers.clone(), state.identity.clone())
+        };
+
+        // Cycle through the servers on every attempt.
+        if !servers.is_empty() {
+            let (ref host, port) = servers[attempt % servers.len()];
+            attempt += 1;
+            if let Ok(stream) = connect(host, port) {
+                // Any error just means we have to reconnect.
+                let _ = run_connection(&shared, stream, identity);
+                let mut state = shared.lock();
+                shared.connected.store(false, Ordering::SeqCst);
+                state.disconnect();
+            }
+        }
+
+        sleep_while_running(&shared, RECONNECT_DELAY);
+    }
+}
+
+/// A NetworkTables 3 client implemented in Rust. The connection is managed by a background thread,
+/// which reconnects whenever the connection drops, and is stopped when this is dropped.
+#[derive(Debug)]
+pub struct Client {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+    notifier: Notifier,
+}
+
+impl Client {
+    /// Panics if `port` doesn't fit in 16 bits. Use `try_start_client` to handle that case.
+    pub fn start_client(server_ip: Ipv4Addr, port: u32) -> Client {
+        Client::try_start_client(server_ip, port).expect("invalid server address")
+    }
+
+    pub fn try_start_client(server_ip: Ipv4Addr, port: u32) -> Result<Client> {
+        Client::try_start_client_multi(vec![(&server_ip.to_string(), port)])
+    }
+
+    /// Start a client that tries each of `servers` in turn until one of them accepts. Panics if a
+    /// port doesn't fit in 16 bits. Use `try_start_client_multi` to handle that case.
+    pub fn start_client_multi(servers: Vec<(&str, u32)>) -> Client {
+        Client::try_start_client_multi(servers).expect("invalid server address")
+    }
+
+    pub fn try_start_client_multi(servers: Vec<(&str, u32)>) -> Result<Client> {
+        let notifier = Notifier::new();
+        let state = State::new(network::parse_servers(&servers)?, notifier.sender());
+        let shared = Arc::new(Shared {
+            state: Mutex::new(state),
+            running: AtomicBool::new(true),
+            connected: AtomicBool::new(false),
+            reconnect: AtomicBool::new(false),
+        });
+
+        let thread_shared = shared.clone();
+        let thread = thread::spawn(move || run(thread_shared));
+        Ok(Client { shared, thread: Some(thread), notifier })
+    }
+
+    /// Set the name this client reports to the server. Takes effect on the next connection.
+    pub fn set_network_identity(&self, name: &str) {
+        self.shared.lock().identity = name.to_owned();
+    }
+
+    /// Get whether the client has finished the handshake with a server.
+    pub fn is_connected(&self) -> bool {
+        self.shared.connected.load(Ordering::SeqCst)
+    }
+
+    pub fn get_entry(&self, key: &str) -> ClientEntry {
+        ClientEntry { shared: self.shared.clone(), name: key.to_owned() }
+    }
+
+    pub fn get_all_entries(&self) -> Vec<ClientEntry> {
+        self.shared.lock().entries.keys().map(|name| self.get_entry(name)).collect()
+    }
+
+    fn entry_state<R, F: FnOnce(&EntryState) -> R>(&self, entry: Handle, func: F) -> Option<R> {
+        let name = self.notifier.name(entry)?;
+        self.shared.lock().entries.get(&name).map(func)
+    }
+}
+
+impl Backend for Client {
+    fn as_any(&self) -> &dyn Any { self }
+
+    fn set_network_identity(&self, name: &str) { Client::set_network_identity(self, name) }
+
+    fn start_server(&self, _persist_filename: &str, _listen_address: &str, _port: u32) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+    fn stop_server(&self) {}
+    fn start_client_none(&self) { self.shared.set_servers(Vec::new()) }
+    fn start_client(&self, servers: &[(&str, u32)]) -> Result<()> {
+        self.shared.set_servers(network::parse_servers(servers)?);
+        Ok(())
+    }
+    fn start_client_team(&self, team: u32, port: u32) { self.set_server_team(team, port) }
+    fn stop_client(&self) { self.shared.set_servers(Vec::new()) }
+    fn set_server(&self, servers: &[(&str, u32)]) -> Result<()> {
+        // Like ntcore, this takes effect on the next connection.
+        self.shared.lock().servers = network::parse_servers(servers)?;
+        Ok(())
+    }
+    fn set_server_team(&self, team: u32, port: u32) {
+        // There's no way to report a bad port here, so there's just nothing to connect to.
+        let servers = u16::try_from(port)
+            .map(|port| network::team_servers(team).into_iter().map(|host| (host, port)).collect())
+            .unwrap_or_default();
+        self.shared.lock().servers = servers;
+    }
+    // Changes are sent every `UPDATE_INTERVAL`, and there's no way to hurry them along.
+    fn set_update_rate(&self, _interval: f64) {}
+    fn flush(&self) {}
+
+    fn is_connected(&self) -> bool { Client::is_connected(self) }
+    fn connections(&self) -> Vec<OwnedConnectionInfo> { self.shared.lock().connection.iter().cloned().collect() }
+
+    fn entry(&self, name: &str) -> Handle { self.notifier.entry(name) }
+    fn entries(&self, prefix: &str, types: EntryMask) -> Result<Vec<Handle>> {
+        Ok(self.shared.values().into_iter()
+            .filter(|(name, value)| name.starts_with(prefix) && (types.0 == 0 || types.0 & value.entry_type() as u32 != 0))
+            .map(|(name, _)| self.notifier.entry(&name))
+            .collect())
+    }
+    fn entry_name(&self, entry: Handle) -> Vec<u8> {
+        self.notifier.name(entry).unwrap_or_default().into_bytes()
+    }
+    fn entry_type(&self, entry: Handle) -> Result<EntryType> {
+        Ok(self.entry_state(entry, |state| state.value.entry_type()).unwrap_or(EntryType::Unassigned))
+    }
+    fn entry_value(&self, entry: Handle) -> Option<Value> {
+        self.entry_state(entry, |state| state.value.clone())
+    }
+    fn set_entry_value(&self, entry: Handle, value: &Value) -> Result<()> {
+        match self.notifier.name(entry) {
+            Some(name) => self.shared.set_value(&name, value.clone()),
+            None => Err(Error::TypeMismatch { expected: EntryType::Unassigned, actual: value.entry_type() }),
+        }
+    }
+    fn set_default_entry_value(&self, entry: Handle, value: &Value) -> Result<bool> {
+        match self.notifier.name(entry) {
+            Some(name) => self.shared.set_default(&name, value.clone()),
+            None => Err(Error::TypeMismatch { expected: EntryType::Unassigned, actual: value.entry_type() }),
+        }
+    }
+    fn entry_flags(&self, entry: Handle) -> EntryFlags {
+        EntryFlags(self.entry_state(entry, |state| state.flags as u32).unwrap_or(0))
+    }
+    fn set_entry_flags(&self, entry: Handle, flags: EntryFlags) {
+        if let Some(name) = self.notifier.name(entry) {
+            self.shared.set_flags(&name, flags.0 as u8);
+        }
+    }
+    fn entry_last_change(&self, entry: Handle) -> NetworkTime {
+        self.entry_state(entry, |state| state.last_change).unwrap_or(NetworkTime(0))
+    }
+    fn delete_entry(&self, entry: Handle) {
+        if let Some(name) = self.notifier.name(entry) {
+            self.shared.delete(&name);
+        }
+    }
+    fn delete_all_entries(&self) {
+        // ntcore leaves persistent entries alone here.
+        let names: Vec<String> = self.shared.lock().entries.iter()
+            .filter(|&(_, entry)| !EntryFlags(entry.flags as u32).is_persistent())
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in names { self.shared.delete(&name); }
+    }
+
+    fn add_entry_listener(&self, prefix: &str, flags: NotifyFlags, callback: NotificationCallback) -> Handle {
+        self.notifier.add_entry_listener(Target::Prefix(prefix.to_owned()), flags, callback, || self.shared.values())
+    }
+    fn add_entry_listener_single(&self, entry: Handle, flags: NotifyFlags, callback: NotificationCallback) -> Handle {
+        self.notifier.add_entry_listener(Target::Entry(entry), flags, callback, || self.shared.values())
+    }
+    fn remove_entry_listener(&self, listener: Handle) { self.notifier.remove_entry_listener(listener) }
+    fn add_connection_listener(&self, immediate_notify: bool, callback: ConnectionCallback) -> Handle {
+        self.notifier.add_connection_listener(immediate_notify, callback, self.connections())
+    }
+    fn remove_connection_listener(&self, listener: Handle) { self.notifier.remove_connection_listener(listener) }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.shared.running.store(false, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// A handle to a possibly existant entry on a `Client`.
+#[derive(Clone, Debug)]
+pub struct ClientEntry {
+    shared: Arc<Shared>,
+    name: String,
+}
+
+impl ClientEntry {
+    pub fn name(&self) -> &str { &self.name }
+
+    pub fn entry_type(&self) -> EntryType {
+        self.shared.lock().entries.get(&self.name).map_or(EntryType::Unassigned, |entry| entry.value.entry_type())
+    }
+
+    pub fn exists(&self) -> bool {
+        self.shared.lock().entries.contains_key(&self.name)
+    }
+
+    pub fn flags(&self) -> EntryFlags {
+        EntryFlags(self.shared.lock().entries.get(&self.name).map_or(0, |entry| entry.flags as u32))
+    }
+
+    /// Get the value of this entry, if this entry does point to something.
+    pub fn value(&self) -> Option<Value> {
+        self.shared.lock().entries.get(&self.name).map(|entry| entry.value.clone())
+    }
+
+    /// Set the value of this entry. Fails if the entry already holds a value of a different type.
+    pub fn set<V: Into<Value>>(&self, value: V) -> Result<()> {
+        self.shared.set_value(&self.name, value.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use entry::Value;
+    use instance::Instance;
+    use nt3::server::Server;
+    use testing::wait_until;
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn start_server(port: u16) -> Server {
+        Server::start_server(String::new(), Ipv4Addr::LOCALHOST, port as u32).unwrap()
+    }
+
+    #[test]
+    fn values_replicate_both_ways() {
+        let server = start_server(0);
+        server.get_entry("/from_server").set(1.0).unwrap();
+        let client = Client::start_client(Ipv4Addr::LOCALHOST, server.local_addr().port() as u32);
+        assert!(wait_until(TIMEOUT, || client.is_connected()));
+        assert!(wait_until(TIMEOUT, || client.get_entry("/from_server").value() == Some(Value::Double(1.0))));
+
+        client.get_entry("/from_client").set(true).unwrap();
+        assert!(wait_until(TIMEOUT, || server.get_entry("/from_client").value() == Some(Value::Bool(true))));
+
+        server.get_entry("/from_server").set(2.0).unwrap();
+        assert!(wait_until(TIMEOUT, || client.get_entry("/from_server").value() == Some(Value::Double(2.0))));
+        client.get_entry("/from_server").set(3.0).unwrap();
+        assert!(wait_until(TIMEOUT, || server.get_entry("/from_server").value() == Some(Value::Double(3.0))));
+    }
+
+    #[test]
+    fn changes_made_while_disconnected_are_sent_on_reconnect() {
+        let server = start_server(0);
+        let port = server.local_addr().port();
+        server.get_entry("/a").set(1.0).unwrap();
+        let client = Client::start_client(Ipv4Addr::LOCALHOST, port as u32);
+        assert!(wait_until(TIMEOUT, || client.get_entry("/a").value() == Some(Value::Double(1.0))));
+
+        drop(server);
+        assert!(wait_until(TIMEOUT, || !client.is_connected()));
+        client.get_entry("/a").set(2.0).unwrap();
+        client.get_entry("/b").set(true).unwrap();
+
+        // The new server has its own value for `/a`, which the client's newer change replaces.
+        let server = start_server(port);
+        server.get_entry("/a").set(1.0).unwrap();
+        assert!(wait_until(TIMEOUT, || client.is_connected()));
+        assert!(wait_until(TIMEOUT, || server.get_entry("/a").value() == Some(Value::Double(2.0))));
+        assert!(wait_until(TIMEOUT, || server.get_entry("/b").value() == Some(Value::Bool(true))));
+        assert_eq!(client.get_entry("/a").value(), Some(Value::Double(2.0)));
+    }
+
+    #[test]
+    fn entries_keep_their_type() {
+        let client = Client::start_client_multi(vec![]);
+        client.get_entry("/a").set(1.0).unwrap();
+        assert_eq!(client.get_entry("/a").set(false),
+                   Err(Error::TypeMismatch { expected: EntryType::Double, actual: EntryType::Boolean }));
+    }
+
+    #[test]
+    fn ports_must_fit_in_16_bits() {
+        assert_eq!(Client::try_start_client(Ipv4Addr::LOCALHOST, 70000).err(),
+                   Some(Error::InvalidAddress("127.0.0.1:70000".to_owned())));
+    }
+
+    #[test]
+    fn works_as_an_instance_backend() {
+        let server = start_server(0);
+        server.get_entry("/from_server").set(1.0).unwrap();
+        let client = Client::start_client(Ipv4Addr::LOCALHOST, server.local_addr().port() as u32);
+        let inst = Instance::from_backend(Arc::new(client));
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let listener_changes = changes.clone();
+        let flags = NotifyFlags::NEW | NotifyFlags::UPDATE | NotifyFlags::DELETE | NotifyFlags::LOCAL;
+        let _listener = inst.add_entry_listener("/", flags, move |change| {
+            listener_changes.lock().unwrap().push((change.name, change.value, change.flags));
+        });
+
+        assert!(wait_until(TIMEOUT, || inst.get_entry("/from_server").value() == Some(Value::Double(1.0))));
+        assert!(inst.is_connected());
+        assert_eq!(inst.get_connections().len(), 1);
+        assert_eq!(inst.get_connections()[0].protocol_version(), PROTOCOL_REVISION as u32);
+
+        inst.get_entry("/from_client").set(true).unwrap();
+        inst.get_entry("/from_client").set_persistent();
+        assert!(wait_until(TIMEOUT, || server.get_entry("/from_client").flags().is_persistent()));
+        server.get_entry("/from_server").delete();
+        assert!(wait_until(TIMEOUT, || !inst.get_entry("/from_server").exists()));
+
+        let expected = vec![
+            ("/from_server".to_owned(), Some(Value::Double(1.0)), NotifyFlags::NEW),
+            ("/from_client".to_owned(), Some(Value::Bool(true)), NotifyFlags::NEW | NotifyFlags::LOCAL),
+            ("/from_server".to_owned(), Some(Value::Double(1.0)), NotifyFlags::DELETE),
+        ];
+        assert!(wait_until(TIMEOUT, || *changes.lock().unwrap() == expected));
+    }
+
+    #[test]
+    fn flags_changed_while_disconnected_are_sent_on_reconnect() {
+        let server = start_server(0);
+        let port = server.local_addr().port();
+        server.get_entry("/a").set(1.0).unwrap();
+        let client = Client::start_client(Ipv4Addr::LOCALHOST, port as u32);
+        let entry = client.entry("/a");
+        assert!(wait_until(TIMEOUT, || client.entry_value(entry).is_some()));
+
+        drop(server);
+        assert!(wait_until(TIMEOUT, || !client.is_connected()));
+        client.set_entry_flags(entry, EntryFlags::PERSISTENT);
+
+        let server = start_server(port);
+        server.get_entry("/a").set(1.0).unwrap();
+        assert!(wait_until(TIMEOUT, || server.get_entry("/a").flags().is_persistent()));
+        assert!(client.entry_flags(entry).is_persistent());
+    }
+
+    #[test]
+    fn clients_cannot_serve() {
+        let client = Client::start_client_multi(vec![]);
+        assert_eq!(client.start_server("", "0.0.0.0", 0), Err(Error::Unsupported));
+    }
+}
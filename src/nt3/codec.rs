@@ -0,0 +1,399 @@
+//! Encoding and decoding of NetworkTables 3 messages. Each message starts with a one byte message
+//! type, followed by fields that depend on the type:
+//!
+//! | Type   | Message                | Fields                                                |
+//! |--------|------------------------|-------------------------------------------------------|
+//! | `0x00` | `KeepAlive`            |                                                       |
+//! | `0x01` | `ClientHello`          | revision (u16), identity (string, revision >= 3.0)    |
+//! | `0x02` | `ProtocolUnsupported`  | server revision (u16)                                 |
+//! | `0x03` | `ServerHelloComplete`  |                                                       |
+//! | `0x04` | `ServerHello`          | flags (u8), identity (string)                         |
+//! | `0x05` | `ClientHelloComplete`  |                                                       |
+//! | `0x10` | `EntryAssignment`      | name (string), type, id (u16), seq (u16), flags, value |
+//! | `0x11` | `EntryUpdate`          | id (u16), seq (u16), type, value                      |
+//! | `0x12` | `FlagsUpdate`          | id (u16), flags (u8)                                  |
+//! | `0x13` | `EntryDelete`          | id (u16)                                              |
+//! | `0x14` | `ClearAllEntries`      | magic (u32)                                           |
+//! | `0x20` | `RpcExecute`           | id (u16), call uid (u16), params (raw)                |
+//! | `0x21` | `RpcResponse`          | id (u16), call uid (u16), result (raw)                |
+
+use std::error;
+use std::fmt;
+use entry::Value;
+use wire::{self, Reader, WireError};
+
+/// The magic number that has to accompany a `ClearAllEntries` message.
+pub const CLEAR_ALL_MAGIC: u32 = 0xD06C_B27A;
+
+/// The id a client uses in an `EntryAssignment` for an entry it created, before the server has
+/// assigned it a real id.
+pub const UNASSIGNED_ID: u16 = 0xFFFF;
+
+const KEEP_ALIVE: u8 = 0x00;
+const CLIENT_HELLO: u8 = 0x01;
+const PROTOCOL_UNSUPPORTED: u8 = 0x02;
+const SERVER_HELLO_COMPLETE: u8 = 0x03;
+const SERVER_HELLO: u8 = 0x04;
+const CLIENT_HELLO_COMPLETE: u8 = 0x05;
+const ENTRY_ASSIGNMENT: u8 = 0x10;
+const ENTRY_UPDATE: u8 = 0x11;
+const FLAGS_UPDATE: u8 = 0x12;
+const ENTRY_DELETE: u8 = 0x13;
+const CLEAR_ALL_ENTRIES: u8 = 0x14;
+const RPC_EXECUTE: u8 = 0x20;
+const RPC_RESPONSE: u8 = 0x21;
+
+/// Flag in `ServerHello` that is set when the server has seen the client's identity before.
+pub const SERVER_HELLO_REFERENCE: u8 = 0x01;
+
+/// A single NetworkTables 3 message. The type of an entry's value is implied by the `Value`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    KeepAlive,
+    ClientHello { protocol_revision: u16, identity: String },
+    ProtocolUnsupported { protocol_revision: u16 },
+    ServerHelloComplete,
+    ServerHello { flags: u8, identity: String },
+    ClientHelloComplete,
+    EntryAssignment { name: String, id: u16, seq: u16, flags: u8, value: Value },
+    EntryUpdate { id: u16, seq: u16, value: Value },
+    FlagsUpdate { id: u16, flags: u8 },
+    EntryDelete { id: u16 },
+    ClearAllEntries,
+    RpcExecute { id: u16, call_uid: u16, params: Vec<u8> },
+    RpcResponse { id: u16, call_uid: u16, result: Vec<u8> },
+}
+
+/// Ways a message can fail to decode. Running out of data is not an error; see `Message::decode`.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum DecodeError {
+    UnknownMessage(u8),
+    UnknownType(u8),
+    InvalidUtf8,
+    /// A `ClearAllEntries` message had the wrong magic number.
+    BadMagic(u32),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::UnknownMessage(ty) => write!(f, "unknown message type 0x{:02x}", ty),
+            DecodeError::UnknownType(ty) => write!(f, "unknown value type 0x{:02x}", ty),
+            DecodeError::InvalidUtf8 => write!(f, "string is not valid UTF-8"),
+            DecodeError::BadMagic(magic) => write!(f, "bad clear all magic 0x{:08x}", magic),
+        }
+    }
+}
+
+impl error::Error for DecodeError {}
+
+/// Turns wire errors into either "need more data" (`Ok(None)`) or a real error.
+fn incomplete<T>(err: WireError) -> Result<Option<T>, DecodeError> {
+    match err {
+        WireError::UnexpectedEof => Ok(None),
+        WireError::UnknownType(ty) => Err(DecodeError::UnknownType(ty)),
+        WireError::InvalidUtf8 => Err(DecodeError::InvalidUtf8),
+    }
+}
+
+fn write_typed_value(out: &mut Vec<u8>, value: &Value) {
+    // Values always have a type, so this never falls back.
+    out.push(wire::type_id(value.entry_type()).unwrap_or(0));
+    wire::write_value(out, value);
+}
+
+impl Message {
+    /// Append the encoded message to `out`.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        match *self {
+            Message::KeepAlive => out.push(KEEP_ALIVE),
+            Message::ClientHello { protocol_revision, ref identity } => {
+                out.push(CLIENT_HELLO);
+                wire::write_u16(out, protocol_revision);
+                if protocol_revision >= 0x0300 { wire::write_string(out, identity); }
+            }
+            Message::ProtocolUnsupported { protocol_revision } => {
+                out.push(PROTOCOL_UNSUPPORTED);
+                wire::write_u16(out, protocol_revision);
+            }
+            Message::ServerHelloComplete => out.push(SERVER_HELLO_COMPLETE),
+            Message::ServerHello { flags, ref identity } => {
+                out.push(SERVER_HELLO);
+                out.push(flags);
+                wire::write_string(out, identity);
+            }
+            Message::ClientHelloComplete => out.push(CLIENT_HELLO_COMPLETE),
+            Message::EntryAssignment { ref name, id, seq, flags, ref value } => {
+                out.push(ENTRY_ASSIGNMENT);
+                wire::write_string(out, name);
+                out.push(wire::type_id(value.entry_type()).unwrap_or(0));
+                wire::write_u16(out, id);
+                wire::write_u16(out, seq);
+                out.push(flags);
+                wire::write_value(out, value);
+            }
+            Message::EntryUpdate { id, seq, ref value } => {
+                out.push(ENTRY_UPDATE);
+                wire::write_u16(out, id);
+                wire::write_u16(out, seq);
+                write_typed_value(out, value);
+            }
+            Message::FlagsUpdate { id, flags } => {
+                out.push(FLAGS_UPDATE);
+                wire::write_u16(out, id);
+                out.push(flags);
+            }
+            Message::EntryDelete { id } => {
+                out.push(ENTRY_DELETE);
+                wire::write_u16(out, id);
+            }
+            Message::ClearAllEntries => {
+                out.push(CLEAR_ALL_ENTRIES);
+                wire::write_u32(out, CLEAR_ALL_MAGIC);
+            }
+            Message::RpcExecute { id, call_uid, ref params } => {
+                out.push(RPC_EXECUTE);
+                wire::write_u16(out, id);
+                wire::write_u16(out, call_uid);
+                wire::write_bytes(out, params);
+            }
+            Message::RpcResponse { id, call_uid, ref result } => {
+                out.push(RPC_RESPONSE);
+                wire::write_u16(out, id);
+                wire::write_u16(out, call_uid);
+                wire::write_bytes(out, result);
+            }
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode(&mut out);
+        out
+    }
+
+    /// Decode the message at the start of `buf`. Returns the message and the number of bytes it
+    /// took up, or `None` if `buf` ends before the message does.
+    pub fn decode(buf: &[u8]) -> Result<Option<(Message, usize)>, DecodeError> {
+        let mut reader = Reader::new(buf);
+        match Message::read(&mut reader) {
+            Ok(msg) => Ok(Some((msg, reader.position()))),
+            Err(ReadError::Wire(err)) => incomplete(err),
+            Err(ReadError::Decode(err)) => Err(err),
+        }
+    }
+
+    fn read(reader: &mut Reader) -> Result<Message, ReadError> {
+        Ok(match reader.read_u8()? {
+            KEEP_ALIVE => Message::KeepAlive,
+            CLIENT_HELLO => {
+                let protocol_revision = reader.read_u16()?;
+                // Identities were added in 3.0.
+                let identity = if protocol_revision >= 0x0300 { reader.read_string()? } else { String::new() };
+                Message::ClientHello { protocol_revision, identity }
+            }
+            PROTOCOL_UNSUPPORTED => Message::ProtocolUnsupported { protocol_revision: reader.read_u16()? },
+            SERVER_HELLO_COMPLETE => Message::ServerHelloComplete,
+            SERVER_HELLO => {
+                let flags = reader.read_u8()?;
+                Message::ServerHello { flags, identity: reader.read_string()? }
+            }
+            CLIENT_HELLO_COMPLETE => Message::ClientHelloComplete,
+            ENTRY_ASSIGNMENT => {
+                let name = reader.read_string()?;
+                let ty = reader.read_type()?;
+                let id = reader.read_u16()?;
+                let seq = reader.read_u16()?;
+                let flags = reader.read_u8()?;
+                Message::EntryAssignment { name, id, seq, flags, value: reader.read_value(ty)? }
+            }
+            ENTRY_UPDATE => {
+                let id = reader.read_u16()?;
+                let seq = reader.read_u16()?;
+                let ty = reader.read_type()?;
+                Message::EntryUpdate { id, seq, value: reader.read_value(ty)? }
+            }
+            FLAGS_UPDATE => {
+                let id = reader.read_u16()?;
+                Message::FlagsUpdate { id, flags: reader.read_u8()? }
+            }
+            ENTRY_DELETE => Message::EntryDelete { id: reader.read_u16()? },
+            CLEAR_ALL_ENTRIES => {
+                let magic = reader.read_u32()?;
+                if magic != CLEAR_ALL_MAGIC { return Err(ReadError::Decode(DecodeError::BadMagic(magic))); }
+                Message::ClearAllEntries
+            }
+            RPC_EXECUTE => {
+                let id = reader.read_u16()?;
+                let call_uid = reader.read_u16()?;
+                Message::RpcExecute { id, call_uid, params: reader.read_bytes()?.to_owned() }
+            }
+            RPC_RESPONSE => {
+                let id = reader.read_u16()?;
+                let call_uid = reader.read_u16()?;
+                Message::RpcResponse { id, call_uid, result: reader.read_bytes()?.to_owned() }
+            }
+            ty => return Err(ReadError::Decode(DecodeError::UnknownMessage(ty))),
+        })
+    }
+}
+
+enum ReadError {
+    Wire(WireError),
+    Decode(DecodeError),
+}
+
+impl From<WireError> for ReadError {
+    fn from(err: WireError) -> Self { ReadError::Wire(err) }
+}
+
+/// Buffers a stream of bytes, like what comes out of a socket, and splits it into messages.
+#[derive(Clone, Debug, Default)]
+pub struct Decoder {
+    buf: Vec<u8>,
+}
+
+impl Decoder {
+    pub fn new() -> Self { Decoder::default() }
+
+    /// Add bytes that were received to the end of the buffer.
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Take the next complete message out of the buffer, if there is one. After an error the
+    /// stream can't be resynchronized, so the connection should be dropped.
+    pub fn next_message(&mut self) -> Result<Option<Message>, DecodeError> {
+        Ok(Message::decode(&self.buf)?.map(|(msg, len)| {
+            self.buf.drain(..len);
+            msg
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nt3::SequenceNumber;
+
+    /// Every message type with its encoding, byte for byte.
+    fn fixtures() -> Vec<(Message, Vec<u8>)> {
+        vec![
+            (Message::KeepAlive, vec![0x00]),
+            (Message::ClientHello { protocol_revision: 0x0300, identity: "rio".into() },
+             vec![0x01, 0x03, 0x00, 0x03, b'r', b'i', b'o']),
+            (Message::ClientHello { protocol_revision: 0x0200, identity: String::new() }, vec![0x01, 0x02, 0x00]),
+            (Message::ProtocolUnsupported { protocol_revision: 0x0300 }, vec![0x02, 0x03, 0x00]),
+            (Message::ServerHelloComplete, vec![0x03]),
+            (Message::ServerHello { flags: SERVER_HELLO_REFERENCE, identity: "ds".into() },
+             vec![0x04, 0x01, 0x02, b'd', b's']),
+            (Message::ClientHelloComplete, vec![0x05]),
+            (Message::EntryAssignment { name: "/a".into(), id: 0x1234, seq: 0x0001, flags: 0x01, value: Value::Bool(true) },
+             vec![0x10, 0x02, b'/', b'a', 0x00, 0x12, 0x34, 0x00, 0x01, 0x01, 0x01]),
+            (Message::EntryAssignment { name: "/d".into(), id: UNASSIGNED_ID, seq: 0, flags: 0, value: Value::Double(1.5) },
+             vec![0x10, 0x02, b'/', b'd', 0x01, 0xff, 0xff, 0x00, 0x00, 0x00, 0x3f, 0xf8, 0, 0, 0, 0, 0, 0]),
+            (Message::EntryUpdate { id: 7, seq: 0xffff, value: Value::String("hi".into()) },
+             vec![0x11, 0x00, 0x07, 0xff, 0xff, 0x02, 0x02, b'h', b'i']),
+            (Message::EntryUpdate { id: 7, seq: 2, value: Value::Raw(vec![0xde, 0xad]) },
+             vec![0x11, 0x00, 0x07, 0x00, 0x02, 0x03, 0x02, 0xde, 0xad]),
+            (Message::EntryUpdate { id: 1, seq: 3, value: Value::BoolArray(vec![true, false]) },
+             vec![0x11, 0x00, 0x01, 0x00, 0x03, 0x10, 0x02, 0x01, 0x00]),
+            (Message::EntryUpdate { id: 1, seq: 4, value: Value::DoubleArray(vec![-2.0]) },
+             vec![0x11, 0x00, 0x01, 0x00, 0x04, 0x11, 0x01, 0xc0, 0x00, 0, 0, 0, 0, 0, 0]),
+            (Message::EntryUpdate { id: 1, seq: 5, value: Value::StringArray(vec!["x".into(), String::new()]) },
+             vec![0x11, 0x00, 0x01, 0x00, 0x05, 0x12, 0x02, 0x01, b'x', 0x00]),
+            (Message::EntryUpdate { id: 1, seq: 6, value: Value::Rpc(vec![0x01]) },
+             vec![0x11, 0x00, 0x01, 0x00, 0x06, 0x20, 0x01, 0x01]),
+            (Message::FlagsUpdate { id: 0x0102, flags: 0x01 }, vec![0x12, 0x01, 0x02, 0x01]),
+            (Message::EntryDelete { id: 0x0102 }, vec![0x13, 0x01, 0x02]),
+            (Message::ClearAllEntries, vec![0x14, 0xd0, 0x6c, 0xb2, 0x7a]),
+            (Message::RpcExecute { id: 3, call_uid: 9, params: vec![1, 2, 3] },
+             vec![0x20, 0x00, 0x03, 0x00, 0x09, 0x03, 1, 2, 3]),
+            (Message::RpcResponse { id: 3, call_uid: 9, result: vec![] }, vec![0x21, 0x00, 0x03, 0x00, 0x09, 0x00]),
+        ]
+    }
+
+    #[test]
+    fn messages_match_fixtures() {
+        for (msg, bytes) in fixtures() {
+            assert_eq!(msg.to_bytes(), bytes, "encoding {:?}", msg);
+            assert_eq!(Message::decode(&bytes), Ok(Some((msg.clone(), bytes.len()))), "decoding {:?}", msg);
+        }
+    }
+
+    #[test]
+    fn truncated_messages_need_more_data() {
+        for (msg, bytes) in fixtures() {
+            for len in 0..bytes.len() {
+                assert_eq!(Message::decode(&bytes[..len]), Ok(None), "{:?} cut to {} bytes", msg, len);
+            }
+        }
+    }
+
+    #[test]
+    fn decoder_splits_a_stream() {
+        let fixtures = fixtures();
+        let stream = fixtures.iter().flat_map(|(_, bytes)| bytes.iter().cloned()).collect::<Vec<_>>();
+
+        // Feed the stream in awkward pieces, so messages are split across pushes.
+        let mut decoder = Decoder::new();
+        let mut decoded = Vec::new();
+        for chunk in stream.chunks(3) {
+            decoder.push(chunk);
+            while let Some(msg) = decoder.next_message().unwrap() {
+                decoded.push(msg);
+            }
+        }
+        assert_eq!(decoded, fixtures.into_iter().map(|(msg, _)| msg).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn string_lengths_are_uleb128() {
+        for &(len, ref prefix) in &[(0, vec![0x00]), (127, vec![0x7f]), (128, vec![0x80, 0x01]), (300, vec![0xac, 0x02]),
+                                    (16384, vec![0x80, 0x80, 0x01])] {
+            let name = "n".repeat(len);
+            let assignment = Message::EntryAssignment { name, id: 0, seq: 0, flags: 0, value: Value::Bool(false) };
+            let bytes = assignment.to_bytes();
+            assert_eq!(&bytes[1..1 + prefix.len()], &prefix[..], "length {}", len);
+            assert_eq!(bytes.len(), 1 + prefix.len() + len + 7);
+            assert_eq!(Message::decode(&bytes), Ok(Some((assignment, bytes.len()))));
+        }
+    }
+
+    #[test]
+    fn arrays_are_cut_at_255_elements() {
+        let msg = Message::EntryUpdate { id: 0, seq: 0, value: Value::BoolArray(vec![true; 300]) };
+        let (decoded, _) = Message::decode(&msg.to_bytes()).unwrap().unwrap();
+        assert_eq!(decoded, Message::EntryUpdate { id: 0, seq: 0, value: Value::BoolArray(vec![true; 255]) });
+    }
+
+    #[test]
+    fn clear_all_needs_the_magic() {
+        assert_eq!(CLEAR_ALL_MAGIC, 0xD06CB27A);
+        assert_eq!(Message::decode(&[0x14, 0xd0, 0x6c, 0xb2, 0x7b]), Err(DecodeError::BadMagic(0xD06CB27B)));
+        assert_eq!(Message::decode(&[0x14, 0, 0, 0, 0]), Err(DecodeError::BadMagic(0)));
+    }
+
+    #[test]
+    fn bad_input_is_an_error() {
+        assert_eq!(Message::decode(&[0x06]), Err(DecodeError::UnknownMessage(0x06)));
+        assert_eq!(Message::decode(&[0x11, 0, 0, 0, 0, 0x04]), Err(DecodeError::UnknownType(0x04)));
+        assert_eq!(Message::decode(&[0x04, 0x00, 0x02, 0xc3, 0x28]), Err(DecodeError::InvalidUtf8));
+    }
+
+    #[test]
+    fn sequence_numbers_wrap_around() {
+        let max = SequenceNumber(0xffff);
+        assert_eq!(max.next(), SequenceNumber(0));
+        assert!(max.next().is_newer_than(max));
+        assert!(!max.is_newer_than(max.next()));
+        assert!(SequenceNumber(0x7fff).is_newer_than(SequenceNumber(0)));
+        assert!(!SequenceNumber(0x8000).is_newer_than(SequenceNumber(0)));
+        assert!(SequenceNumber(0).is_newer_than(SequenceNumber(0x8001)));
+        assert!(!SequenceNumber(5).is_newer_than(SequenceNumber(5)));
+
+        // The wrapped number survives the trip over the wire.
+        let msg = Message::EntryUpdate { id: 1, seq: max.next().0, value: Value::Bool(true) };
+        assert_eq!(Message::decode(&msg.to_bytes()).unwrap().unwrap().0, msg);
+    }
+}
@@ -0,0 +1,44 @@
+//! A pure-Rust implementation of the NetworkTables 3 protocol, for talking to other nodes without
+//! linking against ntcore.
+
+use std::io::{self, Read};
+use std::net::TcpStream;
+use self::codec::{Decoder, Message};
+
+pub mod client;
+pub mod codec;
+pub mod server;
+
+/// The protocol revision implemented here, as sent in `ClientHello` messages.
+pub const PROTOCOL_REVISION: u16 = 0x0300;
+
+/// Sequence numbers of entry values. They wrap around, so which of two numbers is newer depends on
+/// the distance between them rather than which is larger.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct SequenceNumber(pub u16);
+
+impl SequenceNumber {
+    pub fn next(self) -> SequenceNumber { SequenceNumber(self.0.wrapping_add(1)) }
+
+    pub fn is_newer_than(self, other: SequenceNumber) -> bool {
+        self.0 != other.0 && self.0.wrapping_sub(other.0) < 0x8000
+    }
+}
+
+/// Wait for data on a socket with a read timeout, and return every complete message received. A
+/// timeout just gives back no messages.
+pub(crate) fn receive(stream: &mut TcpStream, decoder: &mut Decoder) -> io::Result<Vec<Message>> {
+    let mut buf = [0; 4096];
+    match stream.read(&mut buf) {
+        Ok(0) => return Err(io::Error::new(io::ErrorKind::ConnectionAborted, "connection closed by peer")),
+        Ok(len) => decoder.push(&buf[..len]),
+        Err(ref err) if err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::TimedOut => {}
+        Err(err) => return Err(err),
+    }
+
+    let mut msgs = Vec::new();
+    while let Some(msg) = decoder.next_message().map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))? {
+        msgs.push(msg);
+    }
+    Ok(msgs)
+}
@@ -0,0 +1,925 @@
+//! A NetworkTables 3 server that accepts clients over plain TCP on background threads, and keeps
+//! persistent entries in a file like ntcore does.
+//!
+//! ```rs
+//! let server = Server::start_server("networktables.ini".to_owned(), Ipv4Addr::new(0, 0, 0, 0), 1735)?;
+//! server.get_entry("/Shooter/ready").set(true)?;
+//! println!("{:?}", server.get_entry("/Shooter/speed").value());
+//! ```
+//!
+//! A server is also a `Backend`, so it can be put under an `Instance` with `Instance::from_backend`.
+//! It can't be made into a client.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::net::{Ipv4Addr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use backend::network::{notify, Event, EventSender, Notifier};
+use backend::{Backend, ConnectionCallback, Handle, NotificationCallback, Target};
+use connection::{ConnectionEvent, OwnedConnectionInfo};
+use entry::{EntryFlags, EntryMask, EntryType, Value};
+use instance::LoadWarning;
+use listener::NotifyFlags;
+use nt3::codec::{Decoder, Message, SERVER_HELLO_REFERENCE, UNASSIGNED_ID};
+use nt3::{self, SequenceNumber, PROTOCOL_REVISION};
+use persistent;
+use ::{now, Error, NetworkTime, Result};
+
+/// How long client threads wait for data before checking whether the server was stopped.
+const UPDATE_INTERVAL: Duration = Duration::from_millis(100);
+/// A keep alive is sent when nothing else has been sent for this long.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(1);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often the persistent file is rewritten if a persistent entry changed.
+const PERSIST_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+struct EntryState {
+    name: String,
+    seq: SequenceNumber,
+    flags: u8,
+    value: Value,
+    /// Local time of the last change to the value.
+    last_change: NetworkTime,
+}
+
+impl EntryState {
+    fn new(name: String, flags: u8, value: Value) -> EntryState {
+        EntryState { name, seq: SequenceNumber(0), flags, value, last_change: now() }
+    }
+
+    fn is_persistent(&self) -> bool {
+        EntryFlags(self.flags as u32).is_persistent()
+    }
+
+    fn assignment(&self, id: u16) -> Message {
+        Message::EntryAssignment {
+            name: self.name.clone(),
+            id,
+            seq: self.seq.0,
+            flags: self.flags,
+            value: self.value.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ClientState {
+    /// Messages for the client's writer thread.
+    sender: Sender<Message>,
+    /// Kept so the connection can be shut down when the server stops.
+    stream: TcpStream,
+    info: OwnedConnectionInfo,
+}
+
+#[derive(Debug)]
+struct State {
+    identity: String,
+    entries: HashMap<u16, EntryState>,
+    ids: HashMap<String, u16>,
+    next_id: u16,
+    clients: HashMap<usize, ClientState>,
+    next_client: usize,
+    /// Identities of every client that has connected, for the `ServerHello` reference flag.
+    seen_identities: Vec<String>,
+    /// A persistent entry changed since the file was last written.
+    persist_dirty: bool,
+    /// Where changes are reported, for listeners.
+    events: EventSender,
+}
+
+impl State {
+    fn new(events: EventSender) -> State {
+        State {
+            identity: String::new(),
+            entries: HashMap::new(),
+            ids: HashMap::new(),
+            next_id: 0,
+            clients: HashMap::new(),
+            next_client: 0,
+            seen_identities: Vec::new(),
+            persist_dirty: false,
+            events,
+        }
+    }
+
+    /// Send `msg` to every connected client except `except`.
+    fn broadcast(&self, msg: &Message, except: Option<usize>) {
+        for (&id, client) in &self.clients {
+            if Some(id) != except {
+                // A closed channel means the client is going away, which is cleaned up elsewhere.
+                let _ = client.sender.send(msg.clone());
+            }
+        }
+    }
+
+    fn allocate_id(&mut self) -> u16 {
+        loop {
+            let id = self.next_id;
+            self.next_id = self.next_id.wrapping_add(1);
+            if id != UNASSIGNED_ID && !self.entries.contains_key(&id) { return id; }
+        }
+    }
+
+    /// Create a new entry and tell every client about it. `origin` is `NotifyFlags::LOCAL` for
+    /// changes made through the server itself, here and below.
+    fn assign(&mut self, name: String, flags: u8, value: Value, origin: NotifyFlags) {
+        let id = self.allocate_id();
+        notify(&self.events, &name, Some(value.clone()), NotifyFlags::NEW | origin);
+        let entry = EntryState::new(name.clone(), flags, value);
+        self.persist_dirty |= entry.is_persistent();
+        self.broadcast(&entry.assignment(id), None);
+        self.ids.insert(name, id);
+        self.entries.insert(id, entry);
+    }
+
+    /// Change the value of an existing entry. Returns false if the update was stale or changed the
+    /// type of the entry.
+    fn update(&mut self, id: u16, seq: SequenceNumber, value: Value, origin: NotifyFlags) -> bool {
+        if let Some(entry) = self.entries.get_mut(&id) {
+            if seq.is_newer_than(entry.seq) && value.entry_type() == entry.value.entry_type() {
+                entry.seq = seq;
+                if entry.value != value {
+                    entry.value = value;
+                    entry.last_change = now();
+                    notify(&self.events, &entry.name, Some(entry.value.clone()), NotifyFlags::UPDATE | origin);
+                }
+                self.persist_dirty |= entry.is_persistent();
+                return true;
+            }
+        }
+        false
+    }
+
+    fn set_flags(&mut self, id: u16, flags: u8, origin: NotifyFlags) -> bool {
+        if let Some(entry) = self.entries.get_mut(&id) {
+            if entry.flags != flags {
+                // Both setting and clearing the persistent flag change what goes in the file.
+                self.persist_dirty |= entry.is_persistent() || EntryFlags(flags as u32).is_persistent();
+                entry.flags = flags;
+                notify(&self.events, &entry.name, Some(entry.value.clone()), NotifyFlags::FLAGS | origin);
+                return true;
+            }
+        }
+        false
+    }
+
+    fn delete(&mut self, id: u16, origin: NotifyFlags) -> bool {
+        match self.entries.remove(&id) {
+            Some(entry) => {
+                self.persist_dirty |= entry.is_persistent();
+                self.ids.remove(&entry.name);
+                notify(&self.events, &entry.name, Some(entry.value), NotifyFlags::DELETE | origin);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn clear(&mut self, origin: NotifyFlags) {
+        self.persist_dirty |= self.entries.values().any(EntryState::is_persistent);
+        for (_, entry) in self.entries.drain() {
+            notify(&self.events, &entry.name, Some(entry.value), NotifyFlags::DELETE | origin);
+        }
+        self.ids.clear();
+    }
+
+    fn handle_message(&mut self, client: usize, msg: Message) {
+        // Changes that were applied are passed on to every other client.
+        let relay = match msg {
+            Message::EntryAssignment { name, id: UNASSIGNED_ID, flags, value, .. } => {
+                match self.ids.get(&name).cloned() {
+                    // Someone else created the entry first, so the client gets told the current
+                    // value along with the id. It answers with an update if it wants to win.
+                    Some(id) => if let Some(client) = self.clients.get(&client) {
+                        let _ = client.sender.send(self.entries[&id].assignment(id));
+                    },
+                    None => self.assign(name, flags, value, NotifyFlags::NONE),
+                }
+                None
+            }
+            Message::EntryUpdate { id, seq, value } => {
+                self.update(id, SequenceNumber(seq), value.clone(), NotifyFlags::NONE).then_some(Message::EntryUpdate { id, seq, value })
+            }
+            Message::FlagsUpdate { id, flags } => self.set_flags(id, flags, NotifyFlags::NONE).then_some(Message::FlagsUpdate { id, flags }),
+            Message::EntryDelete { id } => self.delete(id, NotifyFlags::NONE).then_some(Message::EntryDelete { id }),
+            Message::ClearAllEntries => {
+                self.clear(NotifyFlags::NONE);
+                Some(Message::ClearAllEntries)
+            }
+            // Only the server assigns ids, keep alives need no answer, and RPCs aren't supported
+            // by this server.
+            _ => None,
+        };
+
+        if let Some(msg) = relay {
+            self.broadcast(&msg, Some(client));
+        }
+    }
+
+    /// The persistent entries, sorted by name like ntcore writes them.
+    fn persistent_entries(&self) -> Vec<(String, Value)> {
+        let mut entries: Vec<_> = self.entries.values()
+            .filter(|entry| entry.is_persistent())
+            .map(|entry| (entry.name.clone(), entry.value.clone()))
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        entries
+    }
+
+    /// Forget about a client, shutting its connection down.
+    fn remove_client(&mut self, client: usize) {
+        if let Some(client) = self.clients.remove(&client) {
+            // Dropping the sender stops the writer thread.
+            let _ = client.stream.shutdown(Shutdown::Both);
+            let _ = self.events.send(Event::Connection(ConnectionEvent::Disconnected(client.info)));
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    state: Mutex<State>,
+    running: AtomicBool,
+    persist_filename: String,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // A panic on a network thread shouldn't take the robot code down with it.
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn is_running(&self) -> bool { self.running.load(Ordering::SeqCst) }
+
+    /// Write the persistent file, by way of a temporary file so a crash can't leave it truncated.
+    /// The previous file is kept with a `.bak` suffix, like ntcore does.
+    fn save(&self) -> io::Result<()> {
+        let entries = {
+            let mut state = self.lock();
+            state.persist_dirty = false;
+            state.persistent_entries()
+        };
+
+        let tmp = format!("{}.tmp", self.persist_filename);
+        {
+            let mut file = BufWriter::new(File::create(&tmp)?);
+            persistent::write(&mut file, &entries)?;
+            file.flush()?;
+        }
+        if let Err(err) = fs::rename(&self.persist_filename, format!("{}.bak", self.persist_filename)) {
+            if err.kind() != io::ErrorKind::NotFound { return Err(err); }
+        }
+        fs::rename(&tmp, &self.persist_filename)
+    }
+
+    fn set_value(&self, name: &str, value: Value) -> Result<()> {
+        let mut state = self.lock();
+        match state.ids.get(name).cloned() {
+            Some(id) => {
+                let (seq, expected) = {
+                    let entry = &state.entries[&id];
+                    if entry.value == value { return Ok(()); }
+                    (entry.seq.next(), entry.value.entry_type())
+                };
+                if expected != value.entry_type() {
+                    return Err(Error::TypeMismatch { expected, actual: value.entry_type() });
+                }
+                state.update(id, seq, value.clone(), NotifyFlags::LOCAL);
+                state.broadcast(&Message::EntryUpdate { id, seq: seq.0, value }, None);
+            }
+            None => state.assign(name.to_owned(), 0, value, NotifyFlags::LOCAL),
+        }
+        Ok(())
+    }
+
+    /// Set the value only if the entry doesn't have one, as one step.
+    fn set_default(&self, name: &str, value: Value) -> Result<bool> {
+        let mut state = self.lock();
+        match state.ids.get(name) {
+            Some(id) => {
+                let expected = state.entries[id].value.entry_type();
+                if expected != value.entry_type() {
+                    return Err(Error::TypeMismatch { expected, actual: value.entry_type() });
+                }
+                Ok(false)
+            }
+            None => {
+                state.assign(name.to_owned(), 0, value, NotifyFlags::LOCAL);
+                Ok(true)
+            }
+        }
+    }
+
+    fn set_flags(&self, name: &str, flags: u8) {
+        let mut state = self.lock();
+        if let Some(id) = state.ids.get(name).cloned() {
+            if state.set_flags(id, flags, NotifyFlags::LOCAL) {
+                state.broadcast(&Message::FlagsUpdate { id, flags }, None);
+            }
+        }
+    }
+
+    fn delete(&self, name: &str) {
+        let mut state = self.lock();
+        if let Some(id) = state.ids.get(name).cloned() {
+            state.delete(id, NotifyFlags::LOCAL);
+            state.broadcast(&Message::EntryDelete { id }, None);
+        }
+    }
+
+    fn values(&self) -> Vec<(String, Value)> {
+        self.lock().entries.values().map(|entry| (entry.name.clone(), entry.value.clone())).collect()
+    }
+}
+
+fn send_all(stream: &mut TcpStream, first: Message, receiver: &Receiver<Message>) -> io::Result<()> {
+    // Batch up whatever else is already queued into the same write.
+    let mut buf = first.to_bytes();
+    while let Ok(msg) = receiver.try_recv() { msg.encode(&mut buf); }
+    stream.write_all(&buf)
+}
+
+/// Writes queued messages to a client until the server drops the sending half of the channel.
+fn run_writer(mut stream: TcpStream, receiver: Receiver<Message>) {
+    loop {
+        let result = match receiver.recv_timeout(KEEP_ALIVE_INTERVAL) {
+            Ok(msg) => send_all(&mut stream, msg, &receiver),
+            Err(RecvTimeoutError::Timeout) => stream.write_all(&Message::KeepAlive.to_bytes()),
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+        if result.is_err() {
+            // The reader will notice too, and remove the client.
+            let _ = stream.shutdown(Shutdown::Both);
+            return;
+        }
+    }
+}
+
+fn serve_client(shared: &Shared, client: usize, mut stream: TcpStream) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(UPDATE_INTERVAL))?;
+    let mut decoder = Decoder::new();
+
+    // Clients start by saying hello, and anything before that is a protocol error.
+    let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+    let (protocol_revision, identity, early) = loop {
+        if !shared.is_running() { return Ok(()); }
+        if Instant::now() > deadline {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "client did not say hello"));
+        }
+
+        let mut msgs = nt3::receive(&mut stream, &mut decoder)?.into_iter();
+        match msgs.next() {
+            Some(Message::ClientHello { protocol_revision, identity }) => {
+                // Clients should wait for our reply before sending anything else, but whatever
+                // came in the same read is handled once the client is registered.
+                break (protocol_revision, identity, msgs.collect::<Vec<_>>());
+            }
+            Some(_) => return Err(io::Error::new(io::ErrorKind::InvalidData, "expected a client hello")),
+            None => {}
+        }
+    };
+
+    if protocol_revision < PROTOCOL_REVISION {
+        stream.write_all(&Message::ProtocolUnsupported { protocol_revision: PROTOCOL_REVISION }.to_bytes())?;
+        return Ok(());
+    }
+
+    let peer = stream.peer_addr()?;
+    let info = OwnedConnectionInfo::new(identity.clone(), peer.ip().to_string(), peer.port() as u32, now(),
+                                        protocol_revision as u32);
+    let (sender, receiver) = mpsc::channel();
+    let writer = stream.try_clone()?;
+    thread::spawn(move || run_writer(writer, receiver));
+
+    {
+        // Sending the current entries and registering for broadcasts under one lock means the
+        // client can't miss a change that happens in between.
+        let mut state = shared.lock();
+        let flags = if state.seen_identities.contains(&identity) { SERVER_HELLO_REFERENCE } else { 0 };
+        if flags == 0 { state.seen_identities.push(identity); }
+
+        let _ = sender.send(Message::ServerHello { flags, identity: state.identity.clone() });
+        for (&id, entry) in &state.entries {
+            let _ = sender.send(entry.assignment(id));
+        }
+        let _ = sender.send(Message::ServerHelloComplete);
+        state.clients.insert(client, ClientState { sender, stream: stream.try_clone()?, info: info.clone() });
+        let _ = state.events.send(Event::Connection(ConnectionEvent::Connected(info)));
+        for msg in early { state.handle_message(client, msg); }
+    }
+
+    while shared.is_running() {
+        let msgs = nt3::receive(&mut stream, &mut decoder)?;
+        if !msgs.is_empty() {
+            let mut state = shared.lock();
+            for msg in msgs { state.handle_message(client, msg); }
+        }
+    }
+
+    Ok(())
+}
+
+fn run_client(shared: Arc<Shared>, client: usize, stream: TcpStream) {
+    // Any error just means the client goes away.
+    let _ = serve_client(&shared, client, stream);
+    shared.lock().remove_client(client);
+}
+
+fn run_listener(shared: Arc<Shared>, listener: TcpListener) {
+    while shared.is_running() {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                // Accepted sockets shouldn't inherit the listener's nonblocking mode.
+                if stream.set_nonblocking(false).is_err() { continue; }
+                let client = {
+                    let mut state = shared.lock();
+                    state.next_client += 1;
+                    state.next_client
+                };
+                let client_shared = shared.clone();
+                thread::spawn(move || run_client(client_shared, client, stream));
+            }
+            Err(_) => thread::sleep(UPDATE_INTERVAL),
+        }
+    }
+}
+
+fn run_persister(shared: Arc<Shared>) {
+    let mut last_save = Instant::now();
+    while shared.is_running() {
+        thread::sleep(UPDATE_INTERVAL);
+        if last_save.elapsed() >= PERSIST_INTERVAL {
+            last_save = Instant::now();
+            if shared.lock().persist_dirty {
+                // Failures are retried next time, since the entries are still dirty.
+                if shared.save().is_err() { shared.lock().persist_dirty = true; }
+            }
+        }
+    }
+}
+
+/// Load the entries in a persistent file. Lines that can't be parsed are skipped and returned as
+/// warnings, like ntcore does. A missing file just means starting out with no entries, but a file
+/// that can't be read at all is an error, so that the next save doesn't replace it.
+fn load(persist_filename: &str, state: &mut State) -> Result<Vec<LoadWarning>> {
+    let contents = match fs::read_to_string(persist_filename) {
+        Ok(contents) => contents,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(Error::Persistence(format!("{}: {}", persist_filename, err))),
+    };
+
+    let mut warnings = Vec::new();
+    let entries = persistent::read_lenient(&contents, |err| warnings.push(LoadWarning { line: err.line, message: err.message }))
+        .map_err(|err| Error::Persistence(format!("{}:{}", persist_filename, err)))?;
+    let flags = EntryFlags::PERSISTENT.0 as u8;
+    for (name, value) in entries {
+        // Later lines for the same name win.
+        if let Some(&id) = state.ids.get(&name) {
+            state.entries.insert(id, EntryState::new(name, flags, value));
+        } else {
+            state.assign(name, flags, value, NotifyFlags::LOCAL);
+        }
+    }
+    state.persist_dirty = false;
+    Ok(warnings)
+}
+
+/// A NetworkTables 3 server implemented in Rust. Clients are served by background threads, which
+/// are stopped when this is dropped.
+#[derive(Debug)]
+pub struct Server {
+    shared: Arc<Shared>,
+    local_addr: SocketAddr,
+    load_warnings: Vec<LoadWarning>,
+    threads: Vec<JoinHandle<()>>,
+    notifier: Notifier,
+}
+
+impl Server {
+    /// Start a server listening on `listen_address:port`. Persistent entries are loaded from
+    /// `persist_filename`, and written back whenever they change. An empty filename turns
+    /// persistence off.
+    ///
+    /// Lines of the file that can't be parsed are skipped; see `load_warnings`. If the file exists
+    /// but can't be read at all, the server isn't started, rather than replacing the file later.
+    pub fn start_server(persist_filename: String, listen_address: Ipv4Addr, port: u32) -> Result<Server> {
+        let port = u16::try_from(port).map_err(|_| Error::InvalidAddress(format!("{}:{}", listen_address, port)))?;
+        let notifier = Notifier::new();
+        let mut state = State::new(notifier.sender());
+        let load_warnings = if persist_filename.is_empty() { Vec::new() } else { load(&persist_filename, &mut state)? };
+
+        let listener = TcpListener::bind((listen_address, port))?;
+        listener.set_nonblocking(true)?;
+        let local_addr = listener.local_addr()?;
+
+        let persist = !persist_filename.is_empty();
+        let shared = Arc::new(Shared {
+            state: Mutex::new(state),
+            running: AtomicBool::new(true),
+            persist_filename,
+        });
+
+        let mut threads = Vec::new();
+        let listener_shared = shared.clone();
+        threads.push(thread::spawn(move || run_listener(listener_shared, listener)));
+        if persist {
+            let persister_shared = shared.clone();
+            threads.push(thread::spawn(move || run_persister(persister_shared)));
+        }
+
+        Ok(Server { shared, local_addr, load_warnings, threads, notifier })
+    }
+
+    /// Get the address the server is listening on. Useful when it was started on port 0.
+    pub fn local_addr(&self) -> SocketAddr { self.local_addr }
+
+    /// The lines of the persistent file that were skipped when the server started.
+    pub fn load_warnings(&self) -> &[LoadWarning] { &self.load_warnings }
+
+    /// Set the name this server reports to clients. Takes effect for clients that connect later.
+    pub fn set_network_identity(&self, name: &str) {
+        self.shared.lock().identity = name.to_owned();
+    }
+
+    /// Get the number of clients that have finished the handshake.
+    pub fn client_count(&self) -> usize {
+        self.shared.lock().clients.len()
+    }
+
+    /// Write the persistent file now, rather than waiting for the next periodic save.
+    pub fn save_persistent(&self) -> Result<()> {
+        if self.shared.persist_filename.is_empty() {
+            return Err(Error::Persistence("persistence is turned off".to_owned()));
+        }
+        Ok(self.shared.save()?)
+    }
+
+    pub fn get_entry(&self, key: &str) -> ServerEntry {
+        ServerEntry { shared: self.shared.clone(), name: key.to_owned() }
+    }
+
+    pub fn get_all_entries(&self) -> Vec<ServerEntry> {
+        self.shared.lock().ids.keys().map(|name| self.get_entry(name)).collect()
+    }
+
+    fn entry_state<R, F: FnOnce(&EntryState) -> R>(&self, entry: Handle, func: F) -> Option<R> {
+        let name = self.notifier.name(entry)?;
+        let state = self.shared.lock();
+        state.ids.get(&name).map(|id| func(&state.entries[id]))
+    }
+}
+
+impl Backend for Server {
+    fn as_any(&self) -> &dyn Any { self }
+
+    fn set_network_identity(&self, name: &str) { Server::set_network_identity(self, name) }
+
+    // A server is a server for as long as it lives, so these either fail or do nothing.
+    fn start_server(&self, _persist_filename: &str, _listen_address: &str, _port: u32) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+    fn stop_server(&self) {}
+    fn start_client_none(&self) {}
+    fn start_client(&self, _servers: &[(&str, u32)]) -> Result<()> { Err(Error::Unsupported) }
+    fn start_client_team(&self, _team: u32, _port: u32) {}
+    fn stop_client(&self) {}
+    fn set_server(&self, _servers: &[(&str, u32)]) -> Result<()> { Err(Error::Unsupported) }
+    fn set_server_team(&self, _team: u32, _port: u32) {}
+    // Changes are queued for clients as soon as they're made.
+    fn set_update_rate(&self, _interval: f64) {}
+    fn flush(&self) {}
+
+    fn is_connected(&self) -> bool { self.client_count() > 0 }
+    fn connections(&self) -> Vec<OwnedConnectionInfo> {
+        self.shared.lock().clients.values().map(|client| client.info.clone()).collect()
+    }
+
+    fn entry(&self, name: &str) -> Handle { self.notifier.entry(name) }
+    fn entries(&self, prefix: &str, types: EntryMask) -> Result<Vec<Handle>> {
+        Ok(self.shared.values().into_iter()
+            .filter(|(name, value)| name.starts_with(prefix) && (types.0 == 0 || types.0 & value.entry_type() as u32 != 0))
+            .map(|(name, _)| self.notifier.entry(&name))
+            .collect())
+    }
+    fn entry_name(&self, entry: Handle) -> Vec<u8> {
+        self.notifier.name(entry).unwrap_or_default().into_bytes()
+    }
+    fn entry_type(&self, entry: Handle) -> Result<EntryType> {
+        Ok(self.entry_state(entry, |state| state.value.entry_type()).unwrap_or(EntryType::Unassigned))
+    }
+    fn entry_value(&self, entry: Handle) -> Option<Value> {
+        self.entry_state(entry, |state| state.value.clone())
+    }
+    fn set_entry_value(&self, entry: Handle, value: &Value) -> Result<()> {
+        match self.notifier.name(entry) {
+            Some(name) => self.shared.set_value(&name, value.clone()),
+            None => Err(Error::TypeMismatch { expected: EntryType::Unassigned, actual: value.entry_type() }),
+        }
+    }
+    fn set_default_entry_value(&self, entry: Handle, value: &Value) -> Result<bool> {
+        match self.notifier.name(entry) {
+            Some(name) => self.shared.set_default(&name, value.clone()),
+            None => Err(Error::TypeMismatch { expected: EntryType::Unassigned, actual: value.entry_type() }),
+        }
+    }
+    fn entry_flags(&self, entry: Handle) -> EntryFlags {
+        EntryFlags(self.entry_state(entry, |state| state.flags as u32).unwrap_or(0))
+    }
+    fn set_entry_flags(&self, entry: Handle, flags: EntryFlags) {
+        if let Some(name) = self.notifier.name(entry) {
+            self.shared.set_flags(&name, flags.0 as u8);
+        }
+    }
+    fn entry_last_change(&self, entry: Handle) -> NetworkTime {
+        self.entry_state(entry, |state| state.last_change).unwrap_or(NetworkTime(0))
+    }
+    fn delete_entry(&self, entry: Handle) {
+        if let Some(name) = self.notifier.name(entry) {
+            self.shared.delete(&name);
+        }
+    }
+    fn delete_all_entries(&self) {
+        // ntcore leaves persistent entries alone here, so clients get a delete for each of the
+        // others rather than `ClearAllEntries`.
+        let names: Vec<String> = self.shared.lock().entries.values()
+            .filter(|entry| !entry.is_persistent())
+            .map(|entry| entry.name.clone())
+            .collect();
+        for name in names { self.shared.delete(&name); }
+    }
+
+    fn add_entry_listener(&self, prefix: &str, flags: NotifyFlags, callback: NotificationCallback) -> Handle {
+        self.notifier.add_entry_listener(Target::Prefix(prefix.to_owned()), flags, callback, || self.shared.values())
+    }
+    fn add_entry_listener_single(&self, entry: Handle, flags: NotifyFlags, callback: NotificationCallback) -> Handle {
+        self.notifier.add_entry_listener(Target::Entry(entry), flags, callback, || self.shared.values())
+    }
+    fn remove_entry_listener(&self, listener: Handle) { self.notifier.remove_entry_listener(listener) }
+    fn add_connection_listener(&self, immediate_notify: bool, callback: ConnectionCallback) -> Handle {
+        self.notifier.add_connection_listener(immediate_notify, callback, self.connections())
+    }
+    fn remove_connection_listener(&self, listener: Handle) { self.notifier.remove_connection_listener(listener) }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.shared.running.store(false, Ordering::SeqCst);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+
+        let dirty = {
+            let mut state = self.shared.lock();
+            let clients: Vec<usize> = state.clients.keys().cloned().collect();
+            for client in clients { state.remove_client(client); }
+            state.persist_dirty
+        };
+        if dirty && !self.shared.persist_filename.is_empty() {
+            let _ = self.shared.save();
+        }
+    }
+}
+
+/// A handle to a possibly existant entry on a `Server`.
+#[derive(Clone, Debug)]
+pub struct ServerEntry {
+    shared: Arc<Shared>,
+    name: String,
+}
+
+impl ServerEntry {
+    pub fn name(&self) -> &str { &self.name }
+
+    fn with_state<R, F: FnOnce(&EntryState) -> R>(&self, func: F) -> Option<R> {
+        let state = self.shared.lock();
+        state.ids.get(&self.name).map(|id| func(&state.entries[id]))
+    }
+
+    pub fn entry_type(&self) -> EntryType {
+        self.with_state(|entry| entry.value.entry_type()).unwrap_or(EntryType::Unassigned)
+    }
+
+    pub fn exists(&self) -> bool {
+        self.with_state(|_| ()).is_some()
+    }
+
+    pub fn flags(&self) -> EntryFlags {
+        EntryFlags(self.with_state(|entry| entry.flags as u32).unwrap_or(0))
+    }
+
+    /// Get the value of this entry, if this entry does point to something.
+    pub fn value(&self) -> Option<Value> {
+        self.with_state(|entry| entry.value.clone())
+    }
+
+    /// Set the value of this entry. Fails if the entry already holds a value of a different type.
+    pub fn set<V: Into<Value>>(&self, value: V) -> Result<()> {
+        self.shared.set_value(&self.name, value.into())
+    }
+
+    /// Replace the flags of this entry. Does nothing if the entry doesn't exist.
+    pub fn set_flags(&self, flags: EntryFlags) {
+        self.shared.set_flags(&self.name, flags.0 as u8);
+    }
+
+    pub fn set_persistent(&self) {
+        self.set_flags(self.flags() | EntryFlags::PERSISTENT);
+    }
+
+    pub fn clear_persistent(&self) {
+        self.set_flags(self.flags() & !EntryFlags::PERSISTENT);
+    }
+
+    /// Delete this entry on the server and every client.
+    pub fn delete(&self) {
+        self.shared.delete(&self.name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::io::Write;
+    use std::net::{Ipv4Addr, TcpStream};
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use instance::Instance;
+    use nt3::client::Client;
+    use nt3::codec::Message;
+    use nt3::PROTOCOL_REVISION;
+    use testing::wait_until;
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// A persistent file in the temp directory, removed along with its backup when dropped.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, contents: Option<&str>) -> TempFile {
+            let path = env::temp_dir().join(format!("ntcore-nt3-{}-{}.ini", name, ::std::process::id()));
+            if let Some(contents) = contents { fs::write(&path, contents).unwrap(); }
+            TempFile(path)
+        }
+
+        fn name(&self) -> String { self.0.to_string_lossy().into_owned() }
+
+        fn contents(&self) -> String { fs::read_to_string(&self.0).unwrap() }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            for suffix in &["", ".bak", ".tmp"] {
+                let _ = fs::remove_file(format!("{}{}", self.name(), suffix));
+            }
+        }
+    }
+
+    fn start(persist_filename: String) -> Result<Server> {
+        Server::start_server(persist_filename, Ipv4Addr::LOCALHOST, 0)
+    }
+
+    #[test]
+    fn bad_lines_are_skipped_with_warnings() {
+        let file = TempFile::new("bad-lines", Some("[NetworkTables Storage 3.0]\n\
+            double \"/a\"=1\n\
+            double \"/b\"=oops\n\
+            boolean \"/c\"=true\n\
+            boolean \"/c\"=false\n"));
+        let server = start(file.name()).unwrap();
+
+        assert_eq!(server.get_entry("/a").value(), Some(Value::Double(1.0)));
+        assert!(!server.get_entry("/b").exists());
+        assert_eq!(server.get_entry("/c").value(), Some(Value::Bool(false)));
+        assert!(server.get_entry("/a").flags().is_persistent());
+        assert_eq!(server.get_all_entries().len(), 2);
+        assert_eq!(server.load_warnings().iter().map(|warning| warning.line).collect::<Vec<_>>(), vec![3]);
+    }
+
+    #[test]
+    fn unreadable_files_are_left_alone() {
+        let contents = "this is not a persistent file\n";
+        let file = TempFile::new("unreadable", Some(contents));
+        match start(file.name()) {
+            Err(Error::Persistence(_)) => {}
+            other => panic!("expected a persistence error, got {:?}", other.map(|_| ())),
+        }
+        assert_eq!(file.contents(), contents);
+    }
+
+    #[test]
+    fn persistent_entries_are_saved_with_a_backup() {
+        let file = TempFile::new("save", Some("[NetworkTables Storage 3.0]\ndouble \"/old\"=1\n"));
+        let server = start(file.name()).unwrap();
+        server.get_entry("/new").set("x").unwrap();
+        server.get_entry("/new").set_persistent();
+        server.get_entry("/temporary").set(true).unwrap();
+        server.save_persistent().unwrap();
+
+        assert_eq!(file.contents(), "[NetworkTables Storage 3.0]\nstring \"/new\"=\"x\"\ndouble \"/old\"=1\n");
+        assert_eq!(fs::read_to_string(format!("{}.bak", file.name())).unwrap(),
+                   "[NetworkTables Storage 3.0]\ndouble \"/old\"=1\n");
+    }
+
+    #[test]
+    fn saving_needs_a_file() {
+        let server = start(String::new()).unwrap();
+        assert!(server.save_persistent().is_err());
+    }
+
+    #[test]
+    fn messages_sent_with_the_hello_are_handled() {
+        let server = start(String::new()).unwrap();
+        let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+
+        let mut buf = Vec::new();
+        Message::ClientHello { protocol_revision: PROTOCOL_REVISION, identity: "eager".into() }.encode(&mut buf);
+        Message::EntryAssignment { name: "/eager".into(), id: UNASSIGNED_ID, seq: 0, flags: 0, value: Value::Bool(true) }
+            .encode(&mut buf);
+        stream.write_all(&buf).unwrap();
+
+        assert!(wait_until(TIMEOUT, || server.get_entry("/eager").value() == Some(Value::Bool(true))));
+    }
+
+    #[test]
+    fn values_keep_their_type() {
+        let server = start(String::new()).unwrap();
+        server.get_entry("/a").set(1.0).unwrap();
+        assert_eq!(server.get_entry("/a").set("no"),
+                   Err(Error::TypeMismatch { expected: EntryType::Double, actual: EntryType::String }));
+        server.get_entry("/a").delete();
+        assert!(!server.get_entry("/a").exists());
+    }
+
+    #[test]
+    fn ports_must_fit_in_16_bits() {
+        assert_eq!(Server::start_server(String::new(), Ipv4Addr::LOCALHOST, 65536).err(),
+                   Some(Error::InvalidAddress("127.0.0.1:65536".to_owned())));
+    }
+
+    #[test]
+    fn works_as_an_instance_backend() {
+        let server = Arc::new(start(String::new()).unwrap());
+        let port = server.local_addr().port();
+        let inst = Instance::from_backend(server);
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let listener_changes = changes.clone();
+        let flags = NotifyFlags::NEW | NotifyFlags::UPDATE | NotifyFlags::FLAGS | NotifyFlags::LOCAL;
+        let _listener = inst.add_entry_listener("/", flags, move |change| {
+            listener_changes.lock().unwrap().push((change.name, change.value, change.flags));
+        });
+        let connections = Arc::new(Mutex::new(Vec::new()));
+        let listener_connections = connections.clone();
+        let _connection_listener = inst.add_connection_listener(false, move |event| {
+            listener_connections.lock().unwrap().push(event.is_connected());
+        });
+
+        inst.get_entry("/local").set(1.0).unwrap();
+        let client = Client::start_client(Ipv4Addr::LOCALHOST, port as u32);
+        client.get_entry("/remote").set("hi").unwrap();
+        assert!(wait_until(TIMEOUT, || inst.get_entry("/remote").value() == Some(Value::String("hi".into()))));
+        assert!(inst.is_connected());
+        assert_eq!(inst.get_connections().len(), 1);
+
+        inst.get_entry("/local").set_persistent();
+        assert!(wait_until(TIMEOUT, || client.get_entry("/local").flags().is_persistent()));
+        drop(client);
+        assert!(wait_until(TIMEOUT, || !inst.is_connected()));
+
+        let expected = vec![
+            ("/local".to_owned(), Some(Value::Double(1.0)), NotifyFlags::NEW | NotifyFlags::LOCAL),
+            ("/remote".to_owned(), Some(Value::String("hi".into())), NotifyFlags::NEW),
+            ("/local".to_owned(), Some(Value::Double(1.0)), NotifyFlags::FLAGS | NotifyFlags::LOCAL),
+        ];
+        assert!(wait_until(TIMEOUT, || *changes.lock().unwrap() == expected));
+        assert!(wait_until(TIMEOUT, || *connections.lock().unwrap() == vec![true, false]));
+    }
+
+    #[test]
+    fn deleting_everything_keeps_persistent_entries() {
+        let server = start(String::new()).unwrap();
+        server.get_entry("/kept").set(1.0).unwrap();
+        server.get_entry("/kept").set_persistent();
+        server.get_entry("/gone").set(2.0).unwrap();
+        server.delete_all_entries();
+        assert!(server.get_entry("/kept").exists());
+        assert!(!server.get_entry("/gone").exists());
+    }
+
+    #[test]
+    fn servers_cannot_be_clients() {
+        let server = start(String::new()).unwrap();
+        assert_eq!(server.start_client(&[("localhost", 1735)]), Err(Error::Unsupported));
+        assert_eq!(server.set_server(&[("localhost", 1735)]), Err(Error::Unsupported));
+    }
+}
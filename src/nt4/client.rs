@@ -0,0 +1,922 @@
+//! A NetworkTables 4 client that runs over a WebSocket on a background thread. Topics show up as
+//! entries with the same `Value`s as NT3, so code written against `nt3::client` works unchanged.
+//!
+//! ```rs
+//! let client = Client::start_client("dashboard", Ipv4Addr::new(10, 0, 0, 2), nt4::DEFAULT_PORT);
+//! let speed = client.get_entry("/Shooter/speed");
+//! speed.set(3000.0)?;
+//! println!("{:?}", client.get_entry("/Shooter/ready").value());
+//! ```
+//!
+//! A client is also a `Backend`, so it can be put under an `Instance` with `Instance::from_backend`.
+//! It can't be made into a server, and entries only exist while they have a value.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io;
+use std::mem;
+use std::net::{Ipv4Addr, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use rmpv;
+use tungstenite::client::IntoClientRequest;
+use tungstenite::http::HeaderValue;
+use tungstenite::{self, HandshakeError, Message as Frame, WebSocket};
+use backend::network::{self, notify, Event, EventSender, Notifier};
+use backend::{Backend, ConnectionCallback, Handle, NotificationCallback, Target};
+use connection::{ConnectionEvent, OwnedConnectionInfo};
+use entry::{EntryFlags, EntryMask, EntryType, Value};
+use listener::NotifyFlags;
+use nt4::codec::{self, ClientMessage, DataMessage, ServerMessage, TopicType, TIME_SYNC_ID};
+use nt4::SUBPROTOCOL;
+use ::{now, Error, NetworkTime, Result};
+
+/// How long the network thread waits for data before sending queued changes.
+const UPDATE_INTERVAL: Duration = Duration::from_millis(100);
+/// How often the offset between our clock and the server's is measured again.
+const TIME_SYNC_INTERVAL: Duration = Duration::from_secs(5);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+const RECONNECT_DELAY: Duration = Duration::from_millis(500);
+/// We only ever need one subscription, to every topic.
+const SUBUID: i64 = 1;
+/// Reported as the protocol version of connections, like ntcore does for NT4.
+const PROTOCOL_VERSION: u32 = 0x0400;
+
+#[derive(Debug)]
+struct Topic {
+    /// The type announced by the server, or the one we published with.
+    ty: TopicType,
+    value: Option<Value>,
+    /// Server time of the last change, in microseconds.
+    timestamp: i64,
+    persistent: bool,
+    /// Set while the server has announced the topic.
+    id: Option<i64>,
+    /// Set once we publish to the topic.
+    pubuid: Option<i64>,
+    /// Local time of the last change.
+    last_change: NetworkTime,
+}
+
+impl Topic {
+    fn new(ty: TopicType, persistent: bool) -> Topic {
+        Topic { ty, value: None, timestamp: 0, persistent, id: None, pubuid: None, last_change: NetworkTime(0) }
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    identity: String,
+    servers: Vec<(String, u16)>,
+    topics: HashMap<String, Topic>,
+    ids: HashMap<i64, String>,
+    next_pubuid: i64,
+    /// Difference between server time and `local_micros`, in microseconds.
+    time_offset: i64,
+    epoch: Instant,
+    /// Messages waiting to be sent by the network thread. Only filled while connected.
+    outgoing_text: Vec<ClientMessage>,
+    outgoing_data: Vec<DataMessage>,
+    /// The server we're connected to, if any.
+    connection: Option<OwnedConnectionInfo>,
+    /// Where changes are reported, for listeners.
+    events: EventSender,
+}
+
+impl State {
+    fn local_micros(&self) -> i64 {
+        let elapsed = self.epoch.elapsed();
+        elapsed.as_secs() as i64 * 1_000_000 + elapsed.subsec_micros() as i64
+    }
+
+    fn server_micros(&self) -> i64 { self.local_micros() + self.time_offset }
+
+    fn time_sync(&self) -> DataMessage {
+        let now = self.local_micros();
+        DataMessage { id: TIME_SYNC_ID, timestamp: 0, data_type: TopicType::Int.data_type(), data: rmpv::Value::from(now) }
+    }
+
+    fn handle_text(&mut self, msg: ServerMessage) {
+        let State { ref mut topics, ref mut ids, ref events, .. } = *self;
+
+        match msg {
+            ServerMessage::Announce { name, id, ty, persistent, .. } => {
+                ids.insert(id, name.clone());
+                let topic = topics.entry(name.clone()).or_insert_with(|| Topic::new(ty, persistent));
+                // The server's type wins. A value of another type can't be kept around.
+                if topic.ty.entry_type() != ty.entry_type() {
+                    if let Some(old) = topic.value.take() {
+                        topic.last_change = now();
+                        notify(events, &name, Some(old), NotifyFlags::DELETE);
+                    }
+                }
+                if topic.persistent != persistent && topic.value.is_some() {
+                    notify(events, &name, topic.value.clone(), NotifyFlags::FLAGS);
+                }
+                topic.ty = ty;
+                topic.persistent = persistent;
+                topic.id = Some(id);
+            }
+            ServerMessage::Unannounce { name, id } => {
+                ids.remove(&id);
+                let published = match topics.get_mut(&name) {
+                    Some(topic) => {
+                        topic.id = None;
+                        topic.pubuid.is_some()
+                    }
+                    None => true,
+                };
+                // Topics we publish stay around, since we'll announce them again.
+                if !published {
+                    if let Some(old) = topics.remove(&name).and_then(|topic| topic.value) {
+                        notify(events, &name, Some(old), NotifyFlags::DELETE);
+                    }
+                }
+            }
+            ServerMessage::Properties { name, persistent } => {
+                if let (Some(topic), Some(persistent)) = (topics.get_mut(&name), persistent) {
+                    if topic.persistent != persistent && topic.value.is_some() {
+                        notify(events, &name, topic.value.clone(), NotifyFlags::FLAGS);
+                    }
+                    topic.persistent = persistent;
+                }
+            }
+        }
+    }
+
+    fn handle_data(&mut self, msg: DataMessage) {
+        if msg.id == TIME_SYNC_ID {
+            // The server echoes our send time back, so half the round trip is added on to the
+            // server's time.
+            if let Some(sent) = msg.data.as_i64() {
+                let now = self.local_micros();
+                self.time_offset = msg.timestamp + (now - sent) / 2 - now;
+            }
+            return;
+        }
+
+        let State { ref mut topics, ref ids, ref events, .. } = *self;
+        let name = match ids.get(&msg.id) {
+            Some(name) => name,
+            None => return,
+        };
+        if let Some(topic) = topics.get_mut(name) {
+            // Out of order values are dropped, like ntcore does.
+            if msg.timestamp < topic.timestamp { return; }
+            if let Some(value) = topic.ty.decode_value(&msg.data) {
+                topic.timestamp = msg.timestamp;
+                let flags = match topic.value {
+                    None => NotifyFlags::NEW,
+                    Some(ref old) if *old == value => return,
+                    Some(_) => NotifyFlags::UPDATE,
+                };
+                topic.value = Some(value.clone());
+                topic.last_change = now();
+                notify(events, name, Some(value), flags);
+            }
+        }
+    }
+
+    /// Set the value of a topic, publishing it if this is the first time it's set.
+    fn set_value(&mut self, name: &str, value: Value, connected: bool) -> Result<()> {
+        let timestamp = self.server_micros();
+
+        let (publish, flags) = {
+            let topic = self.topics.entry(name.to_owned()).or_insert_with(|| Topic::new(TopicType::for_value(&value), false));
+            let expected = topic.ty.entry_type();
+            if expected != value.entry_type() {
+                return Err(Error::TypeMismatch { expected, actual: value.entry_type() });
+            }
+            // Integer topics can only take whole numbers that fit in an i64.
+            if topic.ty.encode_value(&value).is_none() {
+                return Err(Error::InvalidConversion { value, target: topic.ty.name() });
+            }
+            if topic.value.as_ref() == Some(&value) { return Ok(()); }
+
+            let flags = if topic.value.is_some() { NotifyFlags::UPDATE } else { NotifyFlags::NEW };
+            topic.value = Some(value.clone());
+            topic.timestamp = timestamp;
+            topic.last_change = now();
+            (topic.pubuid.is_none(), flags)
+        };
+        notify(&self.events, name, Some(value), flags | NotifyFlags::LOCAL);
+
+        if publish {
+            let msg = self.publish(name);
+            if connected { self.outgoing_text.extend(msg); }
+        }
+        if connected {
+            let data = State::data(&self.topics[name]);
+            self.outgoing_data.extend(data);
+        }
+        Ok(())
+    }
+
+    fn publish(&mut self, name: &str) -> Option<ClientMessage> {
+        let pubuid = self.next_pubuid;
+        let topic = self.topics.get_mut(name)?;
+        self.next_pubuid += 1;
+        topic.pubuid = Some(pubuid);
+        Some(ClientMessage::Publish { name: name.to_owned(), pubuid, ty: topic.ty, persistent: topic.persistent })
+    }
+
+    fn data(topic: &Topic) -> Option<DataMessage> {
+        let data = topic.ty.encode_value(topic.value.as_ref()?)?;
+        Some(DataMessage { id: topic.pubuid?, timestamp: topic.timestamp, data_type: topic.ty.data_type(), data })
+    }
+
+    /// Messages to publish every topic we set a value on, for the start of a connection.
+    fn publish_all(&mut self) -> (Vec<ClientMessage>, Vec<DataMessage>) {
+        let names: Vec<String> = self.topics.iter()
+            .filter(|&(_, topic)| topic.pubuid.is_some())
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        let mut text = Vec::new();
+        let mut data = Vec::new();
+        for name in names {
+            text.extend(self.publish(&name));
+            data.extend(State::data(&self.topics[&name]));
+        }
+        (text, data)
+    }
+
+    /// Forget everything the server told us about ids, so that our topics are published again on
+    /// the next connection.
+    fn disconnect(&mut self) {
+        if let Some(info) = self.connection.take() {
+            let _ = self.events.send(Event::Connection(ConnectionEvent::Disconnected(info)));
+        }
+        self.ids.clear();
+        self.outgoing_text.clear();
+        self.outgoing_data.clear();
+        let State { ref mut topics, ref events, .. } = *self;
+        topics.retain(|name, topic| {
+            let keep = topic.pubuid.is_some();
+            if let (false, Some(old)) = (keep, topic.value.take()) {
+                notify(events, name, Some(old), NotifyFlags::DELETE);
+            }
+            keep
+        });
+        for topic in self.topics.values_mut() {
+            topic.id = None;
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    state: Mutex<State>,
+    running: AtomicBool,
+    connected: AtomicBool,
+    /// Set to make the network thread drop the current connection, after the servers change.
+    reconnect: AtomicBool,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // A panic on the network thread shouldn't take the robot code down with it.
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn is_running(&self) -> bool { self.running.load(Ordering::SeqCst) }
+
+    fn set_value(&self, name: &str, value: Value) -> Result<()> {
+        let mut state = self.lock();
+        // Read under the lock so this can't race with the connection starting.
+        let connected = self.connected.load(Ordering::SeqCst);
+        state.set_value(name, value, connected)
+    }
+
+    /// Set the value only if the topic doesn't have one, as one step.
+    fn set_default(&self, name: &str, value: Value) -> Result<bool> {
+        let mut state = self.lock();
+        let connected = self.connected.load(Ordering::SeqCst);
+        match state.topics.get(name).and_then(|topic| topic.value.as_ref()).map(Value::entry_type) {
+            Some(ty) if ty == value.entry_type() => Ok(false),
+            Some(ty) => Err(Error::TypeMismatch { expected: ty, actual: value.entry_type() }),
+            None => state.set_value(name, value, connected).map(|()| true),
+        }
+    }
+
+    fn set_persistent(&self, name: &str, persistent: bool) {
+        let mut state = self.lock();
+        let connected = self.connected.load(Ordering::SeqCst);
+        let value = match state.topics.get_mut(name) {
+            Some(topic) if topic.persistent != persistent => {
+                topic.persistent = persistent;
+                topic.value.clone()
+            }
+            _ => return,
+        };
+        if value.is_some() {
+            notify(&state.events, name, value, NotifyFlags::FLAGS | NotifyFlags::LOCAL);
+        }
+        if connected {
+            state.outgoing_text.push(ClientMessage::SetProperties { name: name.to_owned(), persistent });
+        }
+    }
+
+    /// Drop our value for a topic, and stop publishing it. Values from other publishers still
+    /// come through.
+    fn delete(&self, name: &str) {
+        let mut state = self.lock();
+        let connected = self.connected.load(Ordering::SeqCst);
+        let (old, pubuid) = match state.topics.get_mut(name) {
+            Some(topic) => (topic.value.take(), topic.pubuid.take()),
+            None => return,
+        };
+        if state.topics.get(name).is_some_and(|topic| topic.id.is_none()) {
+            state.topics.remove(name);
+        }
+        if let (true, Some(pubuid)) = (connected, pubuid) {
+            state.outgoing_text.push(ClientMessage::Unpublish { pubuid });
+        }
+        if old.is_some() {
+            notify(&state.events, name, old, NotifyFlags::DELETE | NotifyFlags::LOCAL);
+        }
+    }
+
+    /// Get every topic that has a value.
+    fn values(&self) -> Vec<(String, Value)> {
+        self.lock().topics.iter()
+            .filter_map(|(name, topic)| topic.value.clone().map(|value| (name.clone(), value)))
+            .collect()
+    }
+
+    /// Change the servers to connect to, dropping the current connection.
+    fn set_servers(&self, servers: Vec<(String, u16)>) {
+        self.lock().servers = servers;
+        self.reconnect.store(true, Ordering::SeqCst);
+    }
+}
+
+fn other_error<E: ToString>(err: E) -> io::Error {
+    io::Error::other(err.to_string())
+}
+
+/// Escape a client name so that it can go in the path of the WebSocket URL.
+fn escape_path(name: &str) -> String {
+    let mut out = String::new();
+    for &byte in name.as_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => out.push(byte as char),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+fn connect(host: &str, port: u16) -> io::Result<TcpStream> {
+    let mut last_err = io::Error::new(io::ErrorKind::NotFound, "server name did not resolve to any address");
+    for addr in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_err = err,
+        }
+    }
+    Err(last_err)
+}
+
+fn open_socket(host: &str, port: u16, identity: &str) -> io::Result<WebSocket<TcpStream>> {
+    let stream = connect(host, port)?;
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(UPDATE_INTERVAL))?;
+
+    let url = format!("ws://{}:{}/nt/{}", host, port, escape_path(identity));
+    let mut request = url.into_client_request().map_err(other_error)?;
+    request.headers_mut().insert("Sec-WebSocket-Protocol", HeaderValue::from_static(SUBPROTOCOL));
+
+    // The read timeout can interrupt the handshake, in which case it's picked up again.
+    let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+    let mut handshake = tungstenite::client(request, stream);
+    loop {
+        match handshake {
+            Ok((socket, _)) => return Ok(socket),
+            Err(HandshakeError::Interrupted(mid)) => {
+                if Instant::now() > deadline {
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, "server did not finish the handshake"));
+                }
+                handshake = mid.handshake();
+            }
+            Err(HandshakeError::Failure(err)) => return Err(other_error(err)),
+        }
+    }
+}
+
+/// Send one text frame and one binary frame, skipping whichever would be empty.
+fn send(socket: &mut WebSocket<TcpStream>, text: &[ClientMessage], data: &[DataMessage]) -> io::Result<()> {
+    if !text.is_empty() {
+        socket.send(Frame::Text(codec::encode_text(text))).map_err(other_error)?;
+    }
+    if !data.is_empty() {
+        socket.send(Frame::Binary(codec::encode_binary(data))).map_err(other_error)?;
+    }
+    Ok(())
+}
+
+/// Wait up to one update interval for a frame, and handle it.
+fn receive(shared: &Shared, socket: &mut WebSocket<TcpStream>) -> io::Result<()> {
+    match socket.read() {
+        // Frames that fail to decode are skipped, since the next one starts fresh.
+        Ok(Frame::Text(text)) => if let Ok(msgs) = codec::decode_text(&text) {
+            let mut state = shared.lock();
+            for msg in msgs { state.handle_text(msg); }
+        },
+        Ok(Frame::Binary(data)) => if let Ok(msgs) = codec::decode_binary(&data) {
+            let mut state = shared.lock();
+            for msg in msgs { state.handle_data(msg); }
+        },
+        Ok(Frame::Close(_)) => return Err(io::Error::new(io::ErrorKind::ConnectionAborted, "server closed the connection")),
+        // Pings are answered by tungstenite on the next write.
+        Ok(_) => {}
+        Err(tungstenite::Error::Io(ref err))
+            if err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::TimedOut => {}
+        Err(err) => return Err(other_error(err)),
+    }
+    Ok(())
+}
+
+fn run_connection(shared: &Shared, mut socket: WebSocket<TcpStream>, info: OwnedConnectionInfo) -> io::Result<()> {
+    let (mut text, mut data) = {
+        let mut state = shared.lock();
+        let (text, data) = state.publish_all();
+        shared.connected.store(true, Ordering::SeqCst);
+        state.connection = Some(info.clone());
+        let _ = state.events.send(Event::Connection(ConnectionEvent::Connected(info)));
+        (text, data)
+    };
+    text.insert(0, ClientMessage::Subscribe { prefixes: vec![String::new()], subuid: SUBUID });
+    data.insert(0, shared.lock().time_sync());
+    send(&mut socket, &text, &data)?;
+    let mut last_time_sync = Instant::now();
+
+    while shared.is_running() && !shared.reconnect.load(Ordering::SeqCst) {
+        let (text, mut data) = {
+            let mut state = shared.lock();
+            (mem::take(&mut state.outgoing_text), mem::take(&mut state.outgoing_data))
+        };
+        if last_time_sync.elapsed() >= TIME_SYNC_INTERVAL {
+            data.push(shared.lock().time_sync());
+            last_time_sync = Instant::now();
+        }
+        send(&mut socket, &text, &data)?;
+
+        receive(shared, &mut socket)?;
+        // Sends any pong queued up while reading.
+        match socket.flush() {
+            Err(tungstenite::Error::Io(ref err)) if err.kind() == io::ErrorKind::WouldBlock => {}
+            result => result.map_err(other_error)?,
+        }
+    }
+
+    let _ = socket.close(None);
+    Ok(())
+}
+
+fn sleep_while_running(shared: &Shared, duration: Duration) {
+    let deadline = Instant::now() + duration;
+    while shared.is_running() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+fn run(shared: Arc<Shared>) {
+    let mut attempt = 0;
+    while shared.is_running() {
+        // Servers changed before this point are picked up below.
+        shared.reconnect.store(false, Ordering::SeqCst);
+        let (servers, identity) = {
+            let state = shared.lock();
+            (state.servers.clone(), state.identity.clone())
+        };
+
+        // Cycle through the servers on every attempt.
+        if !servers.is_empty() {
+            let (ref host, port) = servers[attempt % servers.len()];
+            attempt += 1;
+            if let Ok(socket) = open_socket(host, port, &identity) {
+                let remote_ip = socket.get_ref().peer_addr().map_or_else(|_| host.clone(), |addr| addr.ip().to_string());
+                let info = OwnedConnectionInfo::new(host.clone(), remote_ip, port as u32, now(), PROTOCOL_VERSION);
+                // Any error just means we have to reconnect.
+                let _ = run_connection(&shared, socket, info);
+                let mut state = shared.lock();
+                shared.connected.store(false, Ordering::SeqCst);
+                state.disconnect();
+            }
+        }
+
+        sleep_while_running(&shared, RECONNECT_DELAY);
+    }
+}
+
+/// A NetworkTables 4 client implemented in Rust. The connection is managed by a background thread,
+/// which reconnects whenever the connection drops, and is stopped when this is dropped.
+#[derive(Debug)]
+pub struct Client {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+    notifier: Notifier,
+}
+
+impl Client {
+    /// Start a client that identifies itself to the server as `identity`. Panics if `port` doesn't
+    /// fit in 16 bits. Use `try_start_client` to handle that case.
+    pub fn start_client(identity: &str, server_ip: Ipv4Addr, port: u32) -> Client {
+        Client::try_start_client(identity, server_ip, port).expect("invalid server address")
+    }
+
+    pub fn try_start_client(identity: &str, server_ip: Ipv4Addr, port: u32) -> Result<Client> {
+        Client::try_start_client_multi(identity, vec![(&server_ip.to_string(), port)])
+    }
+
+    /// Start a client that tries each of `servers` in turn until one of them accepts. Panics if a
+    /// port doesn't fit in 16 bits. Use `try_start_client_multi` to handle that case.
+    pub fn start_client_multi(identity: &str, servers: Vec<(&str, u32)>) -> Client {
+        Client::try_start_client_multi(identity, servers).expect("invalid server address")
+    }
+
+    pub fn try_start_client_multi(identity: &str, servers: Vec<(&str, u32)>) -> Result<Client> {
+        let notifier = Notifier::new();
+        let state = State {
+            identity: identity.to_owned(),
+            servers: network::parse_servers(&servers)?,
+            topics: HashMap::new(),
+            ids: HashMap::new(),
+            next_pubuid: 1,
+            time_offset: 0,
+            epoch: Instant::now(),
+            outgoing_text: Vec::new(),
+            outgoing_data: Vec::new(),
+            connection: None,
+            events: notifier.sender(),
+        };
+        let shared = Arc::new(Shared {
+            state: Mutex::new(state),
+            running: AtomicBool::new(true),
+            connected: AtomicBool::new(false),
+            reconnect: AtomicBool::new(false),
+        });
+
+        let thread_shared = shared.clone();
+        let thread = thread::spawn(move || run(thread_shared));
+        Ok(Client { shared, thread: Some(thread), notifier })
+    }
+
+    /// Set the name this client reports to the server. Takes effect on the next connection.
+    pub fn set_network_identity(&self, name: &str) {
+        self.shared.lock().identity = name.to_owned();
+    }
+
+    /// Get whether the client has a WebSocket open to a server.
+    pub fn is_connected(&self) -> bool {
+        self.shared.connected.load(Ordering::SeqCst)
+    }
+
+    /// Get the current time on the server, in microseconds, as measured by time synchronization.
+    pub fn server_time(&self) -> i64 {
+        self.shared.lock().server_micros()
+    }
+
+    pub fn get_entry(&self, key: &str) -> ClientEntry {
+        ClientEntry { shared: self.shared.clone(), name: key.to_owned() }
+    }
+
+    pub fn get_all_entries(&self) -> Vec<ClientEntry> {
+        self.shared.lock().topics.keys().map(|name| self.get_entry(name)).collect()
+    }
+
+    fn topic<R, F: FnOnce(&Topic) -> R>(&self, entry: Handle, func: F) -> Option<R> {
+        let name = self.notifier.name(entry)?;
+        self.shared.lock().topics.get(&name).map(func)
+    }
+}
+
+impl Backend for Client {
+    fn as_any(&self) -> &dyn Any { self }
+
+    fn set_network_identity(&self, name: &str) { Client::set_network_identity(self, name) }
+
+    fn start_server(&self, _persist_filename: &str, _listen_address: &str, _port: u32) -> Result<()> {
+        Err(Error::Unsupported)
+    }
+    fn stop_server(&self) {}
+    fn start_client_none(&self) { self.shared.set_servers(Vec::new()) }
+    fn start_client(&self, servers: &[(&str, u32)]) -> Result<()> {
+        self.shared.set_servers(network::parse_servers(servers)?);
+        Ok(())
+    }
+    fn start_client_team(&self, team: u32, port: u32) { self.set_server_team(team, port) }
+    fn stop_client(&self) { self.shared.set_servers(Vec::new()) }
+    fn set_server(&self, servers: &[(&str, u32)]) -> Result<()> {
+        // Like ntcore, this takes effect on the next connection.
+        self.shared.lock().servers = network::parse_servers(servers)?;
+        Ok(())
+    }
+    fn set_server_team(&self, team: u32, port: u32) {
+        // There's no way to report a bad port here, so there's just nothing to connect to.
+        let servers = u16::try_from(port)
+            .map(|port| network::team_servers(team).into_iter().map(|host| (host, port)).collect())
+            .unwrap_or_default();
+        self.shared.lock().servers = servers;
+    }
+    // Changes are sent every `UPDATE_INTERVAL`, and there's no way to hurry them along.
+    fn set_update_rate(&self, _interval: f64) {}
+    fn flush(&self) {}
+
+    fn is_connected(&self) -> bool { Client::is_connected(self) }
+    fn connections(&self) -> Vec<OwnedConnectionInfo> { self.shared.lock().connection.iter().cloned().collect() }
+
+    fn entry(&self, name: &str) -> Handle { self.notifier.entry(name) }
+    fn entries(&self, prefix: &str, types: EntryMask) -> Result<Vec<Handle>> {
+        Ok(self.shared.values().into_iter()
+            .filter(|(name, value)| name.starts_with(prefix) && (types.0 == 0 || types.0 & value.entry_type() as u32 != 0))
+            .map(|(name, _)| self.notifier.entry(&name))
+            .collect())
+    }
+    fn entry_name(&self, entry: Handle) -> Vec<u8> {
+        self.notifier.name(entry).unwrap_or_default().into_bytes()
+    }
+    fn entry_type(&self, entry: Handle) -> Result<EntryType> {
+        Ok(self.entry_value(entry).map_or(EntryType::Unassigned, |value| value.entry_type()))
+    }
+    fn entry_value(&self, entry: Handle) -> Option<Value> {
+        self.topic(entry, |topic| topic.value.clone()).and_then(|value| value)
+    }
+    fn set_entry_value(&self, entry: Handle, value: &Value) -> Result<()> {
+        match self.notifier.name(entry) {
+            Some(name) => self.shared.set_value(&name, value.clone()),
+            None => Err(Error::TypeMismatch { expected: EntryType::Unassigned, actual: value.entry_type() }),
+        }
+    }
+    fn set_default_entry_value(&self, entry: Handle, value: &Value) -> Result<bool> {
+        match self.notifier.name(entry) {
+            Some(name) => self.shared.set_default(&name, value.clone()),
+            None => Err(Error::TypeMismatch { expected: EntryType::Unassigned, actual: value.entry_type() }),
+        }
+    }
+    fn entry_flags(&self, entry: Handle) -> EntryFlags {
+        match self.topic(entry, |topic| topic.persistent && topic.value.is_some()) {
+            Some(true) => EntryFlags::PERSISTENT,
+            _ => EntryFlags::NONE,
+        }
+    }
+    fn set_entry_flags(&self, entry: Handle, flags: EntryFlags) {
+        // Like ntcore, entries without a value can't have flags.
+        if self.entry_value(entry).is_none() { return; }
+        if let Some(name) = self.notifier.name(entry) {
+            self.shared.set_persistent(&name, flags.is_persistent());
+        }
+    }
+    fn entry_last_change(&self, entry: Handle) -> NetworkTime {
+        self.topic(entry, |topic| topic.last_change).unwrap_or(NetworkTime(0))
+    }
+    fn delete_entry(&self, entry: Handle) {
+        if let Some(name) = self.notifier.name(entry) {
+            self.shared.delete(&name);
+        }
+    }
+    fn delete_all_entries(&self) {
+        // ntcore leaves persistent entries alone here.
+        let names: Vec<String> = self.shared.lock().topics.iter()
+            .filter(|&(_, topic)| !topic.persistent)
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in names { self.shared.delete(&name); }
+    }
+
+    fn add_entry_listener(&self, prefix: &str, flags: NotifyFlags, callback: NotificationCallback) -> Handle {
+        self.notifier.add_entry_listener(Target::Prefix(prefix.to_owned()), flags, callback, || self.shared.values())
+    }
+    fn add_entry_listener_single(&self, entry: Handle, flags: NotifyFlags, callback: NotificationCallback) -> Handle {
+        self.notifier.add_entry_listener(Target::Entry(entry), flags, callback, || self.shared.values())
+    }
+    fn remove_entry_listener(&self, listener: Handle) { self.notifier.remove_entry_listener(listener) }
+    fn add_connection_listener(&self, immediate_notify: bool, callback: ConnectionCallback) -> Handle {
+        self.notifier.add_connection_listener(immediate_notify, callback, self.connections())
+    }
+    fn remove_connection_listener(&self, listener: Handle) { self.notifier.remove_connection_listener(listener) }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.shared.running.store(false, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// A handle to a possibly existant topic on a `Client`.
+#[derive(Clone, Debug)]
+pub struct ClientEntry {
+    shared: Arc<Shared>,
+    name: String,
+}
+
+impl ClientEntry {
+    pub fn name(&self) -> &str { &self.name }
+
+    pub fn entry_type(&self) -> EntryType {
+        self.topic_type().map_or(EntryType::Unassigned, TopicType::entry_type)
+    }
+
+    /// Get the type the topic was announced with, which tells integers and floats apart from
+    /// doubles.
+    pub fn topic_type(&self) -> Option<TopicType> {
+        self.shared.lock().topics.get(&self.name).map(|topic| topic.ty)
+    }
+
+    pub fn exists(&self) -> bool {
+        self.shared.lock().topics.contains_key(&self.name)
+    }
+
+    pub fn flags(&self) -> EntryFlags {
+        let persistent = self.shared.lock().topics.get(&self.name).is_some_and(|topic| topic.persistent);
+        if persistent { EntryFlags::PERSISTENT } else { EntryFlags::NONE }
+    }
+
+    pub fn is_persistent(&self) -> bool { self.flags().is_persistent() }
+
+    pub fn set_persistent(&self) { self.shared.set_persistent(&self.name, true); }
+
+    pub fn clear_persistent(&self) { self.shared.set_persistent(&self.name, false); }
+
+    /// Get the value of this entry, if a value has been received or set.
+    pub fn value(&self) -> Option<Value> {
+        self.shared.lock().topics.get(&self.name).and_then(|topic| topic.value.clone())
+    }
+
+    /// Get the server time of the last change to this entry, in microseconds.
+    pub fn last_changed(&self) -> Option<i64> {
+        self.shared.lock().topics.get(&self.name).and_then(|topic| topic.value.as_ref().map(|_| topic.timestamp))
+    }
+
+    /// Set the value of this entry, publishing the topic if this is the first time it's set.
+    /// Doubles are sent as integers or floats if the topic was announced with one of those types.
+    /// Fails if the entry already holds a value of a different type.
+    pub fn set<V: Into<Value>>(&self, value: V) -> Result<()> {
+        self.shared.set_value(&self.name, value.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, TcpListener};
+    use std::sync::mpsc::{self, Receiver};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use serde_json::{self, Value as Json};
+    use tungstenite::handshake::server::{Request, Response};
+    use instance::Instance;
+    use testing::wait_until;
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// What the test server got from the client.
+    #[derive(Debug)]
+    enum Received {
+        Text(Json),
+        Data(DataMessage),
+    }
+
+    fn receive_until<F: FnMut(&Received) -> bool>(received: &Receiver<Received>, mut condition: F) -> bool {
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            match received.recv_timeout(left) {
+                Ok(msg) => if condition(&msg) { return true; },
+                Err(_) => return false,
+            }
+        }
+    }
+
+    /// Run a server for one connection. It announces `/server/int` with a value of 42, answers
+    /// every publish with an announcement and every time sync with its time, and hands everything
+    /// else the client sends to the test.
+    #[allow(clippy::result_large_err)]
+    fn start_server() -> (u32, Receiver<Received>) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, received) = mpsc::channel();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = tungstenite::accept_hdr(stream, |_: &Request, mut response: Response| {
+                response.headers_mut().insert("Sec-WebSocket-Protocol", HeaderValue::from_static(SUBPROTOCOL));
+                Ok(response)
+            }).unwrap();
+
+            let announce = json!([{ "method": "announce", "params": { "name": "/server/int", "id": 1, "type": "int", "properties": {} } }]);
+            socket.send(Frame::Text(announce.to_string())).unwrap();
+            let value = DataMessage { id: 1, timestamp: 1, data_type: TopicType::Int.data_type(), data: rmpv::Value::from(42) };
+            socket.send(Frame::Binary(codec::encode_binary(&[value]))).unwrap();
+
+            // Runs until the client hangs up.
+            while let Ok(frame) = socket.read() {
+                match frame {
+                    Frame::Text(text) => for msg in serde_json::from_str::<Vec<Json>>(&text).unwrap() {
+                        if msg["method"] == "publish" {
+                            let params = &msg["params"];
+                            let announce = json!([{ "method": "announce", "params": {
+                                "name": params["name"],
+                                "id": params["pubuid"].as_i64().unwrap() + 100,
+                                "type": params["type"],
+                                "pubuid": params["pubuid"],
+                                "properties": params["properties"],
+                            } }]);
+                            let _ = socket.send(Frame::Text(announce.to_string()));
+                        }
+                        let _ = sender.send(Received::Text(msg));
+                    },
+                    Frame::Binary(data) => for msg in codec::decode_binary(&data).unwrap() {
+                        if msg.id == TIME_SYNC_ID {
+                            let reply = DataMessage { timestamp: 1_000_000, ..msg };
+                            let _ = socket.send(Frame::Binary(codec::encode_binary(&[reply])));
+                        } else {
+                            let _ = sender.send(Received::Data(msg));
+                        }
+                    },
+                    _ => {}
+                }
+            }
+        });
+        (port as u32, received)
+    }
+
+    #[test]
+    fn works_as_an_instance_backend() {
+        let (port, received) = start_server();
+        let inst = Instance::from_backend(Arc::new(Client::start_client("test", Ipv4Addr::LOCALHOST, port)));
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        let listener_changes = changes.clone();
+        let _listener = inst.add_entry_listener("/", NotifyFlags::NEW | NotifyFlags::UPDATE | NotifyFlags::LOCAL, move |change| {
+            listener_changes.lock().unwrap().push((change.name, change.value, change.flags));
+        });
+
+        assert!(wait_until(TIMEOUT, || inst.get_entry("/server/int").value() == Some(Value::Double(42.0))));
+        assert!(inst.is_connected());
+        assert_eq!(inst.get_connections().len(), 1);
+        assert_eq!(inst.get_connections()[0].protocol_version(), PROTOCOL_VERSION);
+
+        inst.get_entry("/client/speed").set(3.5).unwrap();
+        assert!(receive_until(&received, |msg| match *msg {
+            Received::Data(ref data) => data.data == rmpv::Value::F64(3.5),
+            _ => false,
+        }));
+        assert_eq!(inst.get_entry("/client/speed").value(), Some(Value::Double(3.5)));
+
+        let expected = vec![
+            ("/server/int".to_owned(), Some(Value::Double(42.0)), NotifyFlags::NEW),
+            ("/client/speed".to_owned(), Some(Value::Double(3.5)), NotifyFlags::NEW | NotifyFlags::LOCAL),
+        ];
+        assert!(wait_until(TIMEOUT, || *changes.lock().unwrap() == expected));
+    }
+
+    #[test]
+    fn integer_topics_only_take_whole_numbers() {
+        let (port, received) = start_server();
+        let inst = Instance::from_backend(Arc::new(Client::start_client("test", Ipv4Addr::LOCALHOST, port)));
+        let entry = inst.get_entry("/server/int");
+        assert!(wait_until(TIMEOUT, || entry.value().is_some()));
+
+        match entry.set(1.5) {
+            Err(Error::InvalidConversion { value: Value::Double(num), target: "int" }) => assert_eq!(num, 1.5),
+            other => panic!("expected a conversion error, got {:?}", other),
+        }
+        assert_eq!(entry.value(), Some(Value::Double(42.0)));
+
+        entry.set(7.0).unwrap();
+        assert!(receive_until(&received, |msg| match *msg {
+            Received::Text(ref json) => json["method"] == "publish" && json["params"]["type"] == "int",
+            _ => false,
+        }));
+        assert!(receive_until(&received, |msg| match *msg {
+            Received::Data(ref data) => data.data == rmpv::Value::from(7),
+            _ => false,
+        }));
+    }
+
+    #[test]
+    fn ports_must_fit_in_16_bits() {
+        match Client::try_start_client("test", Ipv4Addr::LOCALHOST, 70000) {
+            Err(Error::InvalidAddress(addr)) => assert_eq!(addr, "127.0.0.1:70000"),
+            other => panic!("expected an address error, got {:?}", other),
+        }
+
+        let inst = Instance::from_backend(Arc::new(Client::start_client_multi("test", Vec::new())));
+        assert_eq!(inst.set_server_multi(vec![("localhost", 65536)]), Err(Error::InvalidAddress("localhost:65536".to_owned())));
+        assert_eq!(inst.set_server_multi(vec![("localhost", 65535)]), Ok(()));
+    }
+
+    #[test]
+    fn clients_cannot_serve() {
+        let client = Client::start_client_multi("test", Vec::new());
+        assert_eq!(client.start_server("", "0.0.0.0", 0), Err(Error::Unsupported));
+    }
+}
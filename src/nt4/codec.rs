@@ -0,0 +1,413 @@
+//! Encoding and decoding of NetworkTables 4 messages.
+//!
+//! Text frames hold a JSON array of `{"method": ..., "params": {...}}` objects. Binary frames hold
+//! any number of MessagePack arrays of the form `[topic id, timestamp, data type, value]`, where a
+//! topic id of -1 is used for time synchronization.
+
+use std::error;
+use std::fmt;
+use rmpv;
+use serde_json::{self, Value as Json};
+use entry::{EntryType, Value};
+
+/// The topic id used by time synchronization messages.
+pub const TIME_SYNC_ID: i64 = -1;
+
+/// The type of a topic, as announced by its publisher. NT4 has a few more types than `EntryType`,
+/// which are mapped onto the closest entry type: integers and floats become doubles, and the
+/// structured binary types become raw.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum TopicType {
+    Boolean,
+    Double,
+    Int,
+    Float,
+    String,
+    Json,
+    Raw,
+    Rpc,
+    MsgPack,
+    Protobuf,
+    BooleanArray,
+    DoubleArray,
+    IntArray,
+    FloatArray,
+    StringArray,
+}
+
+impl TopicType {
+    /// Parse the type string of an announcement. Struct and protobuf schemas are treated as raw
+    /// data, since the schema isn't needed to pass the bytes along.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "boolean" => TopicType::Boolean,
+            "double" => TopicType::Double,
+            "int" => TopicType::Int,
+            "float" => TopicType::Float,
+            "string" => TopicType::String,
+            "json" => TopicType::Json,
+            "raw" => TopicType::Raw,
+            "rpc" => TopicType::Rpc,
+            "msgpack" => TopicType::MsgPack,
+            "protobuf" => TopicType::Protobuf,
+            "boolean[]" => TopicType::BooleanArray,
+            "double[]" => TopicType::DoubleArray,
+            "int[]" => TopicType::IntArray,
+            "float[]" => TopicType::FloatArray,
+            "string[]" => TopicType::StringArray,
+            name if name.starts_with("struct:") || name.starts_with("proto:") => TopicType::Raw,
+            _ => return None,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TopicType::Boolean => "boolean",
+            TopicType::Double => "double",
+            TopicType::Int => "int",
+            TopicType::Float => "float",
+            TopicType::String => "string",
+            TopicType::Json => "json",
+            TopicType::Raw => "raw",
+            TopicType::Rpc => "rpc",
+            TopicType::MsgPack => "msgpack",
+            TopicType::Protobuf => "protobuf",
+            TopicType::BooleanArray => "boolean[]",
+            TopicType::DoubleArray => "double[]",
+            TopicType::IntArray => "int[]",
+            TopicType::FloatArray => "float[]",
+            TopicType::StringArray => "string[]",
+        }
+    }
+
+    /// The data type id used in binary frames.
+    pub fn data_type(self) -> u8 {
+        match self {
+            TopicType::Boolean => 0,
+            TopicType::Double => 1,
+            TopicType::Int => 2,
+            TopicType::Float => 3,
+            TopicType::String | TopicType::Json => 4,
+            TopicType::Raw | TopicType::Rpc | TopicType::MsgPack | TopicType::Protobuf => 5,
+            TopicType::BooleanArray => 16,
+            TopicType::DoubleArray => 17,
+            TopicType::IntArray => 18,
+            TopicType::FloatArray => 19,
+            TopicType::StringArray => 20,
+        }
+    }
+
+    /// The type of the entry a topic of this type shows up as.
+    pub fn entry_type(self) -> EntryType {
+        match self {
+            TopicType::Boolean => EntryType::Boolean,
+            TopicType::Double | TopicType::Int | TopicType::Float => EntryType::Double,
+            TopicType::String | TopicType::Json => EntryType::String,
+            TopicType::Raw | TopicType::MsgPack | TopicType::Protobuf => EntryType::Raw,
+            TopicType::Rpc => EntryType::Rpc,
+            TopicType::BooleanArray => EntryType::BooleanArray,
+            TopicType::DoubleArray | TopicType::IntArray | TopicType::FloatArray => EntryType::DoubleArray,
+            TopicType::StringArray => EntryType::StringArray,
+        }
+    }
+
+    /// The type to publish a value as when the topic doesn't exist yet.
+    pub fn for_value(value: &Value) -> TopicType {
+        match *value {
+            Value::Bool(_) => TopicType::Boolean,
+            Value::BoolArray(_) => TopicType::BooleanArray,
+            Value::Double(_) => TopicType::Double,
+            Value::DoubleArray(_) => TopicType::DoubleArray,
+            Value::String(_) => TopicType::String,
+            Value::StringArray(_) => TopicType::StringArray,
+            Value::Raw(_) => TopicType::Raw,
+            Value::Rpc(_) => TopicType::Rpc,
+        }
+    }
+
+    /// Encode `value` as MessagePack data of this type. Returns `None` if the value doesn't have
+    /// the matching entry type, or if this is an integer type and a number isn't a whole number
+    /// that fits in an `i64`.
+    pub fn encode_value(self, value: &Value) -> Option<rmpv::Value> {
+        fn number(ty: TopicType, num: f64) -> Option<rmpv::Value> {
+            // 2^63, the first double past `i64::MAX`.
+            const INT_END: f64 = 9_223_372_036_854_775_808.0;
+            Some(match ty {
+                TopicType::Int | TopicType::IntArray => {
+                    if num.fract() != 0.0 || !(-INT_END..INT_END).contains(&num) { return None; }
+                    rmpv::Value::from(num as i64)
+                }
+                TopicType::Float | TopicType::FloatArray => rmpv::Value::F32(num as f32),
+                _ => rmpv::Value::F64(num),
+            })
+        }
+
+        if value.entry_type() != self.entry_type() { return None; }
+        Some(match *value {
+            Value::Bool(b) => rmpv::Value::Boolean(b),
+            Value::BoolArray(ref arr) => rmpv::Value::Array(arr.iter().map(|&b| rmpv::Value::Boolean(b)).collect()),
+            Value::Double(num) => number(self, num)?,
+            Value::DoubleArray(ref arr) => rmpv::Value::Array(arr.iter().map(|&num| number(self, num)).collect::<Option<_>>()?),
+            Value::String(ref s) => rmpv::Value::from(s.as_str()),
+            Value::StringArray(ref arr) => rmpv::Value::Array(arr.iter().map(|s| rmpv::Value::from(s.as_str())).collect()),
+            Value::Raw(ref data) | Value::Rpc(ref data) => rmpv::Value::Binary(data.clone()),
+        })
+    }
+
+    /// Decode MessagePack data for a topic of this type. Numbers are accepted in any width, since
+    /// servers aren't always strict about it. Returns `None` if the data has the wrong shape, or
+    /// holds an integer too big to be a double exactly.
+    pub fn decode_value(self, data: &rmpv::Value) -> Option<Value> {
+        fn number(data: &rmpv::Value) -> Option<f64> {
+            // Every integer up to 2^53 has a double of its own.
+            const EXACT: u64 = 1 << 53;
+            match *data {
+                rmpv::Value::Integer(int) => match int.as_i64() {
+                    Some(int) if int.unsigned_abs() <= EXACT => Some(int as f64),
+                    _ => None,
+                },
+                rmpv::Value::F32(num) => Some(num as f64),
+                rmpv::Value::F64(num) => Some(num),
+                _ => None,
+            }
+        }
+
+        fn array<T, F: Fn(&rmpv::Value) -> Option<T>>(data: &rmpv::Value, func: F) -> Option<Vec<T>> {
+            data.as_array().and_then(|arr| arr.iter().map(func).collect())
+        }
+
+        fn string(data: &rmpv::Value) -> Option<String> {
+            data.as_str().map(str::to_owned)
+        }
+
+        Some(match self {
+            TopicType::Boolean => Value::Bool(data.as_bool()?),
+            TopicType::Double | TopicType::Int | TopicType::Float => Value::Double(number(data)?),
+            TopicType::String | TopicType::Json => Value::String(string(data)?),
+            TopicType::Raw | TopicType::MsgPack | TopicType::Protobuf => Value::Raw(data.as_slice()?.to_owned()),
+            TopicType::Rpc => Value::Rpc(data.as_slice()?.to_owned()),
+            TopicType::BooleanArray => Value::BoolArray(array(data, rmpv::Value::as_bool)?),
+            TopicType::DoubleArray | TopicType::IntArray | TopicType::FloatArray => Value::DoubleArray(array(data, number)?),
+            TopicType::StringArray => Value::StringArray(array(data, string)?),
+        })
+    }
+}
+
+/// Ways a frame can fail to decode. Every frame is self contained, so a bad frame can be skipped.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum DecodeError {
+    /// A text frame was not valid JSON.
+    Json(String),
+    /// A binary frame was not valid MessagePack.
+    MsgPack(String),
+    /// The frame was well formed, but not laid out like an NT4 message.
+    Malformed(String),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::Json(ref msg) => write!(f, "invalid JSON: {}", msg),
+            DecodeError::MsgPack(ref msg) => write!(f, "invalid MessagePack: {}", msg),
+            DecodeError::Malformed(ref msg) => write!(f, "malformed message: {}", msg),
+        }
+    }
+}
+
+impl error::Error for DecodeError {}
+
+fn malformed<T>(msg: &str) -> Result<T, DecodeError> {
+    Err(DecodeError::Malformed(msg.to_owned()))
+}
+
+/// Messages a client sends in text frames.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClientMessage {
+    Publish { name: String, pubuid: i64, ty: TopicType, persistent: bool },
+    Unpublish { pubuid: i64 },
+    SetProperties { name: String, persistent: bool },
+    /// Subscribe to every topic whose name starts with one of `prefixes`.
+    Subscribe { prefixes: Vec<String>, subuid: i64 },
+    Unsubscribe { subuid: i64 },
+}
+
+impl ClientMessage {
+    fn to_json(&self) -> Json {
+        match *self {
+            ClientMessage::Publish { ref name, pubuid, ty, persistent } => json!({
+                "method": "publish",
+                "params": { "name": name, "pubuid": pubuid, "type": ty.name(), "properties": { "persistent": persistent } },
+            }),
+            ClientMessage::Unpublish { pubuid } => json!({ "method": "unpublish", "params": { "pubuid": pubuid } }),
+            ClientMessage::SetProperties { ref name, persistent } => json!({
+                "method": "setproperties",
+                "params": { "name": name, "update": { "persistent": persistent } },
+            }),
+            ClientMessage::Subscribe { ref prefixes, subuid } => json!({
+                "method": "subscribe",
+                "params": { "topics": prefixes, "subuid": subuid, "options": { "prefix": true } },
+            }),
+            ClientMessage::Unsubscribe { subuid } => json!({ "method": "unsubscribe", "params": { "subuid": subuid } }),
+        }
+    }
+}
+
+/// Encode messages as the contents of one text frame.
+pub fn encode_text(msgs: &[ClientMessage]) -> String {
+    Json::Array(msgs.iter().map(ClientMessage::to_json).collect()).to_string()
+}
+
+/// Messages a server sends in text frames.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ServerMessage {
+    /// A topic was published. `pubuid` is set when the announcement answers our own publish.
+    Announce { name: String, id: i64, ty: TopicType, pubuid: Option<i64>, persistent: bool },
+    Unannounce { name: String, id: i64 },
+    /// The properties of a topic changed. Only the persistent property is tracked.
+    Properties { name: String, persistent: Option<bool> },
+}
+
+impl ServerMessage {
+    /// Returns `Ok(None)` for methods that aren't understood, and for topics with types that
+    /// can't be represented.
+    fn from_json(json: &Json) -> Result<Option<ServerMessage>, DecodeError> {
+        let (method, params) = match (json.get("method").and_then(Json::as_str), json.get("params")) {
+            (Some(method), Some(params)) => (method, params),
+            _ => return malformed("message is missing its method or params"),
+        };
+        if method != "announce" && method != "unannounce" && method != "properties" { return Ok(None); }
+
+        let name = match params.get("name").and_then(Json::as_str) {
+            Some(name) => name.to_owned(),
+            None => return malformed("message is missing a topic name"),
+        };
+        let id = params.get("id").and_then(Json::as_i64);
+        let persistent = |props: Option<&Json>| props.and_then(|props| props.get("persistent")).and_then(Json::as_bool);
+
+        Ok(match method {
+            "announce" => {
+                let id = match id { Some(id) => id, None => return malformed("announce is missing a topic id") };
+                let ty = match params.get("type").and_then(Json::as_str) {
+                    Some(ty) => ty,
+                    None => return malformed("announce is missing a type"),
+                };
+                TopicType::from_name(ty).map(|ty| ServerMessage::Announce {
+                    name,
+                    id,
+                    ty,
+                    pubuid: params.get("pubuid").and_then(Json::as_i64),
+                    persistent: persistent(params.get("properties")).unwrap_or(false),
+                })
+            }
+            "unannounce" => match id {
+                Some(id) => Some(ServerMessage::Unannounce { name, id }),
+                None => return malformed("unannounce is missing a topic id"),
+            },
+            _ => Some(ServerMessage::Properties { name, persistent: persistent(params.get("update")) }),
+        })
+    }
+}
+
+/// Decode the contents of a text frame.
+pub fn decode_text(text: &str) -> Result<Vec<ServerMessage>, DecodeError> {
+    let json: Json = serde_json::from_str(text).map_err(|err| DecodeError::Json(err.to_string()))?;
+    let msgs = match json.as_array() {
+        Some(msgs) => msgs,
+        None => return malformed("text frame is not an array"),
+    };
+
+    let mut out = Vec::new();
+    for msg in msgs {
+        if let Some(msg) = ServerMessage::from_json(msg)? { out.push(msg); }
+    }
+    Ok(out)
+}
+
+/// A value sent in a binary frame. Timestamps are in microseconds of server time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DataMessage {
+    pub id: i64,
+    pub timestamp: i64,
+    pub data_type: u8,
+    pub data: rmpv::Value,
+}
+
+impl DataMessage {
+    /// Append the encoded message to `out`.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        let msg = rmpv::Value::Array(vec![
+            rmpv::Value::from(self.id),
+            rmpv::Value::from(self.timestamp),
+            rmpv::Value::from(self.data_type),
+            self.data.clone(),
+        ]);
+        // Writing to a `Vec` can't fail.
+        let _ = rmpv::encode::write_value(out, &msg);
+    }
+
+    fn from_msgpack(msg: rmpv::Value) -> Result<DataMessage, DecodeError> {
+        let mut fields = match msg {
+            rmpv::Value::Array(fields) if fields.len() == 4 => fields,
+            _ => return malformed("value message is not an array of 4 elements"),
+        };
+
+        let data = fields.pop().unwrap_or(rmpv::Value::Nil);
+        match (fields[0].as_i64(), fields[1].as_i64(), fields[2].as_u64()) {
+            (Some(id), Some(timestamp), Some(data_type)) if data_type <= 0xFF =>
+                Ok(DataMessage { id, timestamp, data_type: data_type as u8, data }),
+            _ => malformed("value message has a bad id, timestamp or type"),
+        }
+    }
+}
+
+/// Encode messages as the contents of one binary frame.
+pub fn encode_binary(msgs: &[DataMessage]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for msg in msgs { msg.encode(&mut out); }
+    out
+}
+
+/// Decode the contents of a binary frame.
+pub fn decode_binary(mut data: &[u8]) -> Result<Vec<DataMessage>, DecodeError> {
+    let mut out = Vec::new();
+    while !data.is_empty() {
+        let msg = rmpv::decode::read_value(&mut data).map_err(|err| DecodeError::MsgPack(err.to_string()))?;
+        out.push(DataMessage::from_msgpack(msg)?);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integers_must_be_whole() {
+        let ty = TopicType::Int;
+        assert_eq!(ty.encode_value(&Value::Double(-42.0)), Some(rmpv::Value::from(-42)));
+        assert_eq!(ty.encode_value(&Value::Double(1.5)), None);
+        assert_eq!(ty.encode_value(&Value::Double(f64::NAN)), None);
+        assert_eq!(TopicType::IntArray.encode_value(&Value::DoubleArray(vec![1.0, 2.5])), None);
+        assert_eq!(TopicType::Double.encode_value(&Value::Double(1.5)), Some(rmpv::Value::F64(1.5)));
+    }
+
+    #[test]
+    fn integers_must_fit() {
+        let ty = TopicType::Int;
+        let min = i64::MIN as f64;
+        assert_eq!(ty.encode_value(&Value::Double(min)), Some(rmpv::Value::from(i64::MIN)));
+        assert_eq!(ty.encode_value(&Value::Double(-min)), None);
+        assert_eq!(ty.encode_value(&Value::Double(1e300)), None);
+        assert_eq!(ty.encode_value(&Value::Double(f64::INFINITY)), None);
+    }
+
+    #[test]
+    fn received_integers_must_be_exact() {
+        let ty = TopicType::Int;
+        assert_eq!(ty.decode_value(&rmpv::Value::from(1i64 << 53)), Some(Value::Double(9_007_199_254_740_992.0)));
+        assert_eq!(ty.decode_value(&rmpv::Value::from(-(1i64 << 53))), Some(Value::Double(-9_007_199_254_740_992.0)));
+        assert_eq!(ty.decode_value(&rmpv::Value::from((1i64 << 53) + 1)), None);
+        assert_eq!(ty.decode_value(&rmpv::Value::from(u64::MAX)), None);
+        assert_eq!(TopicType::IntArray.decode_value(&rmpv::Value::Array(vec![rmpv::Value::from(1), rmpv::Value::from(i64::MAX)])), None);
+    }
+}
@@ -0,0 +1,12 @@
+//! A pure-Rust implementation of the NetworkTables 4 protocol. NT4 runs over a WebSocket, with
+//! topic announcements and subscriptions sent as JSON text frames, and values sent as MessagePack
+//! binary frames.
+
+pub mod client;
+pub mod codec;
+
+/// The port NT4 servers listen on by default.
+pub const DEFAULT_PORT: u32 = 5810;
+
+/// The WebSocket subprotocol NT4 servers expect clients to ask for.
+pub const SUBPROTOCOL: &str = "networktables.first.wpi.edu";
@@ -0,0 +1,675 @@
+//! Reading and writing the text format ntcore uses for persistent files, without needing a running
+//! instance. A file looks like this:
+//! ```text
+//! [NetworkTables Storage 3.0]
+//! boolean "/SmartDashboard/enabled"=true
+//! double "/Drive/kP"=0.25
+//! string "/Auto/mode"="two \"ball\""
+//! raw "/Vision/mask"=3q2+7w==
+//! array double "/Drive/gains"=0.25,0,1.5
+//! array string "/Auto/modes"="left","right"
+//! ```
+//! Files written by `write` read back into the same entries, and writing those entries again gives
+//! the same bytes. That doesn't hold for files from elsewhere: `read` drops comments and blank
+//! lines, and `write` prints doubles with as many digits as it takes to read them back exactly,
+//! where ntcore may use fewer. To change a few entries of an existing file without touching the
+//! rest of it, use `Document`, which keeps the text of every line it didn't change.
+
+use std::fmt;
+use std::error;
+use std::io::{self, Write};
+use entry::Value;
+
+/// The first line of every persistent file.
+pub const HEADER: &str = "[NetworkTables Storage 3.0]";
+
+/// A problem with the contents of a persistent file. Lines and columns start at 1.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl error::Error for ParseError {}
+
+/// Parse the contents of a persistent file into a list of entry names and values, in the order they
+/// appear in the file.
+pub fn read(input: &str) -> Result<Vec<(String, Value)>, ParseError> {
+    Document::parse(input).map(Document::into_entries)
+}
+
+/// Parse the contents of a persistent file, skipping lines that can't be parsed like ntcore does.
+/// The problem with each skipped line is passed to `warn`. Only a missing header fails the whole
+/// file.
+pub fn read_lenient<F: FnMut(ParseError)>(input: &str, warn: F) -> Result<Vec<(String, Value)>, ParseError> {
+    Document::parse_lenient(input, warn).map(Document::into_entries)
+}
+
+/// Write entries in the persistent file format. RPC definitions can't be persisted, so those
+/// entries are skipped, like ntcore does.
+pub fn write<W: Write>(out: &mut W, entries: &[(String, Value)]) -> io::Result<()> {
+    writeln!(out, "{}", HEADER)?;
+    for (name, value) in entries {
+        if let Some(line) = format_entry(name, value) {
+            writeln!(out, "{}", line)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write entries in the persistent file format to a string.
+pub fn to_string(entries: &[(String, Value)]) -> String {
+    let mut out = Vec::new();
+    // Writing to a `Vec` can't fail.
+    write(&mut out, entries).unwrap();
+    // Everything we write is either ASCII or comes from a `str`.
+    String::from_utf8(out).unwrap()
+}
+
+/// A persistent file that remembers the text it was parsed from. Writing it back reproduces that
+/// text exactly, except for the lines of entries that were changed with `set` or `remove`, so
+/// comments, spacing and ntcore's own number formatting survive an edit.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Document {
+    lines: Vec<Line>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Line {
+    /// The line as it appeared in the file, including its line ending. `None` for entries added or
+    /// changed since, which are formatted when the document is written.
+    text: Option<String>,
+    /// The entry on this line, or `None` for the header, comments and blank lines.
+    entry: Option<(String, Value)>,
+}
+
+impl Document {
+    /// A file with just the header.
+    pub fn new() -> Document {
+        Document { lines: vec![Line { text: Some(format!("{}\n", HEADER)), entry: None }] }
+    }
+
+    /// Parse the contents of a persistent file.
+    pub fn parse(input: &str) -> Result<Document, ParseError> {
+        Document::parse_lines(input, &mut |err| Err(err))
+    }
+
+    /// Parse the contents of a persistent file, skipping lines that can't be parsed like ntcore
+    /// does. The problem with each skipped line is passed to `warn`, and the document keeps the
+    /// line as it was. Only a missing header fails the whole file.
+    pub fn parse_lenient<F: FnMut(ParseError)>(input: &str, mut warn: F) -> Result<Document, ParseError> {
+        Document::parse_lines(input, &mut |err| {
+            warn(err);
+            Ok(())
+        })
+    }
+
+    /// Parse every line, passing bad entry lines to `bad_line`, which either fails the parse or
+    /// lets it carry on without the entry.
+    fn parse_lines<F>(input: &str, bad_line: &mut F) -> Result<Document, ParseError>
+        where F: FnMut(ParseError) -> Result<(), ParseError>
+    {
+        let mut lines = Vec::new();
+        let mut seen_header = false;
+
+        for (idx, text) in input.split_inclusive('\n').enumerate() {
+            let line = text.strip_suffix('\n').unwrap_or(text);
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            let mut parser = LineParser { line, line_no: idx + 1, pos: 0 };
+            parser.skip_whitespace();
+
+            let entry = if parser.at_end() || parser.rest().starts_with(';') || parser.rest().starts_with('#') {
+                None
+            } else if !seen_header {
+                if parser.rest().trim_end() != HEADER {
+                    return Err(parser.error(format!("expected header `{}`", HEADER)));
+                }
+                seen_header = true;
+                None
+            } else {
+                match parser.entry() {
+                    Ok(entry) => Some(entry),
+                    Err(err) => {
+                        bad_line(err)?;
+                        None
+                    }
+                }
+            };
+            lines.push(Line { text: Some(text.to_owned()), entry });
+        }
+
+        if !seen_header {
+            return Err(ParseError { line: 1, column: 1, message: format!("expected header `{}`", HEADER) });
+        }
+
+        Ok(Document { lines })
+    }
+
+    /// The value of an entry. If the file has the same name more than once, the last one wins, like
+    /// it does when ntcore loads the file.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.lines.iter()
+            .rev()
+            .filter_map(|line| line.entry.as_ref())
+            .find(|entry| entry.0 == name)
+            .map(|entry| &entry.1)
+    }
+
+    /// Change the value of an entry, or add it to the end of the file if it isn't there yet. RPC
+    /// definitions can't be persisted, so setting one removes the entry instead.
+    pub fn set(&mut self, name: &str, value: Value) {
+        if let Value::Rpc(_) = value {
+            self.remove(name);
+            return;
+        }
+
+        let existing = self.lines.iter_mut()
+            .rev()
+            .find(|line| line.entry.as_ref().is_some_and(|entry| entry.0 == name));
+        match existing {
+            Some(line) => {
+                if line.entry.as_ref().map(|entry| &entry.1) != Some(&value) {
+                    *line = Line { text: None, entry: Some((name.to_owned(), value)) };
+                }
+            }
+            None => self.lines.push(Line { text: None, entry: Some((name.to_owned(), value)) }),
+        }
+    }
+
+    /// Remove every line for an entry, returning the value it had.
+    pub fn remove(&mut self, name: &str) -> Option<Value> {
+        let mut removed = None;
+        self.lines.retain(|line| match line.entry {
+            Some((ref entry_name, ref value)) if entry_name == name => {
+                removed = Some(value.clone());
+                false
+            }
+            _ => true,
+        });
+        removed
+    }
+
+    /// The entries in the file, in the order they appear.
+    pub fn into_entries(self) -> Vec<(String, Value)> {
+        self.lines.into_iter().filter_map(|line| line.entry).collect()
+    }
+
+    /// Write the document out. Lines that weren't changed are written exactly as they were read.
+    pub fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write!(out, "{}", self)
+    }
+}
+
+impl Default for Document {
+    fn default() -> Document {
+        Document::new()
+    }
+}
+
+impl fmt::Display for Document {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut needs_newline = false;
+        for line in &self.lines {
+            let text = match (&line.text, &line.entry) {
+                (Some(text), _) => text.clone(),
+                (None, Some((name, value))) => match format_entry(name, value) {
+                    Some(text) => text + "\n",
+                    None => continue,
+                },
+                (None, None) => continue,
+            };
+            // A new line after a last line that had no line ending needs one of its own.
+            if needs_newline && line.text.is_none() {
+                f.write_str("\n")?;
+            }
+            needs_newline = !text.ends_with('\n');
+            f.write_str(&text)?;
+        }
+        Ok(())
+    }
+}
+
+/// Format one entry as a line of a persistent file, without the line ending. Returns `None` for RPC
+/// definitions, which can't be persisted.
+fn format_entry(name: &str, value: &Value) -> Option<String> {
+    let ty = match *value {
+        Value::Bool(_) => "boolean",
+        Value::Double(_) => "double",
+        Value::String(_) => "string",
+        Value::Raw(_) => "raw",
+        Value::BoolArray(_) => "array boolean",
+        Value::DoubleArray(_) => "array double",
+        Value::StringArray(_) => "array string",
+        Value::Rpc(_) => return None,
+    };
+
+    let mut line = format!("{} ", ty);
+    write_quoted(&mut line, name);
+    line.push('=');
+
+    match *value {
+        Value::Bool(val) => line.push_str(bool_str(val)),
+        Value::Double(val) => line.push_str(&val.to_string()),
+        Value::String(ref val) => write_quoted(&mut line, val),
+        Value::Raw(ref val) => line.push_str(&base64_encode(val)),
+        Value::BoolArray(ref arr) => {
+            line.push_str(&arr.iter().map(|&val| bool_str(val)).collect::<Vec<_>>().join(","))
+        }
+        Value::DoubleArray(ref arr) => {
+            line.push_str(&arr.iter().map(|val| val.to_string()).collect::<Vec<_>>().join(","))
+        }
+        Value::StringArray(ref arr) => {
+            for (i, val) in arr.iter().enumerate() {
+                if i != 0 { line.push(','); }
+                write_quoted(&mut line, val);
+            }
+        }
+        Value::Rpc(_) => unreachable!(),
+    }
+
+    Some(line)
+}
+
+fn bool_str(val: bool) -> &'static str {
+    if val { "true" } else { "false" }
+}
+
+/// Quote and escape a string the same way ntcore does. Anything that isn't printable ASCII is
+/// escaped byte by byte, and so is `=`, which ntcore escapes so names never contain a bare one.
+fn write_quoted(out: &mut String, string: &str) {
+    out.push('"');
+    for &byte in string.as_bytes() {
+        match byte {
+            b'\\' => out.push_str("\\\\"),
+            b'"' => out.push_str("\\\""),
+            b'\t' => out.push_str("\\t"),
+            b'\n' => out.push_str("\\n"),
+            b'=' => out.push_str("\\x3D"),
+            0x20..=0x7e => out.push(byte as char),
+            _ => out.push_str(&format!("\\x{:02X}", byte)),
+        }
+    }
+    out.push('"');
+}
+
+struct LineParser<'a> {
+    line: &'a str,
+    line_no: usize,
+    /// Byte offset into `line`.
+    pos: usize,
+}
+
+impl<'a> LineParser<'a> {
+    fn error<S: Into<String>>(&self, message: S) -> ParseError {
+        self.error_at(self.pos, message)
+    }
+
+    fn error_at<S: Into<String>>(&self, pos: usize, message: S) -> ParseError {
+        let column = self.line[..pos].chars().count() + 1;
+        ParseError { line: self.line_no, column, message: message.into() }
+    }
+
+    fn rest(&self) -> &'a str { &self.line[self.pos..] }
+    fn at_end(&self) -> bool { self.pos >= self.line.len() }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, prefix: &str) -> bool {
+        if self.rest().starts_with(prefix) {
+            self.pos += prefix.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, prefix: &str) -> Result<(), ParseError> {
+        if self.eat(prefix) { Ok(()) } else { Err(self.error(format!("expected `{}`", prefix))) }
+    }
+
+    fn expect_end(&mut self) -> Result<(), ParseError> {
+        self.skip_whitespace();
+        if self.at_end() { Ok(()) } else { Err(self.error("unexpected trailing characters")) }
+    }
+
+    /// Take the rest of the line as a single value.
+    fn take_rest(&mut self) -> (usize, &'a str) {
+        let start = self.pos;
+        let rest = self.rest();
+        self.pos = self.line.len();
+        (start, rest.trim())
+    }
+
+    /// Split the rest of the line at commas. An empty line has no items; anything else has one more
+    /// item than it has commas, so a trailing comma leaves an empty item to be rejected.
+    fn take_items(&mut self) -> Vec<(usize, &'a str)> {
+        let mut items = Vec::new();
+        if self.at_end() { return items; }
+        loop {
+            let start = self.pos;
+            let rest = self.rest();
+            let len = rest.find(',').unwrap_or(rest.len());
+            self.pos += len;
+            items.push((start, rest[..len].trim()));
+            if !self.eat(",") { return items; }
+        }
+    }
+
+    fn entry(&mut self) -> Result<(String, Value), ParseError> {
+        let array = self.eat("array ");
+        let ty = ["boolean ", "double ", "string ", "raw "].iter()
+            .cloned()
+            .find(|ty| (!array || *ty != "raw ") && self.eat(ty))
+            .ok_or_else(|| self.error("unrecognized type"))?;
+
+        self.skip_whitespace();
+        let name = self.quoted()?;
+        self.skip_whitespace();
+        self.expect("=")?;
+        self.skip_whitespace();
+
+        let value = match (array, ty) {
+            (false, "boolean ") => {
+                let (pos, item) = self.take_rest();
+                Value::Bool(self.parse_bool(pos, item)?)
+            }
+            (false, "double ") => {
+                let (pos, item) = self.take_rest();
+                Value::Double(self.parse_double(pos, item)?)
+            }
+            (false, "string ") => Value::String(self.quoted()?),
+            (false, _) => {
+                let start = self.pos;
+                let data = self.rest().trim();
+                self.pos = self.line.len();
+                Value::Raw(base64_decode(data).map_err(|offset| self.error_at(start + offset, "invalid base64"))?)
+            }
+            (true, "boolean ") => {
+                let items = self.take_items();
+                Value::BoolArray(items.into_iter().map(|(pos, item)| self.parse_bool(pos, item)).collect::<Result<_, _>>()?)
+            }
+            (true, "double ") => {
+                let items = self.take_items();
+                Value::DoubleArray(items.into_iter().map(|(pos, item)| self.parse_double(pos, item)).collect::<Result<_, _>>()?)
+            }
+            (true, _) => {
+                let mut arr = Vec::new();
+                if !self.at_end() {
+                    loop {
+                        arr.push(self.quoted()?);
+                        self.skip_whitespace();
+                        if !self.eat(",") { break; }
+                        self.skip_whitespace();
+                    }
+                }
+                Value::StringArray(arr)
+            }
+        };
+
+        self.expect_end()?;
+        Ok((name, value))
+    }
+
+    fn parse_bool(&self, pos: usize, item: &str) -> Result<bool, ParseError> {
+        match item {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            _ => Err(self.error_at(pos, format!("expected `true` or `false`, found `{}`", item))),
+        }
+    }
+
+    fn parse_double(&self, pos: usize, item: &str) -> Result<f64, ParseError> {
+        item.parse().map_err(|_| self.error_at(pos, format!("`{}` is not a number", item)))
+    }
+
+    /// Parse a quoted, escaped string.
+    fn quoted(&mut self) -> Result<String, ParseError> {
+        let start = self.pos;
+        self.expect("\"")?;
+
+        let mut bytes = Vec::new();
+        loop {
+            let rest = self.rest().as_bytes();
+            match rest.first() {
+                None => return Err(self.error_at(start, "unterminated string")),
+                Some(&b'"') => {
+                    self.pos += 1;
+                    break;
+                }
+                Some(&b'\\') => {
+                    let escape_pos = self.pos;
+                    let (byte, len) = match rest.get(1) {
+                        Some(&b't') => (b'\t', 2),
+                        Some(&b'n') => (b'\n', 2),
+                        Some(&b'x') => {
+                            let digits = rest.get(2..4)
+                                .and_then(|digits| ::std::str::from_utf8(digits).ok())
+                                .and_then(|digits| u8::from_str_radix(digits, 16).ok());
+                            match digits {
+                                Some(byte) => (byte, 4),
+                                None => return Err(self.error_at(escape_pos, "invalid hex escape")),
+                            }
+                        }
+                        // Any other escaped character stands for itself, which covers `\\` and `\"`.
+                        Some(&byte) if byte.is_ascii() => (byte, 2),
+                        _ => return Err(self.error_at(escape_pos, "invalid escape")),
+                    };
+                    bytes.push(byte);
+                    self.pos += len;
+                }
+                Some(&byte) => {
+                    bytes.push(byte);
+                    self.pos += 1;
+                }
+            }
+        }
+
+        String::from_utf8(bytes).map_err(|_| self.error_at(start, "string is not valid UTF-8"))
+    }
+}
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len() / 3 * 4 + 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |acc, (i, &byte)| acc | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Decode standard base64 with padding. On failure, returns the byte offset of the bad character.
+fn base64_decode(data: &str) -> Result<Vec<u8>, usize> {
+    fn sextet(byte: u8) -> Option<u32> {
+        BASE64_ALPHABET.iter().position(|&ch| ch == byte).map(|pos| pos as u32)
+    }
+
+    let data = data.as_bytes();
+    if !data.len().is_multiple_of(4) { return Err(data.len()); }
+
+    let mut out = Vec::with_capacity(data.len() / 4 * 3);
+    for (chunk_idx, chunk) in data.chunks(4).enumerate() {
+        let is_last = chunk_idx == data.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|&&ch| ch == b'=').count();
+        if padding > 2 || (padding > 0 && !is_last) {
+            return Err(chunk_idx * 4 + 4 - padding);
+        }
+
+        let mut bits = 0;
+        for (i, &ch) in chunk[..4 - padding].iter().enumerate() {
+            bits |= sextet(ch).ok_or(chunk_idx * 4 + i)? << (18 - 6 * i);
+        }
+        for i in 0..3 - padding {
+            out.push((bits >> (16 - 8 * i)) as u8);
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A file as ntcore's `SavePersistent` writes it: names sorted, doubles printed with `%g`, and
+    /// `=` in names escaped.
+    const NTCORE_FILE: &str = "[NetworkTables Storage 3.0]\n\
+        array boolean \"/Auto/enabled\"=true,false\n\
+        array double \"/Drive/gains\"=0.25,0,1.5\n\
+        array string \"/Auto/modes\"=\"left\",\"right\"\n\
+        boolean \"/SmartDashboard/a\\x3Db\"=true\n\
+        double \"/Drive/kP\"=0.123457\n\
+        double \"/Drive/max\"=1e+20\n\
+        raw \"/Vision/mask\"=3q2+7w==\n\
+        string \"/Auto/mode\"=\"two \\\"ball\\\"\\n\\xC3\\xA9\"\n";
+
+    fn entry(name: &str, value: Value) -> (String, Value) {
+        (name.to_owned(), value)
+    }
+
+    #[test]
+    fn reads_ntcore_files() {
+        assert_eq!(read(NTCORE_FILE).unwrap(), vec![
+            entry("/Auto/enabled", Value::BoolArray(vec![true, false])),
+            entry("/Drive/gains", Value::DoubleArray(vec![0.25, 0.0, 1.5])),
+            entry("/Auto/modes", Value::StringArray(vec!["left".into(), "right".into()])),
+            entry("/SmartDashboard/a=b", Value::Bool(true)),
+            entry("/Drive/kP", Value::Double(0.123457)),
+            entry("/Drive/max", Value::Double(1e20)),
+            entry("/Vision/mask", Value::Raw(vec![0xde, 0xad, 0xbe, 0xef])),
+            entry("/Auto/mode", Value::String("two \"ball\"\né".into())),
+        ]);
+    }
+
+    #[test]
+    fn documents_write_ntcore_files_back_unchanged() {
+        assert_eq!(Document::parse(NTCORE_FILE).unwrap().to_string(), NTCORE_FILE);
+
+        let odd = "; saved by hand\r\n\r\n[NetworkTables Storage 3.0]\r\n  double \"/a\" = 1.50  \r\n# done";
+        assert_eq!(Document::parse(odd).unwrap().to_string(), odd);
+    }
+
+    #[test]
+    fn documents_only_rewrite_changed_lines() {
+        let mut doc = Document::parse(NTCORE_FILE).unwrap();
+        doc.set("/Drive/max", Value::Double(1e20));
+        doc.set("/Drive/kP", Value::Double(0.5));
+        assert_eq!(doc.remove("/Vision/mask"), Some(Value::Raw(vec![0xde, 0xad, 0xbe, 0xef])));
+        doc.set("/New", Value::Bool(false));
+
+        let expected = NTCORE_FILE
+            .replace("double \"/Drive/kP\"=0.123457", "double \"/Drive/kP\"=0.5")
+            .replace("raw \"/Vision/mask\"=3q2+7w==\n", "")
+            + "boolean \"/New\"=false\n";
+        assert_eq!(doc.to_string(), expected);
+        assert_eq!(doc.get("/Drive/kP"), Some(&Value::Double(0.5)));
+        assert_eq!(doc.get("/Vision/mask"), None);
+    }
+
+    #[test]
+    fn documents_add_a_line_ending_before_new_entries() {
+        let mut doc = Document::parse("[NetworkTables Storage 3.0]\ndouble \"/a\"=1").unwrap();
+        doc.set("/b", Value::Double(2.0));
+        assert_eq!(doc.to_string(), "[NetworkTables Storage 3.0]\ndouble \"/a\"=1\ndouble \"/b\"=2\n");
+    }
+
+    #[test]
+    fn later_lines_win() {
+        let doc = Document::parse("[NetworkTables Storage 3.0]\ndouble \"/a\"=1\ndouble \"/a\"=2\n").unwrap();
+        assert_eq!(doc.get("/a"), Some(&Value::Double(2.0)));
+    }
+
+    #[test]
+    fn write_matches_ntcore_for_plain_values() {
+        let entries = read(NTCORE_FILE).unwrap()
+            .into_iter()
+            .filter(|(name, _)| name != "/Drive/kP" && name != "/Drive/max")
+            .collect::<Vec<_>>();
+        let expected = NTCORE_FILE.lines()
+            .filter(|line| !line.starts_with("double "))
+            .map(|line| format!("{}\n", line))
+            .collect::<String>();
+        assert_eq!(to_string(&entries), expected);
+    }
+
+    #[test]
+    fn written_files_read_back() {
+        let entries = vec![
+            entry("/a", Value::Double(0.1 + 0.2)),
+            entry("/b", Value::DoubleArray(vec![-1e-300, 12345.678])),
+            entry("/c", Value::String("tab\tquote\"back\\slash=".into())),
+            entry("/d", Value::Raw(vec![1, 2])),
+            entry("/e", Value::Raw(vec![])),
+            entry("/f", Value::StringArray(vec![])),
+        ];
+        let text = to_string(&entries);
+        assert_eq!(read(&text).unwrap(), entries);
+        assert_eq!(to_string(&read(&text).unwrap()), text);
+    }
+
+    #[test]
+    fn rpc_definitions_are_skipped() {
+        let entries = vec![entry("/rpc", Value::Rpc(vec![1])), entry("/a", Value::Bool(true))];
+        assert_eq!(to_string(&entries), "[NetworkTables Storage 3.0]\nboolean \"/a\"=true\n");
+    }
+
+    fn error(input: &str) -> (usize, usize) {
+        let err = read(&format!("[NetworkTables Storage 3.0]\n{}\n", input)).unwrap_err();
+        (err.line, err.column)
+    }
+
+    #[test]
+    fn scalars_reject_trailing_commas() {
+        assert_eq!(error("boolean \"/a\"=true,"), (2, 14));
+        assert_eq!(error("double \"/a\"=1.5,"), (2, 13));
+    }
+
+    #[test]
+    fn arrays_reject_trailing_commas() {
+        assert!(read("[NetworkTables Storage 3.0]\narray double \"/a\"=1,2,\n").is_err());
+        assert!(read("[NetworkTables Storage 3.0]\narray boolean \"/a\"=true,\n").is_err());
+        assert!(read("[NetworkTables Storage 3.0]\narray string \"/a\"=\"x\",\n").is_err());
+    }
+
+    #[test]
+    fn lenient_reads_skip_bad_lines() {
+        let input = "[NetworkTables Storage 3.0]\ndouble \"/a\"=1\ndouble \"/b\"=oops\nboolean \"/c\"=true\n";
+        let mut errors = Vec::new();
+        let entries = read_lenient(input, |err| errors.push((err.line, err.column))).unwrap();
+        assert_eq!(entries, vec![entry("/a", Value::Double(1.0)), entry("/c", Value::Bool(true))]);
+        assert_eq!(errors, vec![(3, 13)]);
+
+        let doc = Document::parse_lenient(input, |_| {}).unwrap();
+        assert_eq!(doc.to_string(), input);
+        assert!(read_lenient("double \"/a\"=1\n", |_| {}).is_err());
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        assert_eq!(error("number \"/a\"=1"), (2, 1));
+        assert_eq!(error("double \"/a\"=one"), (2, 13));
+        assert_eq!(error("string \"/a\"=\"open"), (2, 13));
+        assert_eq!(error("raw \"/a\"=AB*="), (2, 12));
+        assert_eq!(error("string \"/a\"=\"\\xZZ\""), (2, 14));
+
+        let err = read("double \"/a\"=1\n").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 1));
+        assert!(read("").is_err());
+    }
+}
@@ -0,0 +1,744 @@
+//! Remote procedure calls. Making and answering calls needs ntcore, but typed definitions can be
+//! packed and unpacked with any backend.
+
+use std::error;
+use std::fmt;
+#[cfg(feature = "ntcore-sys")]
+use std::os::raw::{c_char, c_void};
+#[cfg(feature = "ntcore-sys")]
+use std::panic::{self, AssertUnwindSafe};
+#[cfg(feature = "ntcore-sys")]
+use std::collections::HashSet;
+#[cfg(feature = "ntcore-sys")]
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "ntcore-sys")]
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+#[cfg(feature = "ntcore-sys")]
+use std::thread;
+#[cfg(feature = "ntcore-sys")]
+use std::time::Duration;
+#[cfg(feature = "ntcore-sys")]
+use sys::{self, NT_Entry, NT_Inst, NT_RpcAnswer, NT_RpcCall, NT_RpcCallPoller};
+#[cfg(feature = "ntcore-sys")]
+use backend::Backend;
+#[cfg(feature = "ntcore-sys")]
+use connection::OwnedConnectionInfo;
+#[cfg(feature = "ntcore-sys")]
+use entry::Entry;
+use entry::{EntryType, Value};
+use wire::{self, Reader, WireError};
+use ::{Error, Result};
+#[cfg(feature = "ntcore-sys")]
+use ::{duration_secs, NtString};
+
+#[cfg(feature = "ntcore-sys")]
+/// An incoming call to a procedure created by this instance. An answer that is dropped without
+/// calling `respond` sends back an empty result, so the caller isn't left waiting.
+#[derive(Debug, PartialEq)]
+pub struct RpcAnswer {
+    entry: Entry,
+    call: NT_RpcCall,
+    name: String,
+    params: Vec<u8>,
+    conn: OwnedConnectionInfo,
+    responded: bool,
+}
+
+#[cfg(feature = "ntcore-sys")]
+impl RpcAnswer {
+    unsafe fn from_raw(backend: &Arc<dyn Backend>, raw: &NT_RpcAnswer) -> Self {
+        RpcAnswer {
+            entry: Entry::new(backend.clone(), raw.entry),
+            call: raw.call,
+            name: NtString(raw.name).to_str_lossy().into_owned(),
+            params: NtString(raw.params).as_bytes().to_owned(),
+            conn: OwnedConnectionInfo::from_raw(&raw.conn),
+            responded: false,
+        }
+    }
+
+    /// The procedure entry that was called.
+    pub fn entry(&self) -> Entry { self.entry.clone() }
+    pub fn name(&self) -> &str { &self.name }
+    /// The packed parameters sent by the caller.
+    pub fn params(&self) -> &[u8] { &self.params }
+    /// The node that made the call.
+    pub fn connection(&self) -> &OwnedConnectionInfo { &self.conn }
+
+    /// Send the packed result of this call back to the caller.
+    pub fn respond(mut self, result: &[u8]) {
+        self.responded = true;
+        post_response(self.entry.handle, self.call, result)
+    }
+}
+
+#[cfg(feature = "ntcore-sys")]
+impl Drop for RpcAnswer {
+    fn drop(&mut self) {
+        if !self.responded {
+            post_response(self.entry.handle, self.call, &[])
+        }
+    }
+}
+
+#[cfg(feature = "ntcore-sys")]
+fn post_response(entry: NT_Entry, call: NT_RpcCall, result: &[u8]) {
+    unsafe { sys::NT_PostRpcResponse(entry, call, result.as_ptr() as *const c_char, result.len()) }
+}
+
+/// What the C callback data of a procedure points to. Answers need the backend to hand out entries.
+#[cfg(feature = "ntcore-sys")]
+struct RpcHandler {
+    backend: Arc<dyn Backend>,
+    callback: Box<dyn Fn(&RpcAnswer) -> Vec<u8> + Send + 'static>,
+}
+
+#[cfg(feature = "ntcore-sys")]
+unsafe extern "C" fn rpc_trampoline(data: *mut c_void, call: *const NT_RpcAnswer) {
+    let handler = &*(data as *const RpcHandler);
+    let call = &*call;
+    // A handler that panics still has to answer, otherwise the caller is left waiting. Copying the
+    // call happens inside too, so nothing unwinds into C.
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut answer = RpcAnswer::from_raw(&handler.backend, call);
+        // The result is posted below instead of when the answer is dropped.
+        answer.responded = true;
+        (handler.callback)(&answer)
+    })).unwrap_or_default();
+    post_response(call.entry, call.call, &result);
+}
+
+#[cfg(feature = "ntcore-sys")]
+pub(crate) fn create_rpc<F>(entry: &Entry, definition: &[u8], handler: F) -> Result<()>
+    where F: Fn(&RpcAnswer) -> Vec<u8> + Send + 'static
+{
+    let handle = entry.native_handle()?;
+    let handler = Box::new(RpcHandler { backend: entry.backend.clone(), callback: Box::new(handler) });
+    // There is no NT_RemoveRpc, so the handler is leaked on purpose.
+    let data = Box::into_raw(handler) as *mut c_void;
+    unsafe {
+        sys::NT_CreateRpc(handle, definition.as_ptr() as *const c_char, definition.len(), data, Some(rpc_trampoline))
+    }
+    Ok(())
+}
+
+#[cfg(feature = "ntcore-sys")]
+pub(crate) fn create_polled_rpc(entry: NT_Entry, definition: &[u8], poller: &RpcCallPoller) {
+    unsafe {
+        sys::NT_CreatePolledRpc(entry, definition.as_ptr() as *const c_char, definition.len(), poller.handle)
+    }
+}
+
+/// A queue of incoming RPC calls that is drained manually. The underlying poller is destroyed when
+/// this is dropped.
+#[cfg(feature = "ntcore-sys")]
+pub struct RpcCallPoller {
+    backend: Arc<dyn Backend>,
+    handle: NT_RpcCallPoller,
+}
+
+#[cfg(feature = "ntcore-sys")]
+impl RpcCallPoller {
+    pub(crate) fn new(backend: Arc<dyn Backend>, inst: NT_Inst) -> Self {
+        RpcCallPoller { backend, handle: unsafe { sys::NT_CreateRpcCallPoller(inst) } }
+    }
+
+    /// Block until there is at least one call in the queue, then return everything in it.
+    /// Returns an empty list if the poll was cancelled with `cancel`.
+    pub fn poll(&self) -> Vec<RpcAnswer> {
+        unsafe {
+            let mut len = 0;
+            let ptr = sys::NT_PollRpc(self.handle, &mut len);
+            take_answers(&self.backend, ptr, len)
+        }
+    }
+
+    /// Like `poll`, but gives up after `timeout`. Returns `None` if the timeout expired.
+    pub fn poll_timeout(&self, timeout: Duration) -> Option<Vec<RpcAnswer>> {
+        unsafe {
+            let mut len = 0;
+            let mut timed_out = 0;
+            let ptr = sys::NT_PollRpcTimeout(self.handle, &mut len, duration_secs(timeout), &mut timed_out);
+            if timed_out != 0 { None } else { Some(take_answers(&self.backend, ptr, len)) }
+        }
+    }
+
+    /// Wake up any thread blocked in `poll` or `poll_timeout`.
+    pub fn cancel(&self) {
+        unsafe { sys::NT_CancelPollRpc(self.handle) }
+    }
+}
+
+#[cfg(feature = "ntcore-sys")]
+impl fmt::Debug for RpcCallPoller {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RpcCallPoller").field("handle", &self.handle).finish()
+    }
+}
+
+#[cfg(feature = "ntcore-sys")]
+impl Drop for RpcCallPoller {
+    fn drop(&mut self) {
+        unsafe { sys::NT_DestroyRpcCallPoller(self.handle) }
+    }
+}
+
+#[cfg(feature = "ntcore-sys")]
+unsafe fn take_answers(backend: &Arc<dyn Backend>, ptr: *mut NT_RpcAnswer, len: usize) -> Vec<RpcAnswer> {
+    if ptr.is_null() { return Vec::new(); }
+    let ret = ::std::slice::from_raw_parts(ptr, len).iter()
+        .map(|raw| RpcAnswer::from_raw(backend, raw))
+        .collect();
+    sys::NT_DisposeRpcAnswerArray(ptr, len);
+    ret
+}
+
+/// A pending call to a remote procedure. If the call is dropped before its result arrives, it is
+/// cancelled.
+#[cfg(feature = "ntcore-sys")]
+#[must_use]
+#[derive(Debug, Eq, PartialEq, Hash)]
+pub struct RpcCall {
+    entry: NT_Entry,
+    handle: NT_RpcCall,
+    /// Set once ntcore is done with the call, whether it got a result or not.
+    finished: bool,
+}
+
+#[cfg(feature = "ntcore-sys")]
+impl RpcCall {
+    pub(crate) fn new(entry: NT_Entry, params: &[u8]) -> Self {
+        let handle = unsafe { sys::NT_CallRpc(entry, params.as_ptr() as *const c_char, params.len()) };
+        RpcCall { entry, handle, finished: false }
+    }
+
+    /// Identifies this call among the results of an `RpcResultPoller`.
+    pub fn id(&self) -> RpcCallId { RpcCallId(self.entry, self.handle) }
+
+    /// Block until the result of the call arrives. Fails if the call failed or was cancelled.
+    pub fn wait(mut self) -> Result<Vec<u8>, RpcError> {
+        let result = unsafe {
+            let mut len = 0;
+            let ptr = sys::NT_GetRpcResult(self.entry, self.handle, &mut len);
+            take_result(ptr, len)
+        };
+        self.finished = true;
+        result.ok_or(RpcError::NoResult)
+    }
+
+    /// Like `wait`, but gives up after `timeout`. Returns `Ok(None)` if the timeout expired, in
+    /// which case the call is still pending and can be waited on again.
+    pub fn wait_timeout(&mut self, timeout: Duration) -> Result<Option<Vec<u8>>, RpcError> {
+        let (result, timed_out) = unsafe {
+            let mut len = 0;
+            let mut timed_out = 0;
+            let ptr = sys::NT_GetRpcResultTimeout(self.entry, self.handle, &mut len, duration_secs(timeout), &mut timed_out);
+            (take_result(ptr, len), timed_out != 0)
+        };
+        if timed_out { return Ok(None); }
+
+        self.finished = true;
+        result.map(Some).ok_or(RpcError::NoResult)
+    }
+
+    /// Stop waiting for the result of this call. Dropping the call does the same.
+    pub fn cancel(self) {}
+}
+
+#[cfg(feature = "ntcore-sys")]
+impl Drop for RpcCall {
+    fn drop(&mut self) {
+        if !self.finished {
+            unsafe { sys::NT_CancelRpcResult(self.entry, self.handle) }
+        }
+    }
+}
+
+#[cfg(feature = "ntcore-sys")]
+unsafe fn take_result(ptr: *mut c_char, len: usize) -> Option<Vec<u8>> {
+    if ptr.is_null() { return None; }
+    let ret = ::std::slice::from_raw_parts(ptr as *const u8, len).to_owned();
+    sys::NT_FreeCharArray(ptr);
+    Some(ret)
+}
+
+/// Identifies a call made with `Entry::call_rpc`.
+#[cfg(feature = "ntcore-sys")]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct RpcCallId(NT_Entry, NT_RpcCall);
+
+/// The outcome of a call handed to an `RpcResultPoller`.
+#[cfg(feature = "ntcore-sys")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct RpcCallResult {
+    pub call: RpcCallId,
+    pub result: Result<Vec<u8>, RpcError>,
+}
+
+/// A queue of the results of calls to remote procedures, for waiting on many calls from one
+/// thread. Calls that are still pending when this is dropped are cancelled.
+///
+/// ntcore can only block on one call at a time, so every call added is waited on by a helper
+/// thread of its own until its result arrives.
+#[cfg(feature = "ntcore-sys")]
+pub struct RpcResultPoller {
+    sender: Sender<Option<RpcCallResult>>,
+    receiver: Receiver<Option<RpcCallResult>>,
+    pending: Arc<Mutex<HashSet<RpcCallId>>>,
+}
+
+#[cfg(feature = "ntcore-sys")]
+impl RpcResultPoller {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        RpcResultPoller { sender, receiver, pending: Arc::new(Mutex::new(HashSet::new())) }
+    }
+
+    /// Queue the result of `call` onto this poller once it arrives. Returns the id the result will
+    /// have.
+    pub fn add(&self, call: RpcCall) -> RpcCallId {
+        let id = call.id();
+        self.pending.lock().unwrap().insert(id);
+        let sender = self.sender.clone();
+        let pending = self.pending.clone();
+        thread::spawn(move || {
+            let result = call.wait();
+            pending.lock().unwrap().remove(&id);
+            // The poller may be gone already, in which case nobody wants the result.
+            let _ = sender.send(Some(RpcCallResult { call: id, result }));
+        });
+        id
+    }
+
+    /// Number of calls whose results haven't arrived yet.
+    pub fn pending(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    /// Block until there is at least one result in the queue, then return everything in it.
+    /// Returns an empty list if the poll was cancelled with `cancel`.
+    pub fn poll(&self) -> Vec<RpcCallResult> {
+        match self.receiver.recv() {
+            Ok(first) => self.drain(first),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Like `poll`, but gives up after `timeout`. Returns `None` if the timeout expired.
+    pub fn poll_timeout(&self, timeout: Duration) -> Option<Vec<RpcCallResult>> {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(first) => Some(self.drain(first)),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => Some(Vec::new()),
+        }
+    }
+
+    /// Wake up a thread blocked in `poll` or `poll_timeout`.
+    pub fn cancel(&self) {
+        let _ = self.sender.send(None);
+    }
+
+    /// Collect `first` and everything else already in the queue. A `None` is a cancellation,
+    /// which ends the poll with whatever came before it.
+    fn drain(&self, first: Option<RpcCallResult>) -> Vec<RpcCallResult> {
+        let mut ret = Vec::new();
+        let mut next = first;
+        while let Some(result) = next {
+            ret.push(result);
+            next = self.receiver.try_recv().ok().and_then(|result| result);
+        }
+        ret
+    }
+}
+
+#[cfg(feature = "ntcore-sys")]
+impl Default for RpcResultPoller {
+    fn default() -> Self { RpcResultPoller::new() }
+}
+
+#[cfg(feature = "ntcore-sys")]
+impl fmt::Debug for RpcResultPoller {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RpcResultPoller").field("pending", &self.pending()).finish()
+    }
+}
+
+#[cfg(feature = "ntcore-sys")]
+impl Drop for RpcResultPoller {
+    fn drop(&mut self) {
+        // Cancelling wakes up the helper threads, which then exit.
+        for &RpcCallId(entry, call) in self.pending.lock().unwrap().iter() {
+            unsafe { sys::NT_CancelRpcResult(entry, call) }
+        }
+    }
+}
+
+/// The version of the RPC definition format this crate reads and writes.
+const DEFINITION_VERSION: u8 = 1;
+
+/// Typed procedures answer calls they can't handle with this, followed by the error message, in
+/// place of results. NetworkTables has no way to report errors, so this is our own convention.
+const ERROR_MARKER: &[u8] = b"\xffntcore error\xff";
+
+/// Errors from packing or unpacking typed RPC data.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RpcError {
+    /// A parameter or result had a different type than the definition says it should.
+    TypeMismatch { name: String, expected: EntryType, actual: EntryType },
+    /// More values were given than the definition has parameters or results for.
+    TooManyValues { expected: usize, actual: usize },
+    /// Fewer results were given than the definition has.
+    TooFewValues { expected: usize, actual: usize },
+    /// The definition itself is not valid, for instance because it uses an unsupported version.
+    InvalidDefinition(String),
+    /// Packed data could not be decoded against the definition.
+    Malformed(String),
+    /// The handler failed to produce a result.
+    Failed(String),
+    /// The procedure answered with an error instead of results. Holds the error message.
+    Remote(String),
+    /// A call ended without a result, because it failed or was cancelled.
+    NoResult,
+    /// The entry doesn't belong to an ntcore instance, which is the only kind that can make and
+    /// answer calls.
+    Unsupported,
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RpcError::TypeMismatch { ref name, expected, actual } =>
+                write!(f, "`{}` should be of type {:?}, but got {:?}", name, expected, actual),
+            RpcError::TooManyValues { expected, actual } =>
+                write!(f, "expected at most {} values, but got {}", expected, actual),
+            RpcError::TooFewValues { expected, actual } =>
+                write!(f, "expected {} values, but got {}", expected, actual),
+            RpcError::InvalidDefinition(ref msg) => write!(f, "invalid RPC definition: {}", msg),
+            RpcError::Malformed(ref msg) => write!(f, "malformed RPC data: {}", msg),
+            RpcError::Failed(ref msg) => write!(f, "RPC failed: {}", msg),
+            RpcError::Remote(ref msg) => write!(f, "the procedure reported an error: {}", msg),
+            RpcError::NoResult => write!(f, "the call ended without a result"),
+            RpcError::Unsupported => write!(f, "RPC needs an instance backed by ntcore"),
+        }
+    }
+}
+
+impl error::Error for RpcError {}
+
+impl From<WireError> for RpcError {
+    fn from(err: WireError) -> Self { RpcError::Malformed(err.to_string()) }
+}
+
+impl From<Error> for RpcError {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Unsupported => RpcError::Unsupported,
+            err => RpcError::Failed(err.to_string()),
+        }
+    }
+}
+
+/// What typed RPC handlers return: the result values in the order of the definition.
+pub type RpcResult = Result<Vec<Value>, RpcError>;
+
+/// A parameter of a remote procedure. The type of the parameter is the type of its default value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RpcParamDef {
+    pub name: String,
+    pub default: Value,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct RpcResultDef {
+    pub name: String,
+    pub ty: EntryType,
+}
+
+/// The schema of a remote procedure, in the form stored in the value of its entry. Definitions are
+/// built up like so:
+/// ```rs
+/// let def = RpcDefinition::new("calibrate")
+///     .param("samples", 100.0)
+///     .param("verbose", false)
+///     .result("offset", EntryType::Double);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct RpcDefinition {
+    pub name: String,
+    pub params: Vec<RpcParamDef>,
+    pub results: Vec<RpcResultDef>,
+}
+
+impl RpcDefinition {
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        RpcDefinition { name: name.into(), params: Vec::new(), results: Vec::new() }
+    }
+
+    pub fn param<S: Into<String>, V: Into<Value>>(mut self, name: S, default: V) -> Self {
+        self.params.push(RpcParamDef { name: name.into(), default: default.into() });
+        self
+    }
+
+    pub fn result<S: Into<String>>(mut self, name: S, ty: EntryType) -> Self {
+        self.results.push(RpcResultDef { name: name.into(), ty });
+        self
+    }
+
+    /// Encode this definition in the NetworkTables 3 format.
+    pub fn pack(&self) -> Result<Vec<u8>, RpcError> {
+        if self.params.len() > 255 || self.results.len() > 255 {
+            return Err(RpcError::InvalidDefinition("more than 255 parameters or results".into()));
+        }
+
+        let mut out = vec![DEFINITION_VERSION];
+        wire::write_string(&mut out, &self.name);
+
+        out.push(self.params.len() as u8);
+        for param in &self.params {
+            out.push(wire_type(&param.name, param.default.entry_type())?);
+            wire::write_string(&mut out, &param.name);
+            wire::write_value(&mut out, &param.default);
+        }
+
+        out.push(self.results.len() as u8);
+        for result in &self.results {
+            out.push(wire_type(&result.name, result.ty)?);
+            wire::write_string(&mut out, &result.name);
+        }
+
+        Ok(out)
+    }
+
+    pub fn unpack(packed: &[u8]) -> Result<Self, RpcError> {
+        let mut reader = Reader::new(packed);
+        let version = reader.read_u8()?;
+        if version != DEFINITION_VERSION {
+            return Err(RpcError::InvalidDefinition(format!("unsupported version {}", version)));
+        }
+
+        let mut def = RpcDefinition::new(reader.read_string()?);
+
+        for _ in 0..reader.read_u8()? {
+            let ty = reader.read_type()?;
+            let name = reader.read_string()?;
+            let default = reader.read_value(ty)?;
+            def.params.push(RpcParamDef { name, default });
+        }
+
+        for _ in 0..reader.read_u8()? {
+            let ty = reader.read_type()?;
+            let name = reader.read_string()?;
+            def.results.push(RpcResultDef { name, ty });
+        }
+
+        Ok(def)
+    }
+
+    /// Pack call parameters, checking them against the definition. Parameters that are left off the
+    /// end take their default value.
+    pub fn pack_params(&self, args: &[Value]) -> Result<Vec<u8>, RpcError> {
+        if args.len() > self.params.len() {
+            return Err(RpcError::TooManyValues { expected: self.params.len(), actual: args.len() });
+        }
+
+        let mut out = Vec::new();
+        for (i, param) in self.params.iter().enumerate() {
+            let arg = args.get(i).unwrap_or(&param.default);
+            check_type(&param.name, param.default.entry_type(), arg)?;
+            wire::write_value(&mut out, arg);
+        }
+        Ok(out)
+    }
+
+    /// Decode packed call parameters against the definition.
+    pub fn unpack_params(&self, packed: &[u8]) -> Result<RpcArgs, RpcError> {
+        let mut reader = Reader::new(packed);
+        let args = self.params.iter()
+            .map(|param| Ok((param.name.clone(), reader.read_value(param.default.entry_type())?)))
+            .collect::<Result<_, RpcError>>()?;
+        check_finished(&reader)?;
+        Ok(RpcArgs { args })
+    }
+
+    /// Pack the results of a call, checking them against the definition. Every result has to be
+    /// given.
+    pub fn pack_results(&self, results: &[Value]) -> Result<Vec<u8>, RpcError> {
+        if results.len() > self.results.len() {
+            return Err(RpcError::TooManyValues { expected: self.results.len(), actual: results.len() });
+        }
+        if results.len() < self.results.len() {
+            return Err(RpcError::TooFewValues { expected: self.results.len(), actual: results.len() });
+        }
+
+        let mut out = Vec::new();
+        for (def, result) in self.results.iter().zip(results) {
+            check_type(&def.name, def.ty, result)?;
+            wire::write_value(&mut out, result);
+        }
+        Ok(out)
+    }
+
+    /// Decode the packed results of a call against the definition. If the procedure answered
+    /// with an error instead, that is returned as `RpcError::Remote`.
+    pub fn unpack_results(&self, packed: &[u8]) -> Result<Vec<Value>, RpcError> {
+        let mut reader = Reader::new(packed);
+        let results = self.results.iter()
+            .map(|def| reader.read_value(def.ty).map_err(RpcError::from))
+            .collect::<Result<Vec<_>, _>>()
+            .and_then(|results| check_finished(&reader).map(|_| results));
+        // Real results win, in case they happen to look like an error.
+        results.map_err(|err| unpack_error(packed).map_or(err, RpcError::Remote))
+    }
+
+    /// Pack an error to send back in place of results. `unpack_results` turns it back into an
+    /// `RpcError::Remote`.
+    pub fn pack_error(err: &RpcError) -> Vec<u8> {
+        let mut out = ERROR_MARKER.to_vec();
+        wire::write_string(&mut out, &err.to_string());
+        out
+    }
+}
+
+fn unpack_error(packed: &[u8]) -> Option<String> {
+    if !packed.starts_with(ERROR_MARKER) { return None; }
+    let mut reader = Reader::new(&packed[ERROR_MARKER.len()..]);
+    reader.read_string().ok().filter(|_| reader.remaining() == 0)
+}
+
+fn check_finished(reader: &Reader) -> Result<(), RpcError> {
+    match reader.remaining() {
+        0 => Ok(()),
+        extra => Err(RpcError::Malformed(format!("{} unexpected bytes after the last value", extra))),
+    }
+}
+
+fn wire_type(name: &str, ty: EntryType) -> Result<u8, RpcError> {
+    wire::type_id(ty).ok_or_else(|| RpcError::InvalidDefinition(format!("`{}` has no type", name)))
+}
+
+fn check_type(name: &str, expected: EntryType, value: &Value) -> Result<(), RpcError> {
+    let actual = value.entry_type();
+    if actual == expected { Ok(()) } else {
+        Err(RpcError::TypeMismatch { name: name.to_owned(), expected, actual })
+    }
+}
+
+/// The decoded parameters of a typed RPC call, in the order of the definition.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RpcArgs {
+    args: Vec<(String, Value)>,
+}
+
+impl RpcArgs {
+    /// Get a parameter by name.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.args.iter().find(|(arg_name, _)| arg_name == name).map(|(_, value)| value)
+    }
+
+    pub fn len(&self) -> usize { self.args.len() }
+    pub fn is_empty(&self) -> bool { self.args.is_empty() }
+
+    pub fn iter(&self) -> ::std::slice::Iter<'_, (String, Value)> { self.args.iter() }
+
+    pub fn into_values(self) -> Vec<Value> {
+        self.args.into_iter().map(|(_, value)| value).collect()
+    }
+}
+
+#[cfg(feature = "ntcore-sys")]
+pub(crate) fn create_typed_rpc<F>(entry: &Entry, definition: RpcDefinition, handler: F) -> Result<(), RpcError>
+    where F: Fn(RpcArgs) -> RpcResult + Send + 'static
+{
+    let packed = definition.pack()?;
+    create_rpc(entry, &packed, move |answer| {
+        definition.unpack_params(answer.params())
+            .and_then(&handler)
+            .and_then(|results| definition.pack_results(&results))
+            .unwrap_or_else(|err| RpcDefinition::pack_error(&err))
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calibrate() -> RpcDefinition {
+        RpcDefinition::new("calibrate")
+            .param("samples", 100.0)
+            .param("verbose", false)
+            .result("offset", EntryType::Double)
+            .result("log", EntryType::StringArray)
+    }
+
+    #[test]
+    fn definitions_round_trip() {
+        let packed = calibrate().pack().unwrap();
+        assert_eq!(&packed[..11], b"\x01\x09calibrate");
+        assert_eq!(RpcDefinition::unpack(&packed), Ok(calibrate()));
+    }
+
+    #[test]
+    fn definitions_need_a_known_version() {
+        let mut packed = calibrate().pack().unwrap();
+        packed[0] = 2;
+        assert!(matches!(RpcDefinition::unpack(&packed), Err(RpcError::InvalidDefinition(_))));
+    }
+
+    #[test]
+    fn missing_params_take_their_defaults() {
+        let def = calibrate();
+        let packed = def.pack_params(&[Value::Double(5.0)]).unwrap();
+        let args = def.unpack_params(&packed).unwrap();
+        assert_eq!(args.get("samples"), Some(&Value::Double(5.0)));
+        assert_eq!(args.get("verbose"), Some(&Value::Bool(false)));
+        assert_eq!(args.len(), 2);
+    }
+
+    #[test]
+    fn params_are_checked_against_the_definition() {
+        let def = calibrate();
+        assert_eq!(def.pack_params(&[Value::Bool(true)]), Err(RpcError::TypeMismatch {
+            name: "samples".to_owned(), expected: EntryType::Double, actual: EntryType::Boolean,
+        }));
+        assert_eq!(def.pack_params(&[Value::Double(1.0), Value::Bool(true), Value::Bool(true)]),
+                   Err(RpcError::TooManyValues { expected: 2, actual: 3 }));
+    }
+
+    #[test]
+    fn unpacking_rejects_short_and_long_data() {
+        let def = calibrate();
+        let mut packed = def.pack_params(&[]).unwrap();
+        assert!(matches!(def.unpack_params(&packed[..4]), Err(RpcError::Malformed(_))));
+        packed.push(0);
+        assert_eq!(def.unpack_params(&packed), Err(RpcError::Malformed("1 unexpected bytes after the last value".to_owned())));
+    }
+
+    #[test]
+    fn results_need_every_value() {
+        let def = calibrate();
+        assert_eq!(def.pack_results(&[Value::Double(0.5)]), Err(RpcError::TooFewValues { expected: 2, actual: 1 }));
+
+        let results = vec![Value::Double(0.5), Value::StringArray(vec!["ok".to_owned()])];
+        let mut packed = def.pack_results(&results).unwrap();
+        assert_eq!(def.unpack_results(&packed), Ok(results));
+        packed.push(0);
+        assert!(matches!(def.unpack_results(&packed), Err(RpcError::Malformed(_))));
+    }
+
+    #[test]
+    fn errors_come_back_to_the_caller() {
+        let def = calibrate();
+        let err = def.pack_params(&[Value::Bool(true)]).unwrap_err();
+        let packed = RpcDefinition::pack_error(&err);
+        assert_eq!(def.unpack_results(&packed), Err(RpcError::Remote(err.to_string())));
+    }
+
+    #[test]
+    fn results_that_look_like_errors_are_still_results() {
+        let def = RpcDefinition::new("raw").result("data", EntryType::Raw);
+        let error = RpcDefinition::pack_error(&RpcError::Failed("no".to_owned()));
+        let results = vec![Value::Raw(error)];
+        assert_eq!(def.unpack_results(&def.pack_results(&results).unwrap()), Ok(results));
+    }
+}
@@ -0,0 +1,751 @@
+//! Reading and writing whole serde types as tables, enabled by the `serde` feature. Use
+//! `NetworkTable::serialize` and `NetworkTable::deserialize`.
+//!
+//! Struct fields and map keys become keys in the table, and nested structs and maps become
+//! subtables. Booleans, numbers and strings are stored as entries of those types, and sequences
+//! of them become arrays. Only byte strings (as with `serde_bytes`) become raw entries; a plain
+//! `Vec<u8>` is an array of numbers, though it can be read back from a raw entry too.
+//!
+//! `None` fields are left alone when writing, and missing entries read back as `None`. Unit enum
+//! variants are stored as strings, and variants with data as a subtable with a single key named
+//! after the variant.
+//!
+//! Errors say which key they are about, starting from the path of the table.
+
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, Deserializer, IntoDeserializer, Visitor};
+use serde::ser::{self, Serialize};
+use convert::FromValue;
+use entry::{EntryMask, EntryType, Value};
+use table::NetworkTable;
+
+/// Doubles can hold every integer up to this exactly.
+const MAX_EXACT_INTEGER: u64 = 1 << 53;
+
+/// An error from reading or writing a table, along with the key it happened at.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error {
+    path: String,
+    message: String,
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+impl Error {
+    fn new<T: fmt::Display>(message: T) -> Self {
+        Error { path: String::new(), message: message.to_string() }
+    }
+
+    /// Put `key` in front of the path of this error, for errors bubbling up out of a subtable.
+    fn within(mut self, key: &str) -> Self {
+        self.path = if self.path.is_empty() { key.to_owned() } else { format!("{}/{}", key, self.path) };
+        self
+    }
+
+    /// The full name of the key or table the error is about.
+    pub fn path(&self) -> &str { &self.path }
+
+    pub fn message(&self) -> &str { &self.message }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self { Error::new(msg) }
+}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self { Error::new(msg) }
+}
+
+impl From<::Error> for Error {
+    fn from(err: ::Error) -> Self { Error::new(err) }
+}
+
+/// Write `value` into `table`. See the module docs for how types are laid out.
+pub fn to_table<T: Serialize + ?Sized>(table: &mut NetworkTable, value: &T) -> Result<()> {
+    let path = table.path().to_owned();
+    match value.serialize(NodeSerializer).map_err(|err| err.within(&path))? {
+        Node::Table(entries) => write(table, entries).map_err(|err| err.within(&path)),
+        _ => Err(Error::new("only structs and maps can be written to a table").within(&path)),
+    }
+}
+
+/// Read a `T` out of `table`. See the module docs for how types are laid out.
+pub fn from_table<T: DeserializeOwned>(table: &mut NetworkTable) -> Result<T> {
+    let path = table.path().to_owned();
+    let mut root = BTreeMap::new();
+    for entry in table.get_filtered("/", EntryMask::all()) {
+        let name = match entry.name() {
+            Some(name) => name,
+            None => continue,
+        };
+        if let (Some(key), Some(value)) = (name.get(path.len() + 1..), entry.value()) {
+            insert(&mut root, key, value);
+        }
+    }
+    T::deserialize(TreeDeserializer(Tree::Table(root))).map_err(|err| err.within(&path))
+}
+
+/// What a value serializes to, before it is written to the table.
+enum Node {
+    Value(Value),
+    /// A sequence with no elements, which could be any array type. It becomes whatever kind of
+    /// array the entry already holds.
+    EmptyArray,
+    Table(Vec<(String, Node)>),
+    /// Nothing is written, for `None` and unit values.
+    Skip,
+}
+
+fn write(table: &mut NetworkTable, entries: Vec<(String, Node)>) -> Result<()> {
+    for (key, node) in entries {
+        match node {
+            Node::Value(value) => table.set(&key, value).map_err(|err| Error::from(err).within(&key))?,
+            Node::EmptyArray => {
+                let entry = table.get(&key);
+                let value = match entry.value() {
+                    Some(Value::BoolArray(_)) => Value::BoolArray(Vec::new()),
+                    Some(Value::StringArray(_)) => Value::StringArray(Vec::new()),
+                    _ => Value::DoubleArray(Vec::new()),
+                };
+                entry.set(value).map_err(|err| Error::from(err).within(&key))?;
+            }
+            Node::Table(entries) => write(&mut table.get_subtable(&key), entries).map_err(|err| err.within(&key))?,
+            Node::Skip => {}
+        }
+    }
+    Ok(())
+}
+
+/// Store an integer as a double, if it fits exactly. Takes the magnitude separately, since it
+/// can't be checked after the conversion.
+fn exact_integer<T: fmt::Display>(value: T, magnitude: u64, double: f64) -> Result<Node> {
+    if magnitude <= MAX_EXACT_INTEGER {
+        Ok(Node::Value(Value::Double(double)))
+    } else {
+        Err(Error::new(format!("{} can't be stored exactly as a double", value)))
+    }
+}
+
+struct NodeSerializer;
+
+impl ser::Serializer for NodeSerializer {
+    type Ok = Node;
+    type Error = Error;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = ser::Impossible<Node, Error>;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = VariantSerializer;
+
+    fn serialize_bool(self, v: bool) -> Result<Node> { Ok(Node::Value(Value::Bool(v))) }
+    fn serialize_i8(self, v: i8) -> Result<Node> { self.serialize_f64(v.into()) }
+    fn serialize_i16(self, v: i16) -> Result<Node> { self.serialize_f64(v.into()) }
+    fn serialize_i32(self, v: i32) -> Result<Node> { self.serialize_f64(v.into()) }
+    fn serialize_i64(self, v: i64) -> Result<Node> { exact_integer(v, v.unsigned_abs(), v as f64) }
+    fn serialize_u8(self, v: u8) -> Result<Node> { self.serialize_f64(v.into()) }
+    fn serialize_u16(self, v: u16) -> Result<Node> { self.serialize_f64(v.into()) }
+    fn serialize_u32(self, v: u32) -> Result<Node> { self.serialize_f64(v.into()) }
+    fn serialize_u64(self, v: u64) -> Result<Node> { exact_integer(v, v, v as f64) }
+    fn serialize_f32(self, v: f32) -> Result<Node> { self.serialize_f64(v.into()) }
+    fn serialize_f64(self, v: f64) -> Result<Node> { Ok(Node::Value(Value::Double(v))) }
+    fn serialize_char(self, v: char) -> Result<Node> { Ok(Node::Value(Value::String(v.to_string()))) }
+    fn serialize_str(self, v: &str) -> Result<Node> { Ok(Node::Value(Value::String(v.to_owned()))) }
+    fn serialize_bytes(self, v: &[u8]) -> Result<Node> { Ok(Node::Value(Value::Raw(v.to_vec()))) }
+    fn serialize_none(self) -> Result<Node> { Ok(Node::Skip) }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Node> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Node> { Ok(Node::Skip) }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Node> { Ok(Node::Skip) }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<Node> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<Node> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self, _name: &'static str, _index: u32,
+                                                        variant: &'static str, value: &T) -> Result<Node> {
+        let node = value.serialize(NodeSerializer).map_err(|err| err.within(variant))?;
+        Ok(Node::Table(vec![(variant.to_owned(), node)]))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer> {
+        Ok(SeqSerializer(Vec::with_capacity(len.unwrap_or(0))))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer> { self.serialize_seq(Some(len)) }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SeqSerializer> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(self, _name: &'static str, _index: u32, variant: &'static str,
+                               _len: usize) -> Result<Self::SerializeTupleVariant> {
+        Err(Error::new("tuple variants can't be written to a table").within(variant))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapSerializer> {
+        Ok(MapSerializer { entries: Vec::new(), key: None })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<MapSerializer> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(self, _name: &'static str, _index: u32, variant: &'static str,
+                                len: usize) -> Result<VariantSerializer> {
+        Ok(VariantSerializer { variant, map: self.serialize_map(Some(len))? })
+    }
+}
+
+struct SeqSerializer(Vec<Node>);
+
+impl SeqSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let index = self.0.len().to_string();
+        let node = value.serialize(NodeSerializer).map_err(|err| err.within(&index))?;
+        self.0.push(node);
+        Ok(())
+    }
+
+    fn into_array(self) -> Result<Node> {
+        let mut values = Vec::with_capacity(self.0.len());
+        for node in self.0 {
+            match node {
+                Node::Value(value) => values.push(value),
+                _ => return Err(Error::new("arrays can only hold booleans, numbers and strings")),
+            }
+        }
+
+        let ty = match values.first() {
+            Some(value) => value.entry_type(),
+            None => return Ok(Node::EmptyArray),
+        };
+        if values.iter().any(|value| value.entry_type() != ty) {
+            return Err(Error::new("arrays can't hold values of different types"));
+        }
+
+        Ok(Node::Value(match ty {
+            EntryType::Boolean => Value::BoolArray(values.into_iter().filter_map(|v| bool::from_value(v).ok()).collect()),
+            EntryType::Double => Value::DoubleArray(values.into_iter().filter_map(|v| f64::from_value(v).ok()).collect()),
+            EntryType::String => Value::StringArray(values.into_iter().filter_map(|v| String::from_value(v).ok()).collect()),
+            _ => return Err(Error::new("arrays can only hold booleans, numbers and strings")),
+        }))
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> { self.push(value) }
+    fn end(self) -> Result<Node> { self.into_array() }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> { self.push(value) }
+    fn end(self) -> Result<Node> { self.into_array() }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> { self.push(value) }
+    fn end(self) -> Result<Node> { self.into_array() }
+}
+
+struct MapSerializer {
+    entries: Vec<(String, Node)>,
+    key: Option<String>,
+}
+
+impl MapSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, key: String, value: &T) -> Result<()> {
+        let node = value.serialize(NodeSerializer).map_err(|err| err.within(&key))?;
+        self.entries.push((key, node));
+        Ok(())
+    }
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<()> {
+        // Numbers are allowed too, so that maps with integer keys work.
+        self.key = Some(match key.serialize(NodeSerializer)? {
+            Node::Value(Value::String(key)) => key,
+            Node::Value(Value::Double(key)) => key.to_string(),
+            _ => return Err(Error::new("map keys must be strings or numbers")),
+        });
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let key = self.key.take().ok_or_else(|| Error::new("map value serialized before its key"))?;
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Node> { Ok(Node::Table(self.entries)) }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()> {
+        self.push(key.to_owned(), value)
+    }
+
+    fn end(self) -> Result<Node> { Ok(Node::Table(self.entries)) }
+}
+
+struct VariantSerializer {
+    variant: &'static str,
+    map: MapSerializer,
+}
+
+impl ser::SerializeStructVariant for VariantSerializer {
+    type Ok = Node;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()> {
+        self.map.push(key.to_owned(), value).map_err(|err| err.within(self.variant))
+    }
+
+    fn end(self) -> Result<Node> {
+        Ok(Node::Table(vec![(self.variant.to_owned(), Node::Table(self.map.entries))]))
+    }
+}
+
+/// The entries of a table, arranged by subtable.
+enum Tree {
+    Value(Value),
+    Table(BTreeMap<String, Tree>),
+}
+
+/// Add the entry named `key` to `table`. If a name is used both for an entry and a subtable, the
+/// subtable wins.
+fn insert(table: &mut BTreeMap<String, Tree>, key: &str, value: Value) {
+    match key.find('/') {
+        Some(slash) => {
+            let subtable = table.entry(key[..slash].to_owned()).or_insert_with(|| Tree::Table(BTreeMap::new()));
+            if let Tree::Value(_) = *subtable {
+                *subtable = Tree::Table(BTreeMap::new());
+            }
+            if let Tree::Table(ref mut subtable) = *subtable {
+                insert(subtable, &key[slash + 1..], value);
+            }
+        }
+        None => {
+            table.entry(key.to_owned()).or_insert(Tree::Value(value));
+        }
+    }
+}
+
+struct TreeDeserializer(Tree);
+
+macro_rules! deserialize_number {
+    ($($method:ident: $type:ty => $visit:ident,)*) => {$(
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+            match self.0 {
+                // Let the conversion check the range, instead of failing on anything but a double.
+                Tree::Value(value @ Value::Double(_)) => visitor.$visit(<$type>::from_value(value)?),
+                tree => TreeDeserializer(tree).deserialize_any(visitor),
+            }
+        }
+    )*}
+}
+
+impl<'de> Deserializer<'de> for TreeDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.0 {
+            Tree::Table(table) => visitor.visit_map(MapDeserializer { iter: table.into_iter(), value: None }),
+            Tree::Value(Value::Bool(v)) => visitor.visit_bool(v),
+            Tree::Value(Value::Double(v)) => visitor.visit_f64(v),
+            Tree::Value(Value::String(v)) => visitor.visit_string(v),
+            Tree::Value(Value::Raw(v)) => visitor.visit_byte_buf(v),
+            Tree::Value(Value::BoolArray(v)) => visitor.visit_seq(SeqDeserializer::new(v.into_iter().map(Value::Bool))),
+            Tree::Value(Value::DoubleArray(v)) => visitor.visit_seq(SeqDeserializer::new(v.into_iter().map(Value::Double))),
+            Tree::Value(Value::StringArray(v)) => visitor.visit_seq(SeqDeserializer::new(v.into_iter().map(Value::String))),
+            Tree::Value(Value::Rpc(_)) => Err(Error::new("remote procedures can't be read")),
+        }
+    }
+
+    deserialize_number! {
+        deserialize_i8: i8 => visit_i8,
+        deserialize_i16: i16 => visit_i16,
+        deserialize_i32: i32 => visit_i32,
+        deserialize_i64: i64 => visit_i64,
+        deserialize_u8: u8 => visit_u8,
+        deserialize_u16: u16 => visit_u16,
+        deserialize_u32: u32 => visit_u32,
+        deserialize_u64: u64 => visit_u64,
+        deserialize_f32: f32 => visit_f32,
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.0 {
+            // So that a `Vec<u8>` can be read from a raw entry.
+            Tree::Value(Value::Raw(v)) => visitor.visit_seq(SeqDeserializer::new(v.into_iter().map(|b| Value::Double(b.into())))),
+            tree => TreeDeserializer(tree).deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        // Missing entries never get this far.
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> { visitor.visit_unit() }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self, _name: &'static str, _variants: &'static [&'static str],
+                                         visitor: V) -> Result<V::Value> {
+        match self.0 {
+            Tree::Value(Value::String(variant)) => visitor.visit_enum(variant.into_deserializer()),
+            Tree::Table(table) => {
+                let mut entries = table.into_iter();
+                match (entries.next(), entries.next()) {
+                    (Some((variant, tree)), None) => visitor.visit_enum(EnumDeserializer { variant, tree }),
+                    _ => Err(Error::new("expected a subtable with a single key")),
+                }
+            }
+            Tree::Value(_) => Err(Error::new("expected a string or a subtable with a single key")),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i128 u128 f64 char str string bytes byte_buf tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct SeqDeserializer<I> {
+    iter: I,
+    index: usize,
+}
+
+impl<I: Iterator<Item = Value>> SeqDeserializer<I> {
+    fn new(iter: I) -> Self { SeqDeserializer { iter, index: 0 } }
+}
+
+impl<'de, I: Iterator<Item = Value>> de::SeqAccess<'de> for SeqDeserializer<I> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        match self.iter.next() {
+            Some(value) => {
+                let index = self.index;
+                self.index += 1;
+                seed.deserialize(TreeDeserializer(Tree::Value(value)))
+                    .map(Some)
+                    .map_err(|err| err.within(&index.to_string()))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+struct MapDeserializer<I> {
+    iter: I,
+    value: Option<(String, Tree)>,
+}
+
+impl<'de, I: Iterator<Item = (String, Tree)>> de::MapAccess<'de> for MapDeserializer<I> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>> {
+        match self.iter.next() {
+            Some((key, tree)) => {
+                let ret = seed.deserialize(de::value::StrDeserializer::<Error>::new(&key)).map(Some);
+                self.value = Some((key, tree));
+                ret
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value> {
+        let (key, tree) = self.value.take().ok_or_else(|| Error::new("map value read before its key"))?;
+        seed.deserialize(TreeDeserializer(tree)).map_err(|err| err.within(&key))
+    }
+}
+
+struct EnumDeserializer {
+    variant: String,
+    tree: Tree,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumDeserializer {
+    type Error = Error;
+    type Variant = VariantDeserializer;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, VariantDeserializer)> {
+        let value = seed.deserialize(de::value::StrDeserializer::<Error>::new(&self.variant))?;
+        Ok((value, VariantDeserializer { variant: self.variant, tree: self.tree }))
+    }
+}
+
+struct VariantDeserializer {
+    variant: String,
+    tree: Tree,
+}
+
+impl<'de> de::VariantAccess<'de> for VariantDeserializer {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> { Ok(()) }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value> {
+        let variant = self.variant;
+        seed.deserialize(TreeDeserializer(self.tree)).map_err(|err| err.within(&variant))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        let variant = self.variant;
+        Deserializer::deserialize_seq(TreeDeserializer(self.tree), visitor).map_err(|err| err.within(&variant))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value> {
+        let variant = self.variant;
+        Deserializer::deserialize_map(TreeDeserializer(self.tree), visitor).map_err(|err| err.within(&variant))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use serde::{Deserialize, Serialize, Serializer};
+    use instance::Instance;
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Drive {
+        speed: f64,
+        enabled: bool,
+        name: String,
+        gears: Vec<u8>,
+        modes: Vec<String>,
+        trim: Option<f64>,
+        arm: Arm,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Arm {
+        angle: f32,
+        limits: [bool; 2],
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Command {
+        Stop,
+        Move(f64),
+        Turn { degrees: f64 },
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Commands {
+        first: Command,
+        second: Command,
+        third: Command,
+    }
+
+    /// Serializes as a byte string, like `serde_bytes` does.
+    struct Bytes(&'static [u8]);
+
+    impl Serialize for Bytes {
+        fn serialize<S: Serializer>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(self.0)
+        }
+    }
+
+    fn drive() -> Drive {
+        Drive {
+            speed: 1.5,
+            enabled: true,
+            name: "tank".to_owned(),
+            gears: vec![1, 2],
+            modes: vec!["slow".to_owned(), "fast".to_owned()],
+            trim: None,
+            arm: Arm { angle: 0.5, limits: [true, false] },
+        }
+    }
+
+    /// Every entry of `inst`, by name.
+    fn values(inst: &Instance) -> BTreeMap<String, Value> {
+        inst.memory_backend().unwrap().values()
+    }
+
+    #[test]
+    fn structs_become_tables() {
+        let inst = Instance::in_memory();
+        inst.get_table("/drive".to_owned()).serialize(&drive()).unwrap();
+
+        let expected: BTreeMap<String, Value> = vec![
+            ("/drive/speed", Value::Double(1.5)),
+            ("/drive/enabled", Value::Bool(true)),
+            ("/drive/name", Value::String("tank".to_owned())),
+            ("/drive/gears", Value::DoubleArray(vec![1.0, 2.0])),
+            ("/drive/modes", Value::StringArray(vec!["slow".to_owned(), "fast".to_owned()])),
+            ("/drive/arm/angle", Value::Double(0.5)),
+            ("/drive/arm/limits", Value::BoolArray(vec![true, false])),
+        ].into_iter().map(|(name, value)| (name.to_owned(), value)).collect();
+        // `trim` is `None`, so it isn't written.
+        assert_eq!(values(&inst), expected);
+    }
+
+    #[test]
+    fn tables_read_back() {
+        let inst = Instance::in_memory();
+        let mut table = inst.get_table("/drive".to_owned());
+        table.serialize(&drive()).unwrap();
+        assert_eq!(table.deserialize::<Drive>(), Ok(drive()));
+
+        table.set("trim", 0.25).unwrap();
+        assert_eq!(table.deserialize::<Drive>().unwrap().trim, Some(0.25));
+    }
+
+    #[test]
+    fn none_leaves_entries_alone() {
+        let inst = Instance::in_memory();
+        let mut table = inst.get_table("/drive".to_owned());
+        table.set("trim", 0.25).unwrap();
+        table.serialize(&drive()).unwrap();
+        assert_eq!(inst.get_entry("/drive/trim").value(), Some(Value::Double(0.25)));
+    }
+
+    #[test]
+    fn enums_are_strings_or_subtables() {
+        let inst = Instance::in_memory();
+        let mut table = inst.get_table("/cmd".to_owned());
+        let commands = Commands { first: Command::Stop, second: Command::Move(2.0), third: Command::Turn { degrees: 90.0 } };
+        table.serialize(&commands).unwrap();
+
+        assert_eq!(inst.get_entry("/cmd/first").value(), Some(Value::String("Stop".to_owned())));
+        assert_eq!(inst.get_entry("/cmd/second/Move").value(), Some(Value::Double(2.0)));
+        assert_eq!(inst.get_entry("/cmd/third/Turn/degrees").value(), Some(Value::Double(90.0)));
+        assert_eq!(table.deserialize::<Commands>(), Ok(commands));
+    }
+
+    #[test]
+    fn byte_strings_are_raw() {
+        let inst = Instance::in_memory();
+        let mut fields = BTreeMap::new();
+        fields.insert("blob", Bytes(b"\x00\x01"));
+        inst.get_table("/data".to_owned()).serialize(&fields).unwrap();
+        assert_eq!(inst.get_entry("/data/blob").value(), Some(Value::Raw(vec![0, 1])));
+
+        // A plain `Vec<u8>` can still be read from a raw entry.
+        let read: BTreeMap<String, Vec<u8>> = inst.get_table("/data".to_owned()).deserialize().unwrap();
+        assert_eq!(read["blob"], vec![0, 1]);
+    }
+
+    #[test]
+    fn maps_can_have_number_keys() {
+        let inst = Instance::in_memory();
+        let mut map = BTreeMap::new();
+        map.insert(1u32, true);
+        map.insert(20u32, false);
+        inst.get_table("/map".to_owned()).serialize(&map).unwrap();
+        assert_eq!(inst.get_entry("/map/1").value(), Some(Value::Bool(true)));
+        assert_eq!(inst.get_entry("/map/20").value(), Some(Value::Bool(false)));
+    }
+
+    #[test]
+    fn empty_arrays_keep_the_entry_type() {
+        let inst = Instance::in_memory();
+        let mut table = inst.get_table("/t".to_owned());
+        table.set("modes", vec!["a"]).unwrap();
+        let mut fields = BTreeMap::new();
+        fields.insert("modes", Vec::<String>::new());
+        fields.insert("other", Vec::<String>::new());
+        table.serialize(&fields).unwrap();
+        assert_eq!(inst.get_entry("/t/modes").value(), Some(Value::StringArray(Vec::new())));
+        // With nothing to go by, an empty array is a double array.
+        assert_eq!(inst.get_entry("/t/other").value(), Some(Value::DoubleArray(Vec::new())));
+    }
+
+    #[test]
+    fn errors_name_the_key() {
+        let inst = Instance::in_memory();
+        let mut table = inst.get_table("/drive".to_owned());
+
+        let mut wide = BTreeMap::new();
+        wide.insert("big", (1u64 << 53) + 1);
+        let err = table.serialize(&wide).unwrap_err();
+        assert_eq!(err.path(), "/drive/big");
+        assert_eq!(err.message(), "9007199254740993 can't be stored exactly as a double");
+
+        table.serialize(&drive()).unwrap();
+        let angle = inst.get_entry("/drive/arm/angle");
+        angle.delete();
+        angle.set("up").unwrap();
+        assert_eq!(table.deserialize::<Drive>().unwrap_err().path(), "/drive/arm/angle");
+
+        angle.delete();
+        angle.set(0.5).unwrap();
+        inst.get_entry("/drive/name").delete();
+        let err = table.deserialize::<Drive>().unwrap_err();
+        assert_eq!(err.to_string(), "/drive: missing field `name`");
+    }
+
+    #[test]
+    fn numbers_must_fit_the_field() {
+        #[derive(Debug, Deserialize)]
+        struct Count { count: u8 }
+
+        let inst = Instance::in_memory();
+        let mut table = inst.get_table("/c".to_owned());
+        table.set("count", 1.5).unwrap();
+        assert_eq!(table.deserialize::<Count>().unwrap_err().path(), "/c/count");
+        table.get("count").set(300.0).unwrap();
+        assert!(table.deserialize::<Count>().is_err());
+        table.get("count").set(200.0).unwrap();
+        assert_eq!(table.deserialize::<Count>().unwrap().count, 200);
+    }
+
+    #[test]
+    fn only_structs_and_maps_make_tables() {
+        let inst = Instance::in_memory();
+        assert!(inst.get_table("/x".to_owned()).serialize(&1.0).is_err());
+
+        let mut mixed = BTreeMap::new();
+        mixed.insert("tuple", (1.0, "a"));
+        let err = inst.get_table("/x".to_owned()).serialize(&mixed).unwrap_err();
+        assert_eq!(err.message(), "arrays can't hold values of different types");
+    }
+}
@@ -0,0 +1,326 @@
+//! Futures and streams for use from async code, enabled by the `async` feature.
+//!
+//! These are built on ordinary listener callbacks: the callback queues the notification and wakes
+//! whichever task is waiting on it. ntcore runs every callback on its one notifier thread, so
+//! having many streams open doesn't cost a thread each, and nothing here ever blocks. They work
+//! with any executor.
+//!
+//! Streams never end on their own. The listener behind a stream is removed when it is dropped.
+//!
+//! Each stream queues at most `DEFAULT_CAPACITY` items, or whatever `set_capacity` says, so a stream
+//! nobody polls can't take up memory without end. When the queue is full, the oldest item is
+//! dropped to make room; for entries, that's a value that has since been replaced anyway. `dropped`
+//! tells how many items were lost that way.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use futures_core::Stream;
+use backend::Backend;
+use connection::ConnectionEvent;
+use listener::{ConnectionListener, EntryListener, EntryNotification};
+
+/// How many items a stream queues before it starts dropping the oldest ones.
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+struct Queue<T> {
+    items: VecDeque<T>,
+    waker: Option<Waker>,
+    capacity: usize,
+    dropped: usize,
+}
+
+impl<T> Queue<T> {
+    fn truncate(&mut self) {
+        while self.items.len() > self.capacity {
+            self.items.pop_front();
+            self.dropped += 1;
+        }
+    }
+}
+
+/// The state shared between a listener callback and the stream it feeds.
+struct Shared<T>(Mutex<Queue<T>>);
+
+impl<T> Shared<T> {
+    fn new() -> Arc<Self> {
+        let queue = Queue { items: VecDeque::new(), waker: None, capacity: DEFAULT_CAPACITY, dropped: 0 };
+        Arc::new(Shared(Mutex::new(queue)))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Queue<T>> {
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn push(&self, item: T) {
+        let waker = {
+            let mut queue = self.lock();
+            queue.items.push_back(item);
+            queue.truncate();
+            queue.waker.take()
+        };
+        // Waking can run arbitrary executor code, so don't do it with the queue locked.
+        if let Some(waker) = waker { waker.wake(); }
+    }
+
+    fn poll_next(&self, cx: &mut Context) -> Poll<Option<T>> {
+        let mut queue = self.lock();
+        match queue.items.pop_front() {
+            Some(item) => Poll::Ready(Some(item)),
+            None => {
+                queue.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.lock().items.len()
+    }
+
+    fn set_capacity(&self, capacity: usize) {
+        let mut queue = self.lock();
+        // A capacity of 0 would drop everything before it could be polled.
+        queue.capacity = capacity.max(1);
+        queue.truncate();
+    }
+
+    fn dropped(&self) -> usize {
+        self.lock().dropped
+    }
+}
+
+/// A stream of changes to an entry or table. Created by `Entry::changes` and
+/// `NetworkTable::changes`.
+#[must_use = "streams do nothing unless polled"]
+pub struct EntryChanges {
+    shared: Arc<Shared<EntryNotification>>,
+    _listener: EntryListener,
+}
+
+impl EntryChanges {
+    /// `register` is handed the callback to register as a listener.
+    pub(crate) fn new<F>(register: F) -> Self
+        where F: FnOnce(Box<dyn Fn(EntryNotification) + Send>) -> EntryListener
+    {
+        let shared = Shared::new();
+        let callback_shared = shared.clone();
+        let listener = register(Box::new(move |notification| callback_shared.push(notification)));
+        EntryChanges { shared, _listener: listener }
+    }
+
+    /// Change how many notifications are queued before the oldest ones are dropped. If more are
+    /// already queued, the oldest are dropped right away. At least one is always kept.
+    pub fn set_capacity(&self, capacity: usize) { self.shared.set_capacity(capacity) }
+
+    /// How many notifications were dropped because the queue was full.
+    pub fn dropped(&self) -> usize { self.shared.dropped() }
+}
+
+impl Stream for EntryChanges {
+    type Item = EntryNotification;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<EntryNotification>> {
+        self.shared.poll_next(cx)
+    }
+}
+
+impl fmt::Debug for EntryChanges {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EntryChanges")
+            .field("listener", &self._listener)
+            .field("queued", &self.shared.len())
+            .field("dropped", &self.shared.dropped())
+            .finish()
+    }
+}
+
+/// A stream of remote nodes connecting and disconnecting. Created by
+/// `Instance::connection_events`.
+#[must_use = "streams do nothing unless polled"]
+pub struct ConnectionEvents {
+    shared: Arc<Shared<ConnectionEvent>>,
+    _listener: ConnectionListener,
+}
+
+impl ConnectionEvents {
+    pub(crate) fn new(backend: &Arc<dyn Backend>, immediate_notify: bool) -> Self {
+        let shared = Shared::new();
+        let callback_shared = shared.clone();
+        let listener = ConnectionListener::new(backend, immediate_notify, move |event| callback_shared.push(event));
+        ConnectionEvents { shared, _listener: listener }
+    }
+
+    /// Change how many events are queued before the oldest ones are dropped. If more are already
+    /// queued, the oldest are dropped right away. At least one is always kept.
+    pub fn set_capacity(&self, capacity: usize) { self.shared.set_capacity(capacity) }
+
+    /// How many events were dropped because the queue was full.
+    pub fn dropped(&self) -> usize { self.shared.dropped() }
+}
+
+impl Stream for ConnectionEvents {
+    type Item = ConnectionEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<ConnectionEvent>> {
+        self.shared.poll_next(cx)
+    }
+}
+
+impl fmt::Debug for ConnectionEvents {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ConnectionEvents")
+            .field("listener", &self._listener)
+            .field("queued", &self.shared.len())
+            .field("dropped", &self.shared.dropped())
+            .finish()
+    }
+}
+
+/// A future that completes once an instance is connected to another node. Created by
+/// `Instance::wait_connected`.
+#[must_use = "futures do nothing unless polled"]
+pub struct WaitConnected {
+    backend: Arc<dyn Backend>,
+    waker: Arc<Mutex<Option<Waker>>>,
+    _listener: ConnectionListener,
+}
+
+impl WaitConnected {
+    pub(crate) fn new(backend: &Arc<dyn Backend>) -> Self {
+        let waker: Arc<Mutex<Option<Waker>>> = Arc::new(Mutex::new(None));
+        let callback_waker = waker.clone();
+        let listener = ConnectionListener::new(backend, false, move |_| {
+            let waker = callback_waker.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).take();
+            if let Some(waker) = waker { waker.wake(); }
+        });
+        WaitConnected { backend: backend.clone(), waker, _listener: listener }
+    }
+}
+
+impl Future for WaitConnected {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if self.backend.is_connected() { return Poll::Ready(()); }
+
+        *self.waker.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(cx.waker().clone());
+        // The connection might have come up after we checked but before the waker was stored, in
+        // which case nobody would wake us.
+        if self.backend.is_connected() { Poll::Ready(()) } else { Poll::Pending }
+    }
+}
+
+impl fmt::Debug for WaitConnected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WaitConnected").field("listener", &self._listener).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::Wake;
+    use entry::Value;
+    use instance::Instance;
+    use listener::NotifyFlags;
+    use super::*;
+
+    /// Counts how often it was woken.
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) { self.0.fetch_add(1, Ordering::SeqCst); }
+    }
+
+    /// Poll `stream` once, with a waker that counts wakes.
+    fn poll<S: Stream + Unpin>(stream: &mut S, waker: &Arc<CountingWaker>) -> Poll<Option<S::Item>> {
+        let waker = Waker::from(waker.clone());
+        Pin::new(stream).poll_next(&mut Context::from_waker(&waker))
+    }
+
+    fn value(poll: Poll<Option<EntryNotification>>) -> Option<Value> {
+        match poll {
+            Poll::Ready(Some(notification)) => notification.value,
+            other => panic!("expected a notification, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn changes_start_with_the_current_value() {
+        let inst = Instance::in_memory();
+        let entry = inst.get_entry("/a");
+        entry.set(1.0).unwrap();
+        let waker = Arc::new(CountingWaker(AtomicUsize::new(0)));
+
+        let mut changes = entry.changes();
+        assert_eq!(value(poll(&mut changes, &waker)), Some(Value::Double(1.0)));
+        assert!(poll(&mut changes, &waker).is_pending());
+
+        entry.set(2.0).unwrap();
+        assert_eq!(waker.0.load(Ordering::SeqCst), 1);
+        assert_eq!(value(poll(&mut changes, &waker)), Some(Value::Double(2.0)));
+    }
+
+    #[test]
+    fn full_queues_drop_the_oldest_items() {
+        let inst = Instance::in_memory();
+        let entry = inst.get_entry("/a");
+        let mut changes = entry.changes_with(NotifyFlags::NEW | NotifyFlags::UPDATE | NotifyFlags::LOCAL);
+        changes.set_capacity(2);
+        for num in 0..5 { entry.set(num as f64).unwrap(); }
+
+        assert_eq!(changes.dropped(), 3);
+        let waker = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        assert_eq!(value(poll(&mut changes, &waker)), Some(Value::Double(3.0)));
+        assert_eq!(value(poll(&mut changes, &waker)), Some(Value::Double(4.0)));
+        assert!(poll(&mut changes, &waker).is_pending());
+    }
+
+    #[test]
+    fn shrinking_the_capacity_drops_right_away() {
+        let inst = Instance::in_memory();
+        let entry = inst.get_entry("/a");
+        let mut changes = entry.changes_with(NotifyFlags::NEW | NotifyFlags::UPDATE | NotifyFlags::LOCAL);
+        for num in 0..3 { entry.set(num as f64).unwrap(); }
+
+        // At least one item is always kept.
+        changes.set_capacity(0);
+        assert_eq!(changes.dropped(), 2);
+        let waker = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        assert_eq!(value(poll(&mut changes, &waker)), Some(Value::Double(2.0)));
+    }
+
+    #[test]
+    fn the_default_capacity_is_bounded() {
+        let inst = Instance::in_memory();
+        let entry = inst.get_entry("/a");
+        let changes = entry.changes_with(NotifyFlags::NEW | NotifyFlags::UPDATE | NotifyFlags::LOCAL);
+        for num in 0..DEFAULT_CAPACITY + 10 { entry.set(num as f64).unwrap(); }
+        assert_eq!(changes.dropped(), 10);
+    }
+
+    #[test]
+    fn dropping_a_stream_removes_its_listener() {
+        let inst = Instance::in_memory();
+        let changes = inst.get_entry("/a").changes();
+        assert_eq!(inst.memory_backend().unwrap().listener_count(), 1);
+        drop(changes);
+        assert_eq!(inst.memory_backend().unwrap().listener_count(), 0);
+    }
+
+    #[test]
+    fn in_memory_instances_never_connect() {
+        let inst = Instance::in_memory();
+        let waker = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let mut events = inst.connection_events(true);
+        assert!(poll(&mut events, &waker).is_pending());
+
+        let mut connected = inst.wait_connected();
+        let waker = Waker::from(waker);
+        assert!(Pin::new(&mut connected).poll(&mut Context::from_waker(&waker)).is_pending());
+    }
+}
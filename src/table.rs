@@ -1,7 +1,19 @@
 use entry::EntryMask;
 use std::collections::HashMap;
 use ::instance::Instance;
-use ::entry::{Value, Entry, EntryType};
+use ::entry::{Value, Entry, EntryFlags};
+use ::Result;
+use ::convert::{FromValue, IntoValue};
+use ::typed::TypedEntry;
+use ::listener::{EntryListener, EntryNotification, NotifyFlags};
+#[cfg(feature = "async")]
+use ::stream::EntryChanges;
+#[cfg(feature = "serde")]
+use serde::de::DeserializeOwned;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+#[cfg(feature = "serde")]
+use ::serialization;
 // use sys::{self};
 
 // const PATH_SEPERATEOR: char = '/';
@@ -19,6 +31,9 @@ impl<'c> NetworkTable<'c> {
         NetworkTable { inst, prefix, entry_cache: HashMap::new() }
     }
 
+    /// The full name of this table, which every key in it starts with.
+    pub fn path(&self) -> &str { &self.prefix }
+
     pub fn get_subtable(&self, key: &str) -> NetworkTable {
         NetworkTable {
             inst: self.inst,
@@ -28,22 +43,99 @@ impl<'c> NetworkTable<'c> {
     }
 
     // NOTE: it's not required to return a mut ref because all the methods on `Entry` use a shared ptr.
+    // Entries are cheap to clone, since they only hold a handle and a pointer to the backend.
     pub fn get(&mut self, name: &str) -> Entry {
         // destructure to avoid borrow checker issues; this allows us to use mut references to the
         // members of `self` at the same time as non-mut refs.
         let &mut NetworkTable { ref mut entry_cache, ref inst, ref prefix, .. } = self;
-        *entry_cache.entry(prefix.clone() + name)
+        entry_cache.entry(prefix.clone() + name)
             .or_insert_with(|| inst.get_entry(&(prefix.clone() + "/" + name)))
+            .clone()
+    }
+
+    /// Get an entry that only holds `T`s, like `table.get_typed::<f64>("speed")`.
+    pub fn get_typed<T: FromValue + IntoValue>(&mut self, name: &str) -> TypedEntry<T> {
+        TypedEntry::new(self.get(name))
     }
 
-    pub fn set<V: Into<Value>>(&mut self, name: &str, value: V) -> Result<(), EntryType> {
+    pub fn set<V: IntoValue>(&mut self, name: &str, value: V) -> Result<()> {
         self.get(name).set(value)
     }
 
-    pub fn put(&mut self, key: &str, val: Value) -> Result<(), EntryType> {
+    pub fn put(&mut self, key: &str, val: Value) -> Result<()> {
         self.get(key).set(val)
     }
 
+    /// Set the value of `key` only if it doesn't have one yet. See `Entry::set_default`.
+    pub fn set_default<V: IntoValue>(&mut self, key: &str, value: V) -> Result<bool> {
+        self.get(key).set_default(value)
+    }
+
+    set_default_value!(set_default_bool(key): bool);
+    set_default_value!(set_default_double(key): f64);
+    set_default_value!(set_default_string(key): String);
+    set_default_value!(set_default_bool_array(key): Vec<bool>);
+    set_default_value!(set_default_double_array(key): Vec<f64>);
+    set_default_value!(set_default_string_array(key): Vec<String>);
+    set_default_value!(set_default_raw(key): Vec<u8>);
+
+    pub fn flags(&mut self, key: &str) -> EntryFlags {
+        self.get(key).flags()
+    }
+
+    pub fn set_flags(&mut self, key: &str, flags: EntryFlags) {
+        self.get(key).set_flags(flags)
+    }
+
+    pub fn clear_flags(&mut self, key: &str, flags: EntryFlags) {
+        self.get(key).clear_flags(flags)
+    }
+
+    pub fn is_persistent(&mut self, key: &str) -> bool {
+        self.get(key).is_persistent()
+    }
+
+    pub fn set_persistent(&mut self, key: &str) {
+        self.get(key).set_persistent()
+    }
+
+    pub fn clear_persistent(&mut self, key: &str) {
+        self.get(key).clear_persistent()
+    }
+
+    /// Register a callback for changes to any entry in this table or its subtables.
+    pub fn add_listener<F>(&self, flags: NotifyFlags, callback: F) -> EntryListener
+        where F: Fn(EntryNotification) + Send + 'static
+    {
+        self.inst.add_entry_listener(&(self.prefix.clone() + "/"), flags, callback)
+    }
+
+    /// A stream of the values of every entry in this table or its subtables, starting with the
+    /// current ones, then every change to them, local or remote.
+    #[cfg(feature = "async")]
+    pub fn changes(&self) -> EntryChanges {
+        self.changes_with(NotifyFlags::IMMEDIATE | NotifyFlags::NEW | NotifyFlags::UPDATE | NotifyFlags::LOCAL)
+    }
+
+    /// A stream of the changes matching `flags` to any entry in this table or its subtables.
+    #[cfg(feature = "async")]
+    pub fn changes_with(&self, flags: NotifyFlags) -> EntryChanges {
+        EntryChanges::new(|callback| self.add_listener(flags, callback))
+    }
+
+    /// Write every field of `value` to this table, with nested structs going into subtables. See
+    /// `serialization` for how types are laid out.
+    #[cfg(feature = "serde")]
+    pub fn serialize<T: Serialize + ?Sized>(&mut self, value: &T) -> ::std::result::Result<(), serialization::Error> {
+        serialization::to_table(self, value)
+    }
+
+    /// Read a `T` back out of this table, the way `serialize` wrote it.
+    #[cfg(feature = "serde")]
+    pub fn deserialize<T: DeserializeOwned>(&mut self) -> ::std::result::Result<T, serialization::Error> {
+        serialization::from_table(self)
+    }
+
     pub fn get_filtered(&mut self, prefix: &str, types: EntryMask) -> Vec<Entry> {
         let entries = self.inst.get_entries_filtered(&(self.prefix.clone() + prefix), types);
 
@@ -51,8 +143,7 @@ impl<'c> NetworkTable<'c> {
         for entry in &entries {
             // Just don't cache entries that don't have UTF-8 names.
             if let Some(entry_name) = entry.name() {
-                // Entry is a copy type, so we can just move out of the reference
-                self.entry_cache.insert(entry_name, *entry);
+                self.entry_cache.insert(entry_name, entry.clone());
             }
         }
 
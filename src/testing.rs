@@ -0,0 +1,223 @@
+//! Helpers for integration tests that replicate entries between real instances over localhost.
+//!
+//! `Loopback` starts a server on a free port plus any number of clients connected to it, so tests
+//! can check what robot code publishes from the point of view of a dashboard. Instead of sleeping
+//! for a fixed time and hoping replication has happened, tests wait for the state they expect with
+//! `wait_for_value` or `Loopback::wait_for_sync`, which return as soon as it shows up.
+//!
+//! `Loopback` needs ntcore. The wait functions work with any backend.
+//!
+//! ```rs
+//! let net = Loopback::start(1, Duration::from_secs(5))?;
+//! net.server().get_entry("/speed").set(3.0)?;
+//! wait_for_value(&net.clients()[0].get_entry("/speed"), 3.0, Duration::from_secs(5))?;
+//! ```
+
+use std::collections::BTreeMap;
+#[cfg(feature = "ntcore-sys")]
+use std::env;
+#[cfg(feature = "ntcore-sys")]
+use std::fs;
+use std::net::{Ipv4Addr, TcpListener};
+#[cfg(feature = "ntcore-sys")]
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+use entry::{Entry, Value};
+use ::{Error, Instance, Result};
+
+/// How often the wait functions check again.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+/// ntcore won't send updates more often than this, in seconds.
+#[cfg(feature = "ntcore-sys")]
+const UPDATE_INTERVAL: f64 = 0.01;
+
+/// Find a port nothing is listening on, by letting the OS pick one.
+///
+/// The port is only free as of the call; something else could take it before it's used. That is
+/// unlikely enough in tests to not be worth the trouble.
+pub fn free_port() -> Result<u16> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    Ok(listener.local_addr()?.port())
+}
+
+/// Check `condition` until it is true or `timeout` runs out. Returns whether it became true.
+pub fn wait_until<F: FnMut() -> bool>(timeout: Duration, mut condition: F) -> bool {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if condition() { return true; }
+        if Instant::now() >= deadline { return false; }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Wait until `entry` holds `expected`.
+pub fn wait_for_value<V: Into<Value>>(entry: &Entry, expected: V, timeout: Duration) -> Result<()> {
+    let expected = Some(expected.into());
+    if wait_until(timeout, || entry.value() == expected) { return Ok(()); }
+
+    Err(Error::Timeout(format!("{:?} to be {:?}, it is {:?}",
+                               entry.name().unwrap_or_default(), expected, entry.value())))
+}
+
+/// Get the value of every entry on `instance`, by name.
+pub fn snapshot(instance: &Instance) -> BTreeMap<String, Value> {
+    instance.get_all_entries().into_iter()
+        .filter_map(|entry| Some((entry.name()?, entry.value()?)))
+        .collect()
+}
+
+/// A server and its clients, all on this machine. The instances are stopped when this is dropped.
+#[cfg(feature = "ntcore-sys")]
+#[derive(Debug)]
+pub struct Loopback {
+    server: Instance,
+    clients: Vec<Instance>,
+    port: u16,
+    persist_filename: PathBuf,
+}
+
+#[cfg(feature = "ntcore-sys")]
+impl Loopback {
+    /// Start a server on a free localhost port and `clients` clients, and wait until they are all
+    /// connected to each other.
+    pub fn start(clients: usize, timeout: Duration) -> Result<Loopback> {
+        let port = free_port()?;
+        // ntcore always persists somewhere, so keep it out of the working directory.
+        let persist_filename = env::temp_dir().join(format!("ntcore-loopback-{}.ini", port));
+
+        let server = Instance::try_start_server(persist_filename.to_string_lossy().into_owned(),
+                                                Ipv4Addr::LOCALHOST, port as u32)?;
+        server.set_network_identity("server");
+        server.set_update_interval(UPDATE_INTERVAL);
+
+        let clients = (0..clients).map(|index| -> Result<Instance> {
+            let client = Instance::try_start_client_multi(vec![("127.0.0.1", port as u32)])?;
+            client.set_network_identity(&format!("client-{}", index));
+            client.set_update_interval(UPDATE_INTERVAL);
+            Ok(client)
+        }).collect::<Result<Vec<_>>>()?;
+
+        let net = Loopback { server, clients, port, persist_filename };
+        net.wait_for_connections(timeout)?;
+        Ok(net)
+    }
+
+    pub fn server(&self) -> &Instance { &self.server }
+
+    pub fn clients(&self) -> &[Instance] { &self.clients }
+
+    pub fn port(&self) -> u16 { self.port }
+
+    /// The server followed by every client.
+    pub fn instances(&self) -> Vec<&Instance> {
+        Some(&self.server).into_iter().chain(&self.clients).collect()
+    }
+
+    /// Wait until every client is connected and the server sees all of them.
+    pub fn wait_for_connections(&self, timeout: Duration) -> Result<()> {
+        let connected = wait_until(timeout, || {
+            self.clients.iter().all(Instance::is_connected)
+                && self.server.get_connections().len() == self.clients.len()
+        });
+        if connected { return Ok(()); }
+
+        let count = self.clients.iter().filter(|client| client.is_connected()).count();
+        Err(Error::Timeout(format!("{} clients to connect, {} did", self.clients.len(), count)))
+    }
+
+    /// Wait until every instance holds the same entries with the same values.
+    pub fn wait_for_sync(&self, timeout: Duration) -> Result<()> {
+        for instance in self.instances() { instance.flush(); }
+
+        let synced = wait_until(timeout, || {
+            let server = snapshot(&self.server);
+            self.clients.iter().all(|client| snapshot(client) == server)
+        });
+        if synced { Ok(()) } else { Err(Error::Timeout("every instance to have the same entries".to_owned())) }
+    }
+}
+
+#[cfg(feature = "ntcore-sys")]
+impl Drop for Loopback {
+    fn drop(&mut self) {
+        // Stop the clients first so the server doesn't have to notice them going away. The server
+        // is stopped explicitly too, since ntcore writes the persistent file when it stops, and
+        // that has to happen before the file is removed.
+        for client in self.clients.drain(..) {
+            client.backend().stop_client();
+        }
+        self.server.backend().stop_server();
+        for suffix in &["", ".bak", ".tmp"] {
+            let mut path = self.persist_filename.clone().into_os_string();
+            path.push(suffix);
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn free_ports_can_be_bound() {
+        let port = free_port().unwrap();
+        assert_ne!(port, 0);
+        TcpListener::bind((Ipv4Addr::LOCALHOST, port)).unwrap();
+    }
+
+    #[test]
+    fn waiting_gives_up_after_the_timeout() {
+        let start = Instant::now();
+        assert!(!wait_until(Duration::from_millis(20), || false));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        let mut checks = 0;
+        assert!(wait_until(TIMEOUT, || { checks += 1; checks == 3 }));
+    }
+
+    #[test]
+    fn waiting_for_a_value_reports_what_was_there() {
+        let inst = Instance::in_memory();
+        let entry = inst.get_entry("/speed");
+        entry.set(1.0).unwrap();
+        wait_for_value(&entry, 1.0, TIMEOUT).unwrap();
+        assert_eq!(wait_for_value(&entry, 2.0, Duration::from_millis(10)),
+                   Err(Error::Timeout("\"/speed\" to be Some(Double(2.0)), it is Some(Double(1.0))".to_owned())));
+    }
+
+    #[test]
+    fn snapshots_skip_entries_without_values() {
+        let inst = Instance::in_memory();
+        inst.get_entry("/a").set(1.0).unwrap();
+        inst.get_entry("/b");
+        assert_eq!(snapshot(&inst).into_iter().collect::<Vec<_>>(), vec![("/a".to_owned(), Value::Double(1.0))]);
+    }
+
+    #[cfg(feature = "ntcore-sys")]
+    #[test]
+    fn loopback_replicates_to_every_client() {
+        let net = Loopback::start(2, TIMEOUT).unwrap();
+        assert_eq!(net.instances().len(), 3);
+        net.server().get_entry("/speed").set(3.0).unwrap();
+        net.clients()[0].get_entry("/ready").set(true).unwrap();
+
+        net.wait_for_sync(TIMEOUT).unwrap();
+        wait_for_value(&net.clients()[1].get_entry("/speed"), 3.0, TIMEOUT).unwrap();
+        wait_for_value(&net.clients()[1].get_entry("/ready"), true, TIMEOUT).unwrap();
+    }
+
+    #[cfg(feature = "ntcore-sys")]
+    #[test]
+    fn dropping_a_loopback_removes_its_files() {
+        let net = Loopback::start(1, TIMEOUT).unwrap();
+        let persist_filename = net.persist_filename.clone();
+        net.server().get_entry("/saved").set(1.0).unwrap();
+        net.server().get_entry("/saved").set_persistent();
+        drop(net);
+        assert!(!persist_filename.exists());
+    }
+}
@@ -0,0 +1,134 @@
+//! Entries that only hold one type of value.
+//!
+//! A `TypedEntry<T>` reads and writes plain `T`s instead of `Value`s. `T` can be any type that
+//! converts both ways, see `convert`. Finding a value of some other type in the entry is an error,
+//! rather than looking the same as a missing value.
+
+use std::fmt;
+use std::marker::PhantomData;
+use convert::{FromValue, IntoValue};
+use entry::Entry;
+use ::Result;
+
+/// An entry that holds values of type `T`. Get one with `NetworkTable::get_typed` or
+/// `Entry::typed`.
+pub struct TypedEntry<T> {
+    entry: Entry,
+    _type: PhantomData<fn() -> T>,
+}
+
+impl<T: FromValue + IntoValue> TypedEntry<T> {
+    pub fn new(entry: Entry) -> Self {
+        TypedEntry { entry, _type: PhantomData }
+    }
+
+    /// The untyped entry underneath.
+    pub fn entry(&self) -> &Entry { &self.entry }
+
+    pub fn into_entry(self) -> Entry { self.entry }
+
+    /// Get the value of this entry. See `Entry::value_as`.
+    pub fn get(&self) -> Result<Option<T>> {
+        self.entry.value_as()
+    }
+
+    /// Get the value of this entry, or `default` if it doesn't exist. Still fails if the entry
+    /// holds something that can't be converted to a `T`.
+    pub fn get_or(&self, default: T) -> Result<T> {
+        self.get().map(|value| value.unwrap_or(default))
+    }
+
+    pub fn set(&self, value: T) -> Result<()> {
+        self.entry.set(value)
+    }
+
+    /// See `Entry::set_default`.
+    pub fn set_default(&self, value: T) -> Result<bool> {
+        self.entry.set_default(value)
+    }
+}
+
+impl<T> Clone for TypedEntry<T> {
+    fn clone(&self) -> Self {
+        TypedEntry { entry: self.entry.clone(), _type: PhantomData }
+    }
+}
+
+impl<T> PartialEq for TypedEntry<T> {
+    fn eq(&self, other: &Self) -> bool { self.entry == other.entry }
+}
+
+impl<T> Eq for TypedEntry<T> {}
+
+impl<T> fmt::Debug for TypedEntry<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TypedEntry")
+            .field("entry", &self.entry)
+            .field("type", &::std::any::type_name::<T>())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use entry::{EntryType, Value};
+    use instance::Instance;
+    use ::Error;
+
+    #[test]
+    fn values_go_both_ways() {
+        let inst = Instance::in_memory();
+        let speed = inst.get_entry("/speed").typed::<f64>();
+        assert_eq!(speed.get(), Ok(None));
+        assert_eq!(speed.get_or(1.0), Ok(1.0));
+
+        speed.set(3.5).unwrap();
+        assert_eq!(speed.get(), Ok(Some(3.5)));
+        assert_eq!(speed.get_or(1.0), Ok(3.5));
+        assert_eq!(speed.entry().value(), Some(Value::Double(3.5)));
+    }
+
+    #[test]
+    fn other_types_are_errors() {
+        let inst = Instance::in_memory();
+        inst.get_entry("/name").set("left").unwrap();
+        let name = inst.get_entry("/name").typed::<bool>();
+        assert_eq!(name.get(), Err(Error::TypeMismatch { expected: EntryType::String, actual: EntryType::Boolean }));
+        assert!(name.get_or(false).is_err());
+        assert!(name.set(true).is_err());
+        assert_eq!(inst.get_entry("/name").value(), Some(Value::String("left".to_owned())));
+    }
+
+    #[test]
+    fn integers_must_fit() {
+        let inst = Instance::in_memory();
+        let count = inst.get_entry("/count").typed::<u8>();
+        count.set(200).unwrap();
+        assert_eq!(count.get(), Ok(Some(200)));
+
+        count.entry().set(1.5).unwrap();
+        assert_eq!(count.get(), Err(Error::InvalidConversion { value: Value::Double(1.5), target: "u8" }));
+        count.entry().set(256.0).unwrap();
+        assert_eq!(count.get(), Err(Error::InvalidConversion { value: Value::Double(256.0), target: "u8" }));
+    }
+
+    #[test]
+    fn defaults_only_apply_to_missing_entries() {
+        let inst = Instance::in_memory();
+        let mode = inst.get_table("/auto".to_owned()).get_typed::<String>("mode");
+        assert_eq!(mode.set_default("drive".to_owned()), Ok(true));
+        assert_eq!(mode.set_default("shoot".to_owned()), Ok(false));
+        assert_eq!(mode.get(), Ok(Some("drive".to_owned())));
+        assert_eq!(mode.entry().name(), Some("/auto/mode".to_owned()));
+    }
+
+    #[test]
+    fn typed_entries_compare_by_entry() {
+        let inst = Instance::in_memory();
+        let a = inst.get_entry("/a").typed::<f64>();
+        assert_eq!(a.clone(), a);
+        assert_ne!(a, inst.get_entry("/b").typed::<f64>());
+        assert_eq!(a.clone().into_entry(), inst.get_entry("/a"));
+        assert!(format!("{:?}", a).contains("f64"));
+    }
+}
@@ -0,0 +1,208 @@
+//! Encoding of values in the NetworkTables 3 wire format. Multi-byte integers and doubles are big
+//! endian, and strings and raw data are prefixed with their length as an unsigned LEB128.
+
+use std::fmt;
+use entry::{EntryType, Value};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub(crate) enum WireError {
+    /// The buffer ended in the middle of an item.
+    UnexpectedEof,
+    UnknownType(u8),
+    InvalidUtf8,
+}
+
+impl fmt::Display for WireError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WireError::UnexpectedEof => write!(f, "unexpected end of data"),
+            WireError::UnknownType(id) => write!(f, "unknown type id 0x{:02x}", id),
+            WireError::InvalidUtf8 => write!(f, "string is not valid UTF-8"),
+        }
+    }
+}
+
+pub(crate) fn type_id(ty: EntryType) -> Option<u8> {
+    Some(match ty {
+        EntryType::Boolean => 0x00,
+        EntryType::Double => 0x01,
+        EntryType::String => 0x02,
+        EntryType::Raw => 0x03,
+        EntryType::BooleanArray => 0x10,
+        EntryType::DoubleArray => 0x11,
+        EntryType::StringArray => 0x12,
+        EntryType::Rpc => 0x20,
+        EntryType::Unassigned => return None,
+    })
+}
+
+pub(crate) fn type_from_id(id: u8) -> Result<EntryType, WireError> {
+    Ok(match id {
+        0x00 => EntryType::Boolean,
+        0x01 => EntryType::Double,
+        0x02 => EntryType::String,
+        0x03 => EntryType::Raw,
+        0x10 => EntryType::BooleanArray,
+        0x11 => EntryType::DoubleArray,
+        0x12 => EntryType::StringArray,
+        0x20 => EntryType::Rpc,
+        id => return Err(WireError::UnknownType(id)),
+    })
+}
+
+pub(crate) fn write_uleb128(out: &mut Vec<u8>, mut val: u64) {
+    loop {
+        let byte = (val & 0x7f) as u8;
+        val >>= 7;
+        if val == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+pub(crate) fn write_u16(out: &mut Vec<u8>, val: u16) {
+    out.push((val >> 8) as u8);
+    out.push(val as u8);
+}
+
+pub(crate) fn write_u32(out: &mut Vec<u8>, val: u32) {
+    write_u16(out, (val >> 16) as u16);
+    write_u16(out, val as u16);
+}
+
+pub(crate) fn write_f64(out: &mut Vec<u8>, val: f64) {
+    let bits = val.to_bits();
+    write_u32(out, (bits >> 32) as u32);
+    write_u32(out, bits as u32);
+}
+
+pub(crate) fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_uleb128(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+pub(crate) fn write_string(out: &mut Vec<u8>, string: &str) {
+    write_bytes(out, string.as_bytes())
+}
+
+/// Write the data of a value without its type. Arrays are limited to 255 elements by the protocol,
+/// so anything past that is dropped, like ntcore does.
+pub(crate) fn write_value(out: &mut Vec<u8>, value: &Value) {
+    fn array_len<T>(arr: &[T]) -> usize { ::std::cmp::min(arr.len(), 255) }
+
+    match *value {
+        Value::Bool(val) => out.push(val as u8),
+        Value::Double(val) => write_f64(out, val),
+        Value::String(ref val) => write_string(out, val),
+        Value::Raw(ref val) | Value::Rpc(ref val) => write_bytes(out, val),
+        Value::BoolArray(ref arr) => {
+            let len = array_len(arr);
+            out.push(len as u8);
+            out.extend(arr[..len].iter().map(|&val| val as u8));
+        }
+        Value::DoubleArray(ref arr) => {
+            let len = array_len(arr);
+            out.push(len as u8);
+            for &val in &arr[..len] { write_f64(out, val); }
+        }
+        Value::StringArray(ref arr) => {
+            let len = array_len(arr);
+            out.push(len as u8);
+            for val in &arr[..len] { write_string(out, val); }
+        }
+    }
+}
+
+/// Cursor over a buffer of wire data.
+#[derive(Clone, Debug)]
+pub(crate) struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self { Reader { buf, pos: 0 } }
+
+    /// Number of bytes consumed so far.
+    pub fn position(&self) -> usize { self.pos }
+
+    /// Number of bytes left to read.
+    pub fn remaining(&self) -> usize { self.buf.len() - self.pos }
+
+    pub fn read_slice(&mut self, len: usize) -> Result<&'a [u8], WireError> {
+        if self.buf.len() - self.pos < len { return Err(WireError::UnexpectedEof); }
+        let slice = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(slice)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, WireError> {
+        self.read_slice(1).map(|bytes| bytes[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, WireError> {
+        self.read_slice(2).map(|bytes| (bytes[0] as u16) << 8 | bytes[1] as u16)
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, WireError> {
+        Ok((self.read_u16()? as u32) << 16 | self.read_u16()? as u32)
+    }
+
+    pub fn read_f64(&mut self) -> Result<f64, WireError> {
+        let bits = (self.read_u32()? as u64) << 32 | self.read_u32()? as u64;
+        Ok(f64::from_bits(bits))
+    }
+
+    pub fn read_uleb128(&mut self) -> Result<u64, WireError> {
+        let mut result = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.read_u8()?;
+            if shift < 64 { result |= ((byte & 0x7f) as u64) << shift; }
+            shift += 7;
+            if byte & 0x80 == 0 { return Ok(result); }
+        }
+    }
+
+    pub fn read_bytes(&mut self) -> Result<&'a [u8], WireError> {
+        let len = self.read_uleb128()?;
+        if len > (self.buf.len() - self.pos) as u64 { return Err(WireError::UnexpectedEof); }
+        self.read_slice(len as usize)
+    }
+
+    pub fn read_string(&mut self) -> Result<String, WireError> {
+        let bytes = self.read_bytes()?;
+        ::std::str::from_utf8(bytes).map(|s| s.to_owned()).map_err(|_| WireError::InvalidUtf8)
+    }
+
+    pub fn read_type(&mut self) -> Result<EntryType, WireError> {
+        self.read_u8().and_then(type_from_id)
+    }
+
+    /// Read the data of a value whose type is already known.
+    pub fn read_value(&mut self, ty: EntryType) -> Result<Value, WireError> {
+        Ok(match ty {
+            EntryType::Boolean => Value::Bool(self.read_u8()? != 0),
+            EntryType::Double => Value::Double(self.read_f64()?),
+            EntryType::String => Value::String(self.read_string()?),
+            EntryType::Raw => Value::Raw(self.read_bytes()?.to_owned()),
+            EntryType::Rpc => Value::Rpc(self.read_bytes()?.to_owned()),
+            EntryType::BooleanArray => {
+                let len = self.read_u8()? as usize;
+                Value::BoolArray(self.read_slice(len)?.iter().map(|&val| val != 0).collect())
+            }
+            EntryType::DoubleArray => {
+                let len = self.read_u8()?;
+                Value::DoubleArray((0..len).map(|_| self.read_f64()).collect::<Result<_, _>>()?)
+            }
+            EntryType::StringArray => {
+                let len = self.read_u8()?;
+                Value::StringArray((0..len).map(|_| self.read_string()).collect::<Result<_, _>>()?)
+            }
+            // There is no type id for unassigned, so `read_type` never returns it.
+            EntryType::Unassigned => return Err(WireError::UnknownType(0xff)),
+        })
+    }
+}
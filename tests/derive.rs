@@ -0,0 +1,93 @@
+//! `#[derive(NtTable)]`, run against the in-memory backend. The cases under `tests/ui` have to be
+//! rejected at compile time.
+
+#![cfg(feature = "derive")]
+
+#[macro_use]
+extern crate ntcore;
+extern crate trybuild;
+
+use ntcore::bind::NtTable;
+use ntcore::entry::Value;
+use ntcore::{Error, Instance};
+
+#[derive(Clone, Debug, Default, PartialEq, NtTable)]
+struct Arm {
+    angle: f64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, NtTable)]
+struct Shooter {
+    #[nt(persistent)]
+    target_rpm: f64,
+    #[nt(rename = "atSpeed", read_only)]
+    at_speed: bool,
+    name: String,
+    #[nt(subtable)]
+    arm: Arm,
+}
+
+fn shooter() -> Shooter {
+    Shooter { target_rpm: 3000.0, at_speed: true, name: "left".to_owned(), arm: Arm { angle: 0.5 } }
+}
+
+#[test]
+fn fields_are_published_under_their_keys() {
+    let inst = Instance::in_memory();
+    shooter().publish(&mut inst.get_table("/Shooter".to_owned())).unwrap();
+
+    assert_eq!(inst.get_entry("/Shooter/target_rpm").value(), Some(Value::Double(3000.0)));
+    assert_eq!(inst.get_entry("/Shooter/atSpeed").value(), Some(Value::Bool(true)));
+    assert_eq!(inst.get_entry("/Shooter/name").value(), Some(Value::String("left".to_owned())));
+    assert_eq!(inst.get_entry("/Shooter/arm/angle").value(), Some(Value::Double(0.5)));
+    assert!(!inst.get_entry("/Shooter/at_speed").exists());
+}
+
+#[test]
+fn persistent_fields_are_marked() {
+    let inst = Instance::in_memory();
+    shooter().publish(&mut inst.get_table("/Shooter".to_owned())).unwrap();
+    assert!(inst.get_entry("/Shooter/target_rpm").flags().is_persistent());
+    assert!(!inst.get_entry("/Shooter/name").flags().is_persistent());
+}
+
+#[test]
+fn read_only_fields_load_as_default() {
+    let inst = Instance::in_memory();
+    let mut table = inst.get_table("/Shooter".to_owned());
+    shooter().publish(&mut table).unwrap();
+    assert_eq!(Shooter::load(&mut table), Ok(Shooter { at_speed: false, ..shooter() }));
+}
+
+#[test]
+fn missing_entries_fail_to_load() {
+    let inst = Instance::in_memory();
+    let mut table = inst.get_table("/Shooter".to_owned());
+    shooter().publish(&mut table).unwrap();
+    inst.get_entry("/Shooter/arm/angle").delete();
+    assert_eq!(Shooter::load(&mut table), Err(Error::MissingEntry("/Shooter/arm/angle".to_owned())));
+}
+
+#[test]
+fn defaults_leave_existing_entries_alone() {
+    let inst = Instance::in_memory();
+    let mut table = inst.get_table("/Shooter".to_owned());
+    table.set("name", "right").unwrap();
+    shooter().set_defaults(&mut table).unwrap();
+
+    assert_eq!(inst.get_entry("/Shooter/name").value(), Some(Value::String("right".to_owned())));
+    assert_eq!(inst.get_entry("/Shooter/arm/angle").value(), Some(Value::Double(0.5)));
+}
+
+#[test]
+fn wrong_types_fail_to_load() {
+    let inst = Instance::in_memory();
+    let mut table = inst.get_table("/Shooter".to_owned());
+    table.set("target_rpm", "fast").unwrap();
+    assert!(Shooter::load(&mut table).is_err());
+}
+
+#[test]
+fn invalid_derives_are_rejected() {
+    trybuild::TestCases::new().compile_fail("tests/ui/*.rs");
+}
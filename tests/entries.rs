@@ -0,0 +1,280 @@
+//! The `Entry` and `NetworkTable` API, run against the in-memory backend.
+
+extern crate ntcore;
+
+use std::sync::{Arc, Barrier, Mutex};
+use std::thread;
+use std::time::Duration;
+use ntcore::entry::{BorrowedValue, EntryFlags, EntryMask, EntryType, Value, ValueRef};
+use ntcore::listener::NotifyFlags;
+use ntcore::{Error, Instance};
+
+#[test]
+fn set_default_only_applies_to_missing_entries() {
+    let inst = Instance::in_memory();
+    let entry = inst.get_entry("/speed");
+    assert_eq!(entry.set_default(1.0), Ok(true));
+    assert_eq!(entry.set_default(2.0), Ok(false));
+    assert_eq!(entry.value(), Some(Value::Double(1.0)));
+
+    entry.delete();
+    assert_eq!(entry.set_default(3.0), Ok(true));
+    assert_eq!(entry.value(), Some(Value::Double(3.0)));
+}
+
+#[test]
+fn set_default_reports_type_mismatches() {
+    let inst = Instance::in_memory();
+    let mut table = inst.get_table("/Shooter".to_owned());
+    table.set("mode", "auto").unwrap();
+    assert_eq!(table.set_default_double("mode", 1.0),
+               Err(Error::TypeMismatch { expected: EntryType::String, actual: EntryType::Double }));
+    assert_eq!(table.get("mode").value(), Some(Value::String("auto".to_owned())));
+}
+
+#[test]
+fn only_one_racing_set_default_wins() {
+    let inst = Instance::in_memory();
+    let barrier = Arc::new(Barrier::new(8));
+    let threads = (0..8).map(|i| {
+        let entry = inst.get_entry("/race");
+        let barrier = barrier.clone();
+        thread::spawn(move || {
+            barrier.wait();
+            entry.set_default(i as f64).unwrap()
+        })
+    }).collect::<Vec<_>>();
+
+    let applied = threads.into_iter().map(|thread| thread.join().unwrap()).filter(|&applied| applied).count();
+    assert_eq!(applied, 1);
+}
+
+#[test]
+fn setting_none_deletes_the_entry() {
+    let inst = Instance::in_memory();
+    let mut table = inst.get_table("/Vision".to_owned());
+    table.set("target", Some(3.0)).unwrap();
+    assert_eq!(table.get("target").value_as::<Option<f64>>(), Ok(Some(Some(3.0))));
+
+    table.set("target", None::<f64>).unwrap();
+    assert_eq!(table.get("target").value(), None);
+    assert_eq!(table.set_default("target", None::<f64>), Ok(false));
+    assert_eq!(table.get("target").value(), None);
+}
+
+#[test]
+fn persistence_flags_can_be_set_and_cleared() {
+    let inst = Instance::in_memory();
+    let mut table = inst.get_table("/Tuning".to_owned());
+    table.set("kP", 0.5).unwrap();
+    assert!(!table.is_persistent("kP"));
+
+    table.set_persistent("kP");
+    assert!(table.is_persistent("kP"));
+    assert_eq!(table.flags("kP"), EntryFlags::PERSISTENT);
+
+    table.clear_persistent("kP");
+    assert_eq!(table.flags("kP"), EntryFlags::NONE);
+}
+
+#[test]
+fn clear_flags_leaves_other_flags_alone() {
+    let inst = Instance::in_memory();
+    let entry = inst.get_entry("/kI");
+    entry.set(0.0).unwrap();
+    // Every bit but the persistent one stands in for flags this crate doesn't know about.
+    let others = !EntryFlags::PERSISTENT;
+    entry.set_flags(EntryFlags::PERSISTENT | others);
+    assert!(entry.is_persistent());
+
+    entry.clear_flags(EntryFlags::PERSISTENT);
+    assert!(!entry.is_persistent());
+    assert_eq!(entry.flags(), others);
+}
+
+#[test]
+fn missing_entries_have_no_flags() {
+    let inst = Instance::in_memory();
+    let entry = inst.get_entry("/missing");
+    entry.set_persistent();
+    assert_eq!(entry.flags(), EntryFlags::NONE);
+}
+
+#[test]
+fn value_refs_read_every_type() {
+    let inst = Instance::in_memory();
+    let entry = |name: &str, value: Value| {
+        let entry = inst.get_entry(name);
+        entry.set(value).unwrap();
+        entry
+    };
+
+    let flag = entry("/flag", Value::Bool(true));
+    assert_eq!(flag.value_ref().unwrap().as_bool(), Some(true));
+    let speed = entry("/speed", Value::Double(1.5));
+    assert_eq!(speed.value_ref().unwrap().as_f64(), Some(1.5));
+    let name = entry("/name", Value::String("left".to_owned()));
+    assert_eq!(name.value_ref().unwrap().as_str().as_ref().map(|name| &name[..]), Some("left"));
+    let raw = entry("/raw", Value::Raw(vec![1, 2]));
+    assert_eq!(raw.value_ref().unwrap().as_bytes(), Some(&[1u8, 2][..]));
+    let speeds = entry("/speeds", Value::DoubleArray(vec![1.0, 2.0]));
+    assert_eq!(speeds.value_ref().unwrap().as_f64_slice(), Some(&[1.0, 2.0][..]));
+    let flags = entry("/flags", Value::BoolArray(vec![true, false]));
+    assert_eq!(flags.value_ref().unwrap().bools().unwrap().collect::<Vec<_>>(), vec![true, false]);
+    let names = entry("/names", Value::StringArray(vec!["a".to_owned(), "b".to_owned()]));
+    assert_eq!(names.value_ref().unwrap().strs().unwrap().collect::<Vec<_>>(), vec!["a", "b"]);
+}
+
+#[test]
+fn value_refs_of_other_types_are_none() {
+    let inst = Instance::in_memory();
+    let entry = inst.get_entry("/speed");
+    assert!(entry.value_ref().is_none());
+
+    entry.set(1.5).unwrap();
+    let value = entry.value_ref().unwrap();
+    assert_eq!(value.entry_type(), EntryType::Double);
+    assert_eq!(value.as_bool(), None);
+    assert_eq!(value.as_str(), None);
+    assert_eq!(value.as_bytes(), None);
+    assert_eq!(value.as_f64_slice(), None);
+    assert!(value.bools().is_none());
+    assert!(value.strs().is_none());
+}
+
+#[test]
+fn value_refs_compare_to_values() {
+    let inst = Instance::in_memory();
+    let entry = inst.get_entry("/speeds");
+    entry.set(vec![1.0, 2.0]).unwrap();
+
+    let value = entry.value_ref().unwrap();
+    assert_eq!(value, Value::DoubleArray(vec![1.0, 2.0]));
+    assert_eq!(value.to_owned(), Value::DoubleArray(vec![1.0, 2.0]));
+    assert_eq!(format!("{:?}", value), "ValueRef(DoubleArray([1.0, 2.0]))");
+
+    // A reference is a snapshot; later changes don't show through it.
+    entry.set(vec![3.0]).unwrap();
+    assert_eq!(value, Value::DoubleArray(vec![1.0, 2.0]));
+    assert_eq!(ValueRef::owned(Value::Bool(true)).as_bool(), Some(true));
+}
+
+#[test]
+fn borrowed_setters_set_the_same_values() {
+    let inst = Instance::in_memory();
+    inst.get_entry("/flag").set_bool(true).unwrap();
+    inst.get_entry("/speed").set_double(1.5).unwrap();
+    inst.get_entry("/name").set_str("left").unwrap();
+    inst.get_entry("/flags").set_bool_slice(&[true, false]).unwrap();
+    inst.get_entry("/speeds").set_double_slice(&[1.0, 2.0]).unwrap();
+    inst.get_entry("/raw").set_raw(b"\x00\x01").unwrap();
+
+    assert_eq!(inst.get_entry("/flag").value(), Some(Value::Bool(true)));
+    assert_eq!(inst.get_entry("/speed").value(), Some(Value::Double(1.5)));
+    assert_eq!(inst.get_entry("/name").value(), Some(Value::String("left".to_owned())));
+    assert_eq!(inst.get_entry("/flags").value(), Some(Value::BoolArray(vec![true, false])));
+    assert_eq!(inst.get_entry("/speeds").value(), Some(Value::DoubleArray(vec![1.0, 2.0])));
+    assert_eq!(inst.get_entry("/raw").value(), Some(Value::Raw(vec![0, 1])));
+}
+
+#[test]
+fn borrowed_setters_keep_the_type() {
+    let inst = Instance::in_memory();
+    let entry = inst.get_entry("/speed");
+    entry.set_double(1.5).unwrap();
+    assert_eq!(entry.set_str("fast"), Err(Error::TypeMismatch { expected: EntryType::Double, actual: EntryType::String }));
+    assert_eq!(entry.set_borrowed(BorrowedValue::DoubleSlice(&[1.0])),
+               Err(Error::TypeMismatch { expected: EntryType::Double, actual: EntryType::DoubleArray }));
+    assert_eq!(entry.value(), Some(Value::Double(1.5)));
+}
+
+#[test]
+fn borrowed_values_match_owned_ones() {
+    let values = [
+        (BorrowedValue::Bool(true), Value::Bool(true)),
+        (BorrowedValue::Double(1.5), Value::Double(1.5)),
+        (BorrowedValue::Str("a"), Value::String("a".to_owned())),
+        (BorrowedValue::BoolSlice(&[true]), Value::BoolArray(vec![true])),
+        (BorrowedValue::DoubleSlice(&[1.0]), Value::DoubleArray(vec![1.0])),
+        (BorrowedValue::Raw(&[1]), Value::Raw(vec![1])),
+    ];
+    for &(borrowed, ref owned) in &values {
+        assert_eq!(borrowed.entry_type(), owned.entry_type());
+        assert_eq!(borrowed.to_value(), *owned);
+    }
+}
+
+#[test]
+fn borrowed_setters_notify_listeners() {
+    let inst = Instance::in_memory();
+    let heard = Arc::new(Mutex::new(Vec::new()));
+    let listener_heard = heard.clone();
+    let _listener = inst.add_entry_listener("/", NotifyFlags::NEW | NotifyFlags::UPDATE | NotifyFlags::LOCAL, move |change| {
+        listener_heard.lock().unwrap().push((change.value, change.flags));
+    });
+
+    let entry = inst.get_entry("/name");
+    entry.set_str("left").unwrap();
+    entry.set_str("left").unwrap();
+    entry.set_str("right").unwrap();
+    assert_eq!(*heard.lock().unwrap(), vec![
+        (Some(Value::String("left".to_owned())), NotifyFlags::NEW | NotifyFlags::LOCAL),
+        (Some(Value::String("right".to_owned())), NotifyFlags::UPDATE | NotifyFlags::LOCAL),
+    ]);
+}
+
+#[test]
+fn info_is_none_for_missing_entries() {
+    let inst = Instance::in_memory();
+    let entry = inst.get_entry("/missing");
+    assert_eq!(entry.info(), None);
+
+    entry.set(1.0).unwrap();
+    entry.delete();
+    assert_eq!(entry.info(), None);
+}
+
+#[test]
+fn info_matches_the_single_getters() {
+    let inst = Instance::in_memory();
+    let entry = inst.get_entry("/kP");
+    entry.set(0.5).unwrap();
+    entry.set_persistent();
+
+    let info = entry.info().unwrap();
+    assert_eq!(info.entry, entry);
+    assert_eq!(info.name, "/kP");
+    assert_eq!(info.entry_type, EntryType::Double);
+    assert_eq!(info.flags, EntryFlags::PERSISTENT);
+    assert_eq!(info.last_change, entry.last_changed());
+}
+
+#[test]
+fn info_tracks_the_last_change() {
+    let inst = Instance::in_memory();
+    let entry = inst.get_entry("/speed");
+    entry.set(1.0).unwrap();
+    let first = entry.info().unwrap().last_change;
+    thread::sleep(Duration::from_millis(2));
+    entry.set(2.0).unwrap();
+    assert!(entry.info().unwrap().last_change > first);
+}
+
+#[test]
+fn entry_info_is_filtered_by_prefix_and_type() {
+    let inst = Instance::in_memory();
+    inst.get_entry("/drive/speed").set(1.0).unwrap();
+    inst.get_entry("/drive/enabled").set(true).unwrap();
+    inst.get_entry("/arm/angle").set(2.0).unwrap();
+    inst.get_entry("/drive/missing");
+
+    let mut names: Vec<String> = inst.get_entry_info("/drive/", EntryMask::all()).into_iter().map(|info| info.name).collect();
+    names.sort();
+    assert_eq!(names, vec!["/drive/enabled", "/drive/speed"]);
+
+    let doubles = inst.get_entry_info("/", EntryMask::new(EntryType::Double));
+    let mut names: Vec<&str> = doubles.iter().map(|info| &info.name[..]).collect();
+    names.sort();
+    assert_eq!(names, vec!["/arm/angle", "/drive/speed"]);
+    assert!(doubles.iter().all(|info| info.entry_type == EntryType::Double && info.entry.value().is_some()));
+}
@@ -0,0 +1,61 @@
+//! Saving and loading persistent files through ntcore.
+
+#![cfg(feature = "ntcore-sys")]
+
+extern crate ntcore;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use ntcore::entry::Value;
+use ntcore::Instance;
+
+fn temp_file(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("ntcore-{}-{}.ini", name, std::process::id()))
+}
+
+#[test]
+fn saved_entries_load_back() {
+    let path = temp_file("saved");
+    let path = path.to_str().unwrap();
+
+    let source = Instance::start_client_none();
+    let entry = source.get_entry("/Tuning/kP");
+    entry.set(0.25).unwrap();
+    entry.set_persistent();
+    source.save_persistent(path).unwrap();
+
+    let target = Instance::start_client_none();
+    let warnings = target.load_persistent(path, |warning| panic!("unexpected warning {:?}", warning)).unwrap();
+    assert!(warnings.is_empty());
+    assert_eq!(target.get_entry("/Tuning/kP").value(), Some(Value::Double(0.25)));
+    let _ = fs::remove_file(path);
+}
+
+#[test]
+fn bad_lines_are_reported_as_they_are_found() {
+    let path = temp_file("warnings");
+    fs::write(&path, "[NetworkTables Storage 3.0]\ndouble \"/a\"=1.5\nnonsense\nboolean \"/b\"=true\n").unwrap();
+
+    let inst = Instance::start_client_none();
+    let mut seen = Vec::new();
+    let warnings = inst.load_persistent(path.to_str().unwrap(), |warning| seen.push(warning.clone())).unwrap();
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].line, 3);
+    assert_eq!(seen, warnings);
+    assert_eq!(inst.get_entry("/a").value(), Some(Value::Double(1.5)));
+    assert_eq!(inst.get_entry("/b").value(), Some(Value::Bool(true)));
+    let _ = fs::remove_file(path);
+}
+
+#[test]
+fn prefixes_limit_what_is_loaded() {
+    let path = temp_file("prefix");
+    fs::write(&path, "[NetworkTables Storage 3.0]\ndouble \"/Arm/kP\"=1\ndouble \"/Drive/kP\"=2\n").unwrap();
+
+    let inst = Instance::start_client_none();
+    inst.load_entries(path.to_str().unwrap(), "/Arm/", |_| {}).unwrap();
+    assert_eq!(inst.get_entry("/Arm/kP").value(), Some(Value::Double(1.0)));
+    assert_eq!(inst.get_entry("/Drive/kP").value(), None);
+    let _ = fs::remove_file(path);
+}
@@ -0,0 +1,143 @@
+//! Remote procedure calls between ntcore instances over localhost.
+
+#![cfg(feature = "ntcore-sys")]
+
+extern crate ntcore;
+
+use std::thread;
+use std::time::Duration;
+use ntcore::entry::{EntryType, Value};
+use ntcore::rpc::{RpcDefinition, RpcError, RpcResultPoller};
+use ntcore::testing::{wait_until, Loopback};
+use ntcore::{Error, Instance};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn echo_definition() -> Vec<u8> {
+    RpcDefinition::new("echo").pack().unwrap()
+}
+
+#[test]
+fn results_come_back_to_the_caller() {
+    let net = Loopback::start(1, TIMEOUT).unwrap();
+    net.server().get_entry("/rpc/echo").create_rpc(&echo_definition(), |answer| answer.params().to_vec()).unwrap();
+
+    let entry = net.clients()[0].get_entry("/rpc/echo");
+    assert!(wait_until(TIMEOUT, || entry.exists()));
+    assert_eq!(entry.call_rpc(b"hello").unwrap().wait(), Ok(b"hello".to_vec()));
+}
+
+#[test]
+fn wait_timeout_tells_timeouts_from_results() {
+    let net = Loopback::start(1, TIMEOUT).unwrap();
+    net.server().get_entry("/rpc/slow").create_rpc(&echo_definition(), |answer| {
+        thread::sleep(Duration::from_millis(200));
+        answer.params().to_vec()
+    }).unwrap();
+
+    let entry = net.clients()[0].get_entry("/rpc/slow");
+    assert!(wait_until(TIMEOUT, || entry.exists()));
+    let mut call = entry.call_rpc(b"later").unwrap();
+    assert_eq!(call.wait_timeout(Duration::from_millis(10)), Ok(None));
+    assert_eq!(call.wait_timeout(TIMEOUT), Ok(Some(b"later".to_vec())));
+}
+
+#[test]
+fn poller_collects_results_of_many_calls() {
+    let net = Loopback::start(1, TIMEOUT).unwrap();
+    net.server().get_entry("/rpc/echo").create_rpc(&echo_definition(), |answer| answer.params().to_vec()).unwrap();
+
+    let entry = net.clients()[0].get_entry("/rpc/echo");
+    assert!(wait_until(TIMEOUT, || entry.exists()));
+    let poller = RpcResultPoller::new();
+    let ids = (0..3u8).map(|i| poller.add(entry.call_rpc(&[i]).unwrap())).collect::<Vec<_>>();
+
+    let mut results = Vec::new();
+    while results.len() < ids.len() {
+        results.extend(poller.poll_timeout(TIMEOUT).expect("timed out waiting for results"));
+    }
+    for (i, id) in ids.iter().enumerate() {
+        let result = results.iter().find(|result| result.call == *id).unwrap();
+        assert_eq!(result.result, Ok(vec![i as u8]));
+    }
+    assert_eq!(poller.pending(), 0);
+}
+
+#[test]
+fn dropped_answers_send_an_empty_result() {
+    let net = Loopback::start(1, TIMEOUT).unwrap();
+    let poller = net.server().rpc_call_poller().unwrap();
+    net.server().get_entry("/rpc/ignored").create_polled_rpc(&echo_definition(), &poller).unwrap();
+
+    let entry = net.clients()[0].get_entry("/rpc/ignored");
+    assert!(wait_until(TIMEOUT, || entry.exists()));
+    let call = entry.call_rpc(b"hello").unwrap();
+    let answers = poller.poll_timeout(TIMEOUT).expect("timed out waiting for the call");
+    assert_eq!(answers.len(), 1);
+    drop(answers);
+    assert_eq!(call.wait(), Ok(Vec::new()));
+}
+
+#[test]
+fn responded_answers_keep_their_result() {
+    let net = Loopback::start(1, TIMEOUT).unwrap();
+    let poller = net.server().rpc_call_poller().unwrap();
+    net.server().get_entry("/rpc/echo").create_polled_rpc(&echo_definition(), &poller).unwrap();
+
+    let entry = net.clients()[0].get_entry("/rpc/echo");
+    assert!(wait_until(TIMEOUT, || entry.exists()));
+    let call = entry.call_rpc(b"hello").unwrap();
+    for answer in poller.poll_timeout(TIMEOUT).expect("timed out waiting for the call") {
+        let params = answer.params().to_vec();
+        answer.respond(&params);
+    }
+    assert_eq!(call.wait(), Ok(b"hello".to_vec()));
+}
+
+#[test]
+fn cancelled_polls_return_nothing() {
+    let poller = RpcResultPoller::new();
+    assert_eq!(poller.poll_timeout(Duration::from_millis(10)), None);
+    poller.cancel();
+    assert_eq!(poller.poll(), Vec::new());
+}
+
+#[test]
+fn typed_calls_report_handler_errors() {
+    let def = RpcDefinition::new("scale")
+        .param("value", 1.0)
+        .result("scaled", EntryType::Double);
+    let net = Loopback::start(1, TIMEOUT).unwrap();
+    net.server().get_entry("/rpc/scale").create_typed_rpc(def.clone(), |args| {
+        match args.get("value") {
+            Some(&Value::Double(value)) if value >= 0.0 => Ok(vec![Value::Double(value * 2.0)]),
+            // The wrong type on purpose, to check that results are checked too.
+            _ => Ok(vec![Value::Bool(false)]),
+        }
+    }).unwrap();
+
+    let entry = net.clients()[0].get_entry("/rpc/scale");
+    assert!(wait_until(TIMEOUT, || entry.exists()));
+    let result = entry.call_typed_rpc(&def, &[Value::Double(2.0)]).unwrap().wait().unwrap();
+    assert_eq!(def.unpack_results(&result), Ok(vec![Value::Double(4.0)]));
+
+    let result = entry.call_typed_rpc(&def, &[Value::Double(-1.0)]).unwrap().wait().unwrap();
+    assert!(matches!(def.unpack_results(&result), Err(RpcError::Remote(_))));
+
+    // Sent raw, so nothing checks the parameters before the handler does.
+    let result = entry.call_rpc(b"\x01").unwrap().wait().unwrap();
+    assert!(matches!(def.unpack_results(&result), Err(RpcError::Remote(_))));
+}
+
+#[test]
+fn other_backends_cannot_make_calls() {
+    let inst = Instance::in_memory();
+    let entry = inst.get_entry("/rpc/echo");
+    assert_eq!(entry.create_rpc(&echo_definition(), |answer| answer.params().to_vec()), Err(Error::Unsupported));
+    assert_eq!(entry.call_rpc(b"hello").err(), Some(Error::Unsupported));
+    assert_eq!(inst.rpc_call_poller().err(), Some(Error::Unsupported));
+
+    let def = RpcDefinition::new("echo");
+    assert_eq!(entry.create_typed_rpc(def.clone(), |_| Ok(Vec::new())), Err(RpcError::Unsupported));
+    assert_eq!(entry.call_typed_rpc(&def, &[]).err(), Some(RpcError::Unsupported));
+}
@@ -0,0 +1,10 @@
+#[macro_use]
+extern crate ntcore;
+
+#[derive(NtTable)]
+enum Mode {
+    Fast,
+    Slow,
+}
+
+fn main() {}
@@ -0,0 +1,15 @@
+#[macro_use]
+extern crate ntcore;
+
+#[derive(NtTable)]
+struct Arm {
+    angle: f64,
+}
+
+#[derive(NtTable)]
+struct Robot {
+    #[nt(subtable, persistent)]
+    arm: Arm,
+}
+
+fn main() {}
@@ -0,0 +1,7 @@
+#[macro_use]
+extern crate ntcore;
+
+#[derive(NtTable)]
+struct Pair(f64, f64);
+
+fn main() {}
@@ -0,0 +1,10 @@
+#[macro_use]
+extern crate ntcore;
+
+#[derive(NtTable)]
+struct Shooter {
+    #[nt(hidden)]
+    speed: f64,
+}
+
+fn main() {}